@@ -0,0 +1,249 @@
+use crate::types::TerminalColor;
+use crate::utils::sgr_foreground;
+
+/// Something an escape sequence in program output asks of the terminal, other than printing text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Control {
+    /// OSC 0 / OSC 2: set the window title.
+    SetTitle(String),
+    /// CSI 2J / 3J: erase the whole display.
+    EraseDisplay,
+    /// CSI row;col H: move to a row (0-based, relative to the top of the display).
+    CursorTo { row: usize },
+    /// CSI K: erase in the current line; 0 = to the end, 1 = to the start, 2 = all of it.
+    EraseLine(u8),
+    /// BEL outside of an OSC string.
+    Bell,
+    /// SGR (`CSI ... m`) changing the foreground: a color, or `None` for the default.
+    Foreground(Option<TerminalColor>),
+}
+
+/// A run of printable output or a control, in the order the program wrote them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    Text(Vec<u8>),
+    Control(Control),
+}
+
+// OSC strings longer than this are dropped rather than buffered forever
+const MAX_OSC_LEN: usize = 4096;
+// No CSI sequence we act on has parameters anywhere near this long
+const MAX_CSI_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    // Saw ESC inside an OSC string, which is the first half of the ST terminator
+    OscEscape,
+}
+
+/// Splits escape sequences out of a program's output stream. Sequences may be split across
+/// reads, so the parser keeps its state between calls to [`AnsiParser::feed`]. Sequences it
+/// doesn't act on are dropped instead of being printed. Colors are only reported when the
+/// parser is made with `color_enabled`; the default parser drops them with everything else.
+#[derive(Default)]
+pub struct AnsiParser {
+    color_enabled: bool,
+    state: State,
+    csi: Vec<u8>,
+    osc: Vec<u8>,
+    osc_overflow: bool,
+}
+
+impl AnsiParser {
+    pub fn new(color_enabled: bool) -> Self {
+        Self { color_enabled, ..Self::default() }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Output> {
+        let mut out = Vec::new();
+        let mut text = Vec::new();
+        for &b in bytes {
+            match self.state {
+                State::Ground if b == 0x1b => self.state = State::Escape,
+                State::Ground if b == 0x07 => push_control(Control::Bell, &mut text, &mut out),
+                State::Ground => text.push(b),
+                State::Escape => self.state = self.after_escape(b),
+                // Parameters and intermediates run until a final byte in 0x40..=0x7E
+                State::Csi if (0x40..=0x7e).contains(&b) => {
+                    self.state = State::Ground;
+                    if let Some(control) = self.csi_control(b) {
+                        push_control(control, &mut text, &mut out);
+                    }
+                }
+                State::Csi => {
+                    if self.csi.len() < MAX_CSI_LEN {
+                        self.csi.push(b);
+                    }
+                }
+                State::Osc if b == 0x07 => self.finish_osc(&mut text, &mut out),
+                State::Osc if b == 0x1b => self.state = State::OscEscape,
+                State::Osc => {
+                    if self.osc.len() < MAX_OSC_LEN {
+                        self.osc.push(b);
+                    } else {
+                        self.osc_overflow = true;
+                    }
+                }
+                State::OscEscape if b == b'\\' => self.finish_osc(&mut text, &mut out),
+                // Not ST after all: the ESC starts a new sequence and the OSC is abandoned
+                State::OscEscape => self.state = self.after_escape(b),
+            }
+        }
+        if !text.is_empty() {
+            out.push(Output::Text(text));
+        }
+        out
+    }
+
+    // The state after ESC followed by `b`
+    fn after_escape(&mut self, b: u8) -> State {
+        match b {
+            b'[' => {
+                self.csi.clear();
+                State::Csi
+            }
+            b']' => {
+                self.osc.clear();
+                self.osc_overflow = false;
+                State::Osc
+            }
+            // Two-byte sequences such as ESC 7 / ESC = carry nothing we use
+            _ => State::Ground,
+        }
+    }
+
+    fn finish_osc(&mut self, text: &mut Vec<u8>, out: &mut Vec<Output>) {
+        self.state = State::Ground;
+        if self.osc_overflow {
+            return;
+        }
+        let osc = String::from_utf8_lossy(&self.osc);
+        let Some((code, arg)) = osc.split_once(';') else {
+            return;
+        };
+        let control = match code {
+            "0" | "2" => Control::SetTitle(arg.to_string()),
+            _ => return,
+        };
+        push_control(control, text, out);
+    }
+
+    // The control for a CSI sequence ending in `final_byte`, if it is one we act on.
+    // Private sequences (`CSI ? ...`) and anything else are dropped.
+    fn csi_control(&self, final_byte: u8) -> Option<Control> {
+        let params = std::str::from_utf8(&self.csi).ok()?;
+        if !params.bytes().all(|b| b.is_ascii_digit() || b == b';') {
+            return None;
+        }
+        // Missing or empty parameters take their default
+        let param = |i: usize, default: usize| params.split(';').nth(i).and_then(|p| p.parse().ok()).unwrap_or(default);
+        match final_byte {
+            b'J' if matches!(param(0, 0), 2 | 3) => Some(Control::EraseDisplay),
+            b'H' | b'f' => Some(Control::CursorTo { row: param(0, 1).saturating_sub(1) }),
+            b'K' => Some(Control::EraseLine(param(0, 0).min(2) as u8)),
+            b'm' if self.color_enabled => sgr_foreground(params).map(Control::Foreground),
+            _ => None,
+        }
+    }
+}
+
+// Ends the current text run so `control` lands after it
+fn push_control(control: Control, text: &mut Vec<u8>, out: &mut Vec<Output>) {
+    if !text.is_empty() {
+        out.push(Output::Text(std::mem::take(text)));
+    }
+    out.push(Output::Control(control));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Output {
+        Output::Text(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_osc_title_is_split_out() {
+        let mut parser = AnsiParser::default();
+        let out = parser.feed(b"before\x1b]0;my title\x07after\x1b]2;other\x1b\\");
+        assert_eq!(
+            out,
+            vec![
+                text("before"),
+                Output::Control(Control::SetTitle("my title".to_string())),
+                text("after"),
+                Output::Control(Control::SetTitle("other".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sequences_split_across_reads() {
+        let mut parser = AnsiParser::default();
+        assert_eq!(parser.feed(b"a\x1b]0;ti"), vec![text("a")]);
+        assert_eq!(parser.feed(b"tle\x07b"), vec![Output::Control(Control::SetTitle("title".to_string())), text("b")]);
+        assert_eq!(parser.feed(b"\x1b[1;3"), vec![]);
+        assert_eq!(parser.feed(b"1mred\x1b[0m"), vec![text("red")]);
+    }
+
+    #[test]
+    fn test_unused_sequences_are_dropped() {
+        let mut parser = AnsiParser::default();
+        assert_eq!(parser.feed(b"\x1b]1;icon\x07\x1b7x\x1b]0;cut\x1b[0my"), vec![text("xy")]);
+    }
+
+    #[test]
+    fn test_bell_is_split_out_but_still_ends_osc() {
+        let mut parser = AnsiParser::default();
+        assert_eq!(
+            parser.feed(b"done\x07\x1b]0;t\x07x"),
+            vec![
+                text("done"),
+                Output::Control(Control::Bell),
+                Output::Control(Control::SetTitle("t".to_string())),
+                text("x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_colors_only_when_enabled() {
+        let input = b"\x1b[1;31mred\x1b[1mbold\x1b[0m \x1b[38;5;208mx\x1b[m";
+        assert_eq!(AnsiParser::new(false).feed(input), vec![text("redbold x")]);
+        assert_eq!(
+            AnsiParser::new(true).feed(input),
+            vec![
+                Output::Control(Control::Foreground(Some(crate::utils::ansi_color(1)))),
+                text("redbold"),
+                Output::Control(Control::Foreground(None)),
+                text(" "),
+                Output::Control(Control::Foreground(Some(crate::utils::ansi_color(208)))),
+                text("x"),
+                Output::Control(Control::Foreground(None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_erase_and_cursor_sequences() {
+        let mut parser = AnsiParser::default();
+        assert_eq!(
+            parser.feed(b"\x1b[H\x1b[2Jtop\x1b[5;1Hx\x1b[K\x1b[2K\x1b[0J\x1b[?25l"),
+            vec![
+                Output::Control(Control::CursorTo { row: 0 }),
+                Output::Control(Control::EraseDisplay),
+                text("top"),
+                Output::Control(Control::CursorTo { row: 4 }),
+                text("x"),
+                Output::Control(Control::EraseLine(0)),
+                Output::Control(Control::EraseLine(2)),
+            ]
+        );
+    }
+}
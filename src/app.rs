@@ -1,49 +1,197 @@
-use crate::shell::spawn_shell_thread;
-use crate::types::{Action, InputEvent, KeyBinding, ModeDefinition, ShellState, TerminalMode, Screen, ShellEvent, TerminalColor, ScreenOperation};
+use crate::pane::Pane;
+use crate::session::Session;
+use crate::shell::resolve_initial_cwd;
+use crate::types::{DEFAULT_CONFIG_RELOAD_DEBOUNCE, DEFAULT_COPY_ON_SELECT, DEFAULT_MAX_INPUT_LENGTH, DEFAULT_WORD_CHARS, DEFAULT_KEY_REPEAT_DELAY, DEFAULT_KEY_REPEAT_RATE, DEFAULT_WINDOW_TITLE_FORMAT, MIN_FONT_SIZE, Action, Line, InputEvent, KeyBinding, ModeAppearance, ModeDefinition, Padding, ShellState, StartupCommands, TerminalMode, Screen, TerminalColor};
 use crate::backend::ProcessBackend;
 use crate::fixed_config::FixedConfig;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use eframe::egui;
-use std::env;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use crate::utils::get_default_config_path;
+use crate::utils::{fuzzy_score, get_default_config_path, CliArgs};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use std::time::{Duration, Instant};
 
-use crate::renderer::TerminalRenderer;
+
+/// Fallback redraw interval while idle; real updates request a repaint as they arrive.
+const IDLE_REPAINT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reloads the config on `kill -HUP <pid>`, for editors that don't trigger watcher events
+/// and for scripted reloads.
+#[cfg(unix)]
+fn spawn_sighup_reloader(config_tx: Sender<()>, ctx: egui::Context) {
+    use signal_hook::consts::SIGHUP;
+    use signal_hook::iterator::Signals;
+
+    let mut signals = match Signals::new([SIGHUP]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            log::warn!("Failed to install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            // Reloads every pane, the same way a config file change does
+            if config_tx.send(()).is_err() {
+                break;
+            }
+            ctx.request_repaint();
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_sighup_reloader(_config_tx: Sender<()>, _ctx: egui::Context) {}
+
+// Runs each line piped to stdin (`echo ls | axiomterm`) as a command in the first pane, after
+// its startup commands. Nothing is read when stdin is a terminal.
+fn spawn_stdin_reader(action_tx: Sender<Action>, ctx: egui::Context) {
+    use std::io::{BufRead, IsTerminal};
+
+    if std::io::stdin().is_terminal() {
+        return;
+    }
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if action_tx.send(Action::RunCommand(line)).is_err() {
+                break;
+            }
+            ctx.request_repaint();
+        }
+    });
+}
+
+/// Holds off reloading the config until its file has gone quiet. Editors often write a file
+/// several times per save, so each change pushes the reload back by the debounce, and the one
+/// reload reads what the last write left.
+#[derive(Default)]
+struct ReloadDebounce {
+    due: Option<Instant>,
+}
+
+impl ReloadDebounce {
+    fn changed(&mut self, now: Instant, debounce: Duration) {
+        self.due = Some(now + debounce);
+    }
+
+    // True once, when the file has been quiet for the debounce since its last change
+    fn take_due(&mut self, now: Instant) -> bool {
+        let due = self.due.is_some_and(|due| due <= now);
+        if due {
+            self.due = None;
+        }
+        due
+    }
+
+    // How long until a pending reload is due
+    fn wait(&self, now: Instant) -> Option<Duration> {
+        self.due.map(|due| due.saturating_duration_since(now))
+    }
+}
+
+// What a new pane runs first: loading the config, to pick up the same settings as the pane it
+// was opened from. It loads the way a save reloads it, so the pane stays in the directory it
+// was opened in rather than moving to `default_cwd`.
+fn new_pane_actions(config_path: Option<&std::path::Path>) -> Vec<Action> {
+    match config_path {
+        Some(path) if path.exists() => vec![Action::ReloadConfig { auto: true }],
+        _ => Vec::new(),
+    }
+}
+
+// The config's `on_startup` commands, run once per launch in the first pane. Each is its own
+// RunCommand, so one that fails doesn't stop the rest.
+fn startup_actions(startup: Option<StartupCommands>, state: &mut ShellState) -> Vec<Action> {
+    let Some(startup) = startup else {
+        return Vec::new();
+    };
+    match startup.commands() {
+        Ok(commands) => commands.into_iter().map(Action::RunCommand).collect(),
+        Err(e) => {
+            let source = match &startup {
+                StartupCommands::File(path) => path.display().to_string(),
+                StartupCommands::List(_) => "on_startup".to_string(),
+            };
+            let error_color = state.error_color;
+            state.screen.push_line(Line::from_string(&format!("Failed to read startup commands from {}: {}", source, e), error_color));
+            Vec::new()
+        }
+    }
+}
+
+// What picking a command palette entry does
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PaletteAction {
+    // Type the command and submit it
+    Run(String),
+    // Type the start of a command that needs arguments, for the user to finish
+    Insert(String),
+    Macro(String),
+}
+
+struct PaletteEntry {
+    label: String,
+    detail: String,
+    action: PaletteAction,
+}
+
+// The Ctrl+P overlay: a query and the highlighted row among the entries it matches
+#[derive(Default)]
+struct CommandPalette {
+    query: String,
+    selected: usize,
+}
 
 pub struct TerminalApp {
-    pub shell_state: Arc<Mutex<ShellState>>,
-    pub action_tx: Sender<Action>,
-    pub output_rx: Receiver<ShellEvent>,
+    // Tabs, in tab bar order; there is always at least one
+    pub sessions: Vec<Session>,
+    pub active: usize,
+    // New panes get their own backend built from this, so each keeps its own cwd
+    pub fixed_config: FixedConfig,
     pub _watcher: Option<RecommendedWatcher>,
     pub config_rx: Receiver<()>,
-    pub last_reload: Instant,
-    pub renderer: TerminalRenderer,
+    reload_debounce: ReloadDebounce,
     pub lua_engine: crate::lua_bridge::LuaEngine,
+    pub input_mapper: crate::input::InputMapper,
+    // Background image path and its texture; `None` texture records a failed load so it isn't retried every frame
+    pub background_texture: Option<(String, Option<egui::TextureHandle>)>,
+    // Open while the command palette is shown; keys go to it instead of the bindings
+    command_palette: Option<CommandPalette>,
+    // Last focus state handed to the panes, so it is only sent when it changes
+    window_focused: bool,
+    // `NO_COLOR` or `--no-color`, which every new pane starts with too
+    color_forced_off: bool,
 }
 
 impl TerminalApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>, backend: Box<dyn ProcessBackend>, fixed_config: &FixedConfig) -> Self {
-        let (action_tx, action_rx) = unbounded::<Action>();
-        let (output_tx, output_rx) = unbounded::<ShellEvent>();
+    pub fn new(cc: &eframe::CreationContext<'_>, backend: Box<dyn ProcessBackend>, fixed_config: &FixedConfig, cli: &CliArgs) -> Self {
         let (config_tx, config_rx) = unbounded::<()>();
+        let ctx = cc.egui_ctx.clone();
+        // Ctrl+= / Ctrl+- / Ctrl+0 resize the terminal font instead of zooming the whole UI
+        ctx.options_mut(|o| o.zoom_with_keyboard = false);
 
-        let current_dir = env::current_dir()
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_else(|_| ".".to_string());
+        // Only the settings that matter at launch are read here; the rest wait for `config load`.
+        // A config that doesn't parse is reported when it's loaded, not here.
+        let startup_config = get_default_config_path()
+            .and_then(|path| crate::config::parse_config(&path).ok())
+            .map(|(update, _)| update)
+            .unwrap_or_default();
+        let (current_dir, cwd_source, cwd_errors) =
+            resolve_initial_cwd(backend.as_ref(), cli.cwd.as_deref(), startup_config.default_cwd.as_deref());
 
         // Set up config watcher
         let mut watcher: Option<RecommendedWatcher> = None;
         if let Some(config_path) = get_default_config_path() {
             if let Some(config_dir) = config_path.parent() {
                  let tx = config_tx.clone();
+                 let watcher_ctx = ctx.clone();
                  if let Ok(mut w) = notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
                      match res {
                          Ok(event) => {
                              if let notify::EventKind::Modify(_) = event.kind {
                                  let _ = tx.send(());
+                                 watcher_ctx.request_repaint();
                              }
                          },
                          Err(_) => {},
@@ -56,69 +204,201 @@ impl TerminalApp {
             }
         }
 
-        // Determine initial mode from FixedConfig
-        let initial_mode = match fixed_config.core.initial_mode.as_str() {
-            "insert" => TerminalMode::Insert,
-            "normal" => TerminalMode::Normal,
-            "visual" => TerminalMode::Visual,
-            _ => TerminalMode::Insert, // Fallback
-        };
+        spawn_sighup_reloader(config_tx.clone(), ctx.clone());
 
-        let state = Arc::new(Mutex::new(ShellState {
-            prompt: "> ".to_string(),
-            prompt_color: TerminalColor::GREEN,
-            text_color: TerminalColor::LIGHT_GRAY,
-            window_title_base: "axiomterm".to_string(),
-            window_title_full: format!("[{}] {}", initial_mode.name(), "axiomterm"),
-            title_updated: false,
-            mode: initial_mode,
-            shortcuts: Vec::new(),
-            opacity: 1.0,
-            font_size: 14.0,
-            current_dir: current_dir.clone(),
-            directory_color: TerminalColor::BLUE,
-            screen: Screen::new(),
-            input_buffer: String::new(),
-            mode_definitions: vec![
-                ModeDefinition {
-                    mode: TerminalMode::Insert,
-                    bindings: vec![
-                        KeyBinding { event: InputEvent::Key { code: "Enter".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Submit) },
-                        KeyBinding { event: InputEvent::Key { code: "Backspace".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Backspace) },
-                        KeyBinding { event: InputEvent::Key { code: "Escape".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ChangeMode(TerminalMode::Normal)) },
-                    ],
-                },
-                ModeDefinition {
-                    mode: TerminalMode::Normal,
-                    bindings: vec![
-                        KeyBinding { event: InputEvent::Key { code: "I".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ChangeMode(TerminalMode::Insert)) },
-                        KeyBinding { event: InputEvent::Key { code: "Escape".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Clear) },
-                    ],
-                },
-            ],
-        }));
+        let lua_engine = crate::lua_bridge::LuaEngine::new();
+        if let Some(path) = get_default_config_path() {
+            let _ = lua_engine.load_config(&path);
+        }
 
-        spawn_shell_thread(action_rx, output_tx, Arc::clone(&state), backend);
+        let mut state = initial_shell_state(fixed_config, &lua_engine, current_dir);
+        state.cwd_source = cwd_source;
+        if cli.colors_disabled() {
+            state.force_no_color();
+        }
+        state.refresh_window_title();
+        let error_color = state.error_color;
+        for error in &cwd_errors {
+            state.screen.push_line(Line::from_string(error, error_color));
+        }
+        let startup = startup_actions(startup_config.on_startup, &mut state);
+        let pane = Pane::spawn(&ctx, state, backend, startup);
+        spawn_stdin_reader(pane.action_tx.clone(), ctx.clone());
 
         Self {
-            shell_state: state,
-            action_tx,
-            output_rx,
+            sessions: vec![Session::new(pane)],
+            active: 0,
+            fixed_config: fixed_config.clone(),
             _watcher: watcher,
             config_rx,
-            last_reload: Instant::now(),
-            renderer: TerminalRenderer::new(),
-            lua_engine: {
-                let engine = crate::lua_bridge::LuaEngine::new();
-                if let Some(path) = get_default_config_path() {
-                     let _ = engine.load_config(&path);
-                }
-                engine
-            },
+            reload_debounce: ReloadDebounce::default(),
+            lua_engine,
+            input_mapper: crate::input::InputMapper::new(),
+            background_texture: None,
+            command_palette: None,
+            window_focused: true,
+            color_forced_off: cli.colors_disabled(),
         }
     }
+}
 
-    // map_input has been moved into the input module
+// A fresh shell with the built-in defaults, before any config is loaded into it
+pub fn initial_shell_state(fixed_config: &FixedConfig, lua_engine: &crate::lua_bridge::LuaEngine, current_dir: String) -> ShellState {
+    // Determine initial mode from FixedConfig
+    let initial_mode = match fixed_config.core.initial_mode.as_str() {
+        "insert" => TerminalMode::Insert,
+        "normal" => TerminalMode::Normal,
+        "visual" => TerminalMode::Visual,
+        _ => TerminalMode::Insert, // Fallback
+    };
+
+    let mut macro_names = lua_engine.list_macros();
+    macro_names.sort();
+
+    let mut state = ShellState {
+        prompt: "> ".to_string(),
+        prompt_color: TerminalColor::GREEN,
+        text_color: TerminalColor::LIGHT_GRAY,
+        window_title_base: "axiomterm".to_string(),
+        window_title_full: String::new(),
+        window_title_format: DEFAULT_WINDOW_TITLE_FORMAT.to_string(),
+        title_updated: false,
+        mode: initial_mode,
+        shortcuts: Vec::new(),
+        opacity: 1.0,
+        font_size: 14.0,
+        configured_font_size: 14.0,
+        min_font_size: MIN_FONT_SIZE,
+        high_contrast: false,
+        color_enabled: true,
+        color_forced_off: false,
+        clear_on_command: false,
+        key_repeat_delay: DEFAULT_KEY_REPEAT_DELAY,
+        key_repeat_rate: DEFAULT_KEY_REPEAT_RATE,
+        current_dir,
+        cwd_source: Default::default(),
+        directory_color: TerminalColor::BLUE,
+        symlink_color: TerminalColor::CYAN,
+        hidden_file_dim: 1.0,
+        ls_colors: Default::default(),
+        scrollbar: Default::default(),
+        scrollbar_width: None,
+        scrollbar_color: None,
+        stderr_color: TerminalColor::RED,
+        error_color: TerminalColor::RED,
+        cursor_color: TerminalColor::WHITE,
+        background_color: TerminalColor::BLACK,
+        background_image: None,
+        background_image_opacity: 1.0,
+        padding: Padding::default(),
+        current_line_highlight: None,
+        clickable_links: true,
+        copy_on_select: DEFAULT_COPY_ON_SELECT,
+        word_chars: DEFAULT_WORD_CHARS.to_string(),
+        max_input_length: DEFAULT_MAX_INPUT_LENGTH,
+        max_command_lines: None,
+        confirm_destructive: false,
+        notify_after_secs: None,
+        window_focused: true,
+        notifications: Default::default(),
+        config_reload_quiet: false,
+        config_reload_debounce: DEFAULT_CONFIG_RELOAD_DEBOUNCE,
+        multiline_paste: Default::default(),
+        pasted_lines: Default::default(),
+        pending_confirmation: None,
+        continuation: None,
+        screen: Screen::new(),
+        input_buffer: String::new(),
+        input_cursor: 0,
+        macro_names,
+        macro_metrics: Arc::clone(&lua_engine.metrics),
+        render_metrics: Default::default(),
+        last_command: String::new(),
+        history: Vec::new(),
+        history_search: None,
+        running: Vec::new(),
+        sleep_until: None,
+        interrupts: 0,
+        last_status: 0,
+        env_overrides: Default::default(),
+        palette: Default::default(),
+        mode_definitions: vec![
+            ModeDefinition {
+                mode: TerminalMode::Insert,
+                bindings: vec![
+                    KeyBinding { event: InputEvent::Key { code: "Enter".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Submit) },
+                    KeyBinding { event: InputEvent::Key { code: "Backspace".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Backspace) },
+                    KeyBinding { event: InputEvent::Key { code: "Delete".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Delete) },
+                    KeyBinding { event: InputEvent::Key { code: "Escape".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ChangeMode(TerminalMode::Normal)) },
+                    KeyBinding { event: InputEvent::Key { code: "U".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ClearLine) },
+                    KeyBinding { event: InputEvent::Key { code: "W".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::DeleteWord) },
+                    KeyBinding { event: InputEvent::Key { code: "A".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::MoveToStart) },
+                    KeyBinding { event: InputEvent::Key { code: "E".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::MoveToEnd) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowLeft".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CursorLeft) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowRight".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CursorRight) },
+                    KeyBinding { event: InputEvent::Key { code: "Home".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::MoveToStart) },
+                    KeyBinding { event: InputEvent::Key { code: "End".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::MoveToEnd) },
+                    KeyBinding { event: InputEvent::Key { code: "R".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ReverseSearch) },
+                    KeyBinding { event: InputEvent::Key { code: "Equals".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::FontSizeUp) },
+                    KeyBinding { event: InputEvent::Key { code: "Minus".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::FontSizeDown) },
+                    KeyBinding { event: InputEvent::Key { code: "Num0".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::FontSizeReset) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowUp".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::OpacityUp) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowDown".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::OpacityDown) },
+                    KeyBinding { event: InputEvent::Key { code: "D".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::SplitPane) },
+                    KeyBinding { event: InputEvent::Key { code: "W".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::ClosePane) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowRight".to_string(), ctrl: false, alt: true, shift: false }, target: crate::types::BindingTarget::Action(Action::FocusNextPane) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowLeft".to_string(), ctrl: false, alt: true, shift: false }, target: crate::types::BindingTarget::Action(Action::FocusPrevPane) },
+                    KeyBinding { event: InputEvent::Key { code: "T".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::NewTab) },
+                    KeyBinding { event: InputEvent::Key { code: "F4".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CloseTab) },
+                    KeyBinding { event: InputEvent::Key { code: "Tab".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::NextTab) },
+                    KeyBinding { event: InputEvent::Key { code: "Tab".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::PrevTab) },
+                    KeyBinding { event: InputEvent::Key { code: "P".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CommandPalette) },
+                    KeyBinding { event: InputEvent::Key { code: "C".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Interrupt) },
+                ],
+                appearance: ModeAppearance::default(),
+            },
+            ModeDefinition {
+                mode: TerminalMode::Normal,
+                bindings: vec![
+                    KeyBinding { event: InputEvent::Key { code: "I".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ChangeMode(TerminalMode::Insert)) },
+                    KeyBinding { event: InputEvent::Key { code: "Escape".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Clear) },
+                    KeyBinding { event: InputEvent::Sequence(vec![InputEvent::Key { code: "G".to_string(), ctrl: false, alt: false, shift: false }; 2]), target: crate::types::BindingTarget::Action(Action::ScrollTop) },
+                    KeyBinding { event: InputEvent::Key { code: "G".to_string(), ctrl: false, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::ScrollBottom) },
+                    KeyBinding { event: InputEvent::Key { code: "U".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ScrollPageUp) },
+                    KeyBinding { event: InputEvent::Key { code: "D".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ScrollPageDown) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowLeft".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CursorLeft) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowRight".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CursorRight) },
+                    KeyBinding { event: InputEvent::Key { code: "Home".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::MoveToStart) },
+                    KeyBinding { event: InputEvent::Key { code: "End".to_string(), ctrl: false, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::MoveToEnd) },
+                    KeyBinding { event: InputEvent::Key { code: "R".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::ReverseSearch) },
+                    KeyBinding { event: InputEvent::Key { code: "Equals".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::FontSizeUp) },
+                    KeyBinding { event: InputEvent::Key { code: "Minus".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::FontSizeDown) },
+                    KeyBinding { event: InputEvent::Key { code: "Num0".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::FontSizeReset) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowUp".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::OpacityUp) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowDown".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::OpacityDown) },
+                    KeyBinding { event: InputEvent::Key { code: "D".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::SplitPane) },
+                    KeyBinding { event: InputEvent::Key { code: "W".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::ClosePane) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowRight".to_string(), ctrl: false, alt: true, shift: false }, target: crate::types::BindingTarget::Action(Action::FocusNextPane) },
+                    KeyBinding { event: InputEvent::Key { code: "ArrowLeft".to_string(), ctrl: false, alt: true, shift: false }, target: crate::types::BindingTarget::Action(Action::FocusPrevPane) },
+                    KeyBinding { event: InputEvent::Key { code: "T".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::NewTab) },
+                    KeyBinding { event: InputEvent::Key { code: "F4".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CloseTab) },
+                    KeyBinding { event: InputEvent::Key { code: "Tab".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::NextTab) },
+                    KeyBinding { event: InputEvent::Key { code: "Tab".to_string(), ctrl: true, alt: false, shift: true }, target: crate::types::BindingTarget::Action(Action::PrevTab) },
+                    KeyBinding { event: InputEvent::Key { code: "P".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::CommandPalette) },
+                    KeyBinding { event: InputEvent::Key { code: "C".to_string(), ctrl: true, alt: false, shift: false }, target: crate::types::BindingTarget::Action(Action::Interrupt) },
+                ],
+                appearance: ModeAppearance::default(),
+            },
+        ],
+    };
+    // Ctrl+1..9 pick a tab in every mode
+    for def in &mut state.mode_definitions {
+        def.bindings.extend((1..=9).map(|n| KeyBinding {
+            event: InputEvent::Key { code: format!("Num{}", n), ctrl: true, alt: false, shift: false },
+            target: crate::types::BindingTarget::Action(Action::SelectTab(n)),
+        }));
+    }
+    state
 }
 
 impl From<TerminalColor> for egui::Color32 {
@@ -127,101 +407,352 @@ impl From<TerminalColor> for egui::Color32 {
     }
 }
 
+impl From<Padding> for egui::Margin {
+    fn from(p: Padding) -> Self {
+        egui::Margin { left: p.left, right: p.right, top: p.top, bottom: p.bottom }
+    }
+}
+
 impl TerminalApp {
-    fn on_structural_change(&mut self, ctx: &egui::Context, _op: &ScreenOperation) {
-        self.renderer.on_structural_change(ctx);
+    fn session(&self) -> &Session {
+        &self.sessions[self.active]
+    }
+
+    fn session_mut(&mut self) -> &mut Session {
+        &mut self.sessions[self.active]
     }
 
-    fn on_visual_change(&mut self, ctx: &egui::Context, op: &ScreenOperation) {
-        self.renderer.on_visual_change(ctx, op);
+    fn focused_pane(&self) -> &Pane {
+        self.session().focused_pane()
     }
 
-    fn on_cursor_change(&mut self, ctx: &egui::Context, _op: &ScreenOperation) {
-        self.renderer.on_cursor_change(ctx);
+    fn all_panes(&self) -> impl Iterator<Item = &Pane> {
+        self.sessions.iter().flat_map(|session| &session.panes)
+    }
+
+    // Pane and tab actions rearrange the window, so they are handled here; the rest go to the focused pane
+    fn dispatch_action(&mut self, ctx: &egui::Context, action: Action) {
+        match action {
+            Action::SplitPane => {
+                if let Some(pane) = self.spawn_pane(ctx) {
+                    self.session_mut().insert_pane(pane);
+                }
+            }
+            Action::ClosePane => {
+                // Closing the last pane of a tab closes the tab
+                if !self.session_mut().close_focused() {
+                    self.close_tab();
+                }
+            }
+            Action::FocusNextPane => self.session_mut().focus_next(),
+            Action::FocusPrevPane => self.session_mut().focus_prev(),
+            Action::NewTab => {
+                if let Some(pane) = self.spawn_pane(ctx) {
+                    self.sessions.insert(self.active + 1, Session::new(pane));
+                    self.select_tab(self.active + 1);
+                }
+            }
+            Action::CloseTab => self.close_tab(),
+            Action::NextTab => self.select_tab((self.active + 1) % self.sessions.len()),
+            Action::PrevTab => self.select_tab((self.active + self.sessions.len() - 1) % self.sessions.len()),
+            // Ctrl+1..9 count from one; past the last tab does nothing
+            Action::SelectTab(n) => {
+                if (1..=self.sessions.len()).contains(&n) {
+                    self.select_tab(n - 1);
+                }
+            }
+            Action::CommandPalette => self.command_palette = Some(CommandPalette::default()),
+            action => self.session_mut().focused_pane_mut().dispatch_action(ctx, action),
+        }
+    }
+
+    fn run_macro(&mut self, ctx: &egui::Context, name: &str) {
+        match self.lua_engine.resolve_macro(name) {
+            Ok(actions) => {
+                log::debug!("Macro '{}' resolved to {} actions", name, actions.len());
+                for action in actions {
+                    self.dispatch_action(ctx, action);
+                }
+            },
+            Err(e) => {
+                log::error!("{}", e);
+                log::debug!("Macro error details: {:?}", e);
+            }
+        }
+    }
+
+    // Builtins and macros, best match for `query` first
+    fn palette_entries(&self, query: &str) -> Vec<PaletteEntry> {
+        let builtins = crate::shell::BUILTINS.iter().map(|(name, usage, description)| PaletteEntry {
+            label: name.to_string(),
+            detail: format!("{} - {}", usage, description),
+            action: if usage == name {
+                PaletteAction::Run(name.to_string())
+            } else {
+                PaletteAction::Insert(format!("{} ", name))
+            },
+        });
+        let macros = self.lua_engine.list_macros().into_iter().map(|name| PaletteEntry {
+            label: name.clone(),
+            detail: "macro".to_string(),
+            action: PaletteAction::Macro(name),
+        });
+        let mut scored: Vec<(i32, PaletteEntry)> = builtins
+            .chain(macros)
+            .filter_map(|entry| fuzzy_score(query, &entry.label).map(|score| (score, entry)))
+            .collect();
+        scored.sort_by(|(a, x), (b, y)| b.cmp(a).then_with(|| x.label.cmp(&y.label)));
+        scored.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    // Puts a palette entry to work in the focused pane. Typed commands replace the input line.
+    fn run_palette_action(&mut self, ctx: &egui::Context, action: PaletteAction) {
+        let (text, submit) = match action {
+            PaletteAction::Run(command) => (command, true),
+            PaletteAction::Insert(command) => (command, false),
+            PaletteAction::Macro(name) => return self.run_macro(ctx, &name),
+        };
+        let pane = self.session_mut().focused_pane_mut();
+        pane.dispatch_action(ctx, Action::ClearLine);
+        for ch in text.chars() {
+            pane.dispatch_action(ctx, Action::AppendChar(ch));
+        }
+        if submit {
+            pane.dispatch_action(ctx, Action::Submit);
+        }
+    }
+
+    // The palette overlay: Up/Down move the highlight, Enter or a click picks, Escape closes
+    fn draw_command_palette(&mut self, ctx: &egui::Context) {
+        let Some(palette) = &mut self.command_palette else { return };
+        let (up, down, enter, escape) = ctx.input(|i| {
+            (
+                i.key_pressed(egui::Key::ArrowUp),
+                i.key_pressed(egui::Key::ArrowDown),
+                i.key_pressed(egui::Key::Enter),
+                i.key_pressed(egui::Key::Escape),
+            )
+        });
+        if escape {
+            self.command_palette = None;
+            return;
+        }
+
+        let query = palette.query.clone();
+        let entries = self.palette_entries(&query);
+        let Some(palette) = &mut self.command_palette else { return };
+        if down {
+            palette.selected += 1;
+        }
+        if up {
+            palette.selected = palette.selected.saturating_sub(1);
+        }
+        palette.selected = palette.selected.min(entries.len().saturating_sub(1));
+
+        let mut picked = enter.then_some(palette.selected);
+        egui::Window::new("command_palette")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+            .show(ctx, |ui| {
+                let response = ui.add(egui::TextEdit::singleline(&mut palette.query).hint_text("Run a builtin or macro..."));
+                response.request_focus();
+                if response.changed() {
+                    palette.selected = 0;
+                }
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (i, entry) in entries.iter().enumerate() {
+                        let selected = i == palette.selected;
+                        let row = ui.selectable_label(selected, format!("{}    {}", entry.label, entry.detail));
+                        if selected && (up || down) {
+                            row.scroll_to_me(None);
+                        }
+                        if row.clicked() {
+                            picked = Some(i);
+                        }
+                    }
+                    if entries.is_empty() {
+                        ui.label("No matches");
+                    }
+                });
+            });
+
+        if let Some(entry) = picked.and_then(|i| entries.into_iter().nth(i)) {
+            self.command_palette = None;
+            self.run_palette_action(ctx, entry.action);
+        }
+    }
+
+    // A new pane in the focused pane's directory, for a split or a new tab. Failures are
+    // reported in the focused pane.
+    fn spawn_pane(&mut self, ctx: &egui::Context) -> Option<Pane> {
+        let current_dir = self.focused_pane().shell_state.lock().unwrap().current_dir.clone();
+        let backend = match crate::backend::make_backend(&self.fixed_config) {
+            Ok(backend) => backend,
+            Err(e) => {
+                let pane = self.session_mut().focused_pane_mut();
+                let mut s = pane.shell_state.lock().unwrap();
+                let error_color = s.error_color;
+                s.screen.push_line(Line::from_string(&format!("Failed to open a pane: {}", e), error_color));
+                drop(s);
+                pane.renderer.on_structural_change(ctx);
+                return None;
+            }
+        };
+        let mut state = initial_shell_state(&self.fixed_config, &self.lua_engine, current_dir.clone());
+        state.window_focused = self.window_focused;
+        if self.color_forced_off {
+            state.force_no_color();
+        }
+        if let Err(e) = backend.change_directory(&current_dir, &current_dir) {
+            let error_color = state.error_color;
+            state.screen.push_line(Line::from_string(&format!("Failed to start in {}: {}", current_dir, e), error_color));
+        }
+        let startup = new_pane_actions(get_default_config_path().as_deref());
+        Some(Pane::spawn(ctx, state, backend, startup))
+    }
+
+    // Closes the active tab and everything running in it. The last tab stays open.
+    fn close_tab(&mut self) {
+        if self.sessions.len() == 1 {
+            return;
+        }
+        let session = self.sessions.remove(self.active);
+        session.kill_running();
+        drop(session);
+        self.select_tab(self.active.min(self.sessions.len() - 1));
+    }
+
+    fn select_tab(&mut self, index: usize) {
+        self.active = index;
+        // The window title follows the focused pane of the active tab
+        self.focused_pane().shell_state.lock().unwrap().title_updated = true;
+    }
+
+    // (Re)loads the background image when its configured path changes; on failure the solid color is used
+    fn background_texture_id(&mut self, ctx: &egui::Context, path: Option<&str>) -> Option<egui::TextureId> {
+        let Some(path) = path else {
+            self.background_texture = None;
+            return None;
+        };
+
+        if self.background_texture.as_ref().map(|(p, _)| p.as_str()) != Some(path) {
+            let texture = match crate::renderer::load_background_texture(ctx, path) {
+                Ok(texture) => Some(texture),
+                Err(e) => {
+                    let pane = self.session_mut().focused_pane_mut();
+                    let mut s = pane.shell_state.lock().unwrap();
+                    let error_color = s.error_color;
+                    s.screen.push_line(Line::from_string(
+                        &format!("Failed to load background_image {}: {}", path, e),
+                        error_color,
+                    ));
+                    pane.renderer.on_structural_change(ctx);
+                    None
+                }
+            };
+            self.background_texture = Some((path.to_string(), texture));
+        }
+
+        self.background_texture
+            .as_ref()
+            .and_then(|(_, texture)| texture.as_ref().map(|t| t.id()))
     }
 }
 
 impl eframe::App for TerminalApp {
+    // Window closed: take running children down with us
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        for session in &self.sessions {
+            session.kill_running();
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Poll for new events (Operations are the primary driver of state changes)
         // Check for config file changes
-        let mut config_updated = false;
-        while let Ok(_) = self.config_rx.try_recv() {
-            config_updated = true;
+        let now = Instant::now();
+        if self.config_rx.try_iter().count() > 0 {
+            let debounce = self.focused_pane().shell_state.lock().unwrap().config_reload_debounce;
+            self.reload_debounce.changed(now, debounce);
         }
-
-        if config_updated {
-            if self.last_reload.elapsed() > Duration::from_millis(500) {
-                let _ = self.action_tx.send(Action::RunCommand("config load".to_string()));
-                self.last_reload = Instant::now();
+        if self.reload_debounce.take_due(now) {
+            for pane in self.all_panes() {
+                // Saving the config doesn't move anyone to default_cwd
+                let _ = pane.action_tx.send(Action::ReloadConfig { auto: true });
             }
+        } else if let Some(wait) = self.reload_debounce.wait(now) {
+            ctx.request_repaint_after(wait);
         }
 
-        while let Ok(event) = self.output_rx.try_recv() {
-            match event {
-                ShellEvent::Operation(op) => {
-                    use crate::types::OperationCategory;
-                    match op.category() {
-                        OperationCategory::Structural => self.on_structural_change(ctx, &op),
-                        OperationCategory::Visual => self.on_visual_change(ctx, &op),
-                        OperationCategory::Cursor => self.on_cursor_change(ctx, &op),
-                    }
-                }
-                ShellEvent::Notification(msg) => {
-                    println!("Notification: {}", msg);
-                }
+        // The shell threads decide on completion notices by whether the window has focus
+        let window_focused = ctx.input(|i| i.focused);
+        if window_focused != self.window_focused {
+            self.window_focused = window_focused;
+            for pane in self.all_panes() {
+                pane.shell_state.lock().unwrap().window_focused = window_focused;
             }
         }
 
-        // Fetch state for interpretation and rendering
-        // Fetch state for interpretation and rendering
-        let (current_mode, _shortcuts, opacity, font_size, current_dir, text_color, dir_color, prompt_text, prompt_color, mode_defs) = {
-            let s = self.shell_state.lock().unwrap();
+        // Background tabs keep taking output too, so nothing piles up while they are hidden
+        for pane in self.sessions.iter_mut().flat_map(|session| &mut session.panes) {
+            pane.poll_events(ctx);
+        }
+
+        // Fetch state for interpretation and rendering; the window-wide look follows the focused pane
+        let (current_mode, opacity, font_size, current_dir, mut text_color, mut dir_color, mode_defs, high_contrast, color_enabled) = {
+            let s = self.focused_pane().shell_state.lock().unwrap();
             (
                 s.mode.clone(),
-                s.shortcuts.clone(),
                 s.opacity,
-                s.font_size,
+                s.display_font_size(),
                 s.current_dir.clone(),
-                s.text_color,
+                s.active_text_color(),
                 s.directory_color,
-                s.prompt.clone(),
-                s.prompt_color,
                 s.mode_definitions.clone(),
+                s.high_contrast,
+                s.color_enabled,
             )
         };
+        if !color_enabled {
+            dir_color = text_color;
+        }
+
+        // High contrast overrides whatever the config, theme and mode resolved to
+        let opacity = if high_contrast {
+            text_color = TerminalColor::WHITE;
+            dir_color = TerminalColor::WHITE;
+            1.0
+        } else {
+            opacity
+        };
 
-        // Capture and process InputEvents
-        // Capture and process InputEvents via extracted input module
-        // Capture and process InputEvents via extracted input module
-        let targets = crate::input::poll_and_map(ctx, &current_mode, &mode_defs);
+        // Map raw input to binding targets; this is the only place egui events are read
+        let searching = {
+            let s = self.sessions[self.active].focused_pane().shell_state.lock().unwrap();
+            self.input_mapper.set_key_repeat(s.key_repeat_delay, s.key_repeat_rate);
+            s.history_search.is_some()
+        };
+        // While the palette is open, it gets the keys and the bindings wait
+        let targets = if self.command_palette.is_some() {
+            Vec::new()
+        } else {
+            self.input_mapper.poll_and_map(ctx, &current_mode, &mode_defs, searching)
+        };
         for target in targets {
             match target {
                 crate::types::BindingTarget::Action(action) => {
-                    let _ = self.action_tx.send(action);
+                    self.dispatch_action(ctx, action);
                 },
-                crate::types::BindingTarget::Macro(name) => {
-                     match self.lua_engine.resolve_macro(&name) {
-                         Ok(actions) => {
-                             println!("DEBUG: Macro '{}' resolved to {} actions", name, actions.len());
-                             for action in actions {
-                                 let _ = self.action_tx.send(action);
-                             }
-                         },
-                         Err(e) => {
-                             // User-facing error message
-                             eprintln!("Error: {}", e);
-                             // Detailed debug log
-                             println!("DEBUG: Macro error details: {:?}", e);
-                         }
-                     }
-                }
+                crate::types::BindingTarget::Macro(name) => self.run_macro(ctx, &name),
             }
         }
+        self.draw_command_palette(ctx);
 
         // Check for window title update
         {
-            let mut s = self.shell_state.lock().unwrap();
+            let mut s = self.focused_pane().shell_state.lock().unwrap();
             if s.title_updated {
                 ctx.send_viewport_cmd(egui::ViewportCommand::Title(s.window_title_full.clone()));
                 s.title_updated = false;
@@ -242,6 +773,20 @@ impl eframe::App for TerminalApp {
             )
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
+                    // The tab bar only shows up once there is more than one tab
+                    if self.sessions.len() > 1 {
+                        let mut selected = None;
+                        for (i, session) in self.sessions.iter().enumerate() {
+                            let label = format!("{}: {}", i + 1, session.title());
+                            if ui.selectable_label(i == self.active, label).clicked() {
+                                selected = Some(i);
+                            }
+                        }
+                        if let Some(i) = selected {
+                            self.select_tab(i);
+                        }
+                        ui.separator();
+                    }
                     ui.label(egui::RichText::new("PWD:").color(egui::Color32::from(text_color)));
                     ui.label(
                         egui::RichText::new(current_dir)
@@ -250,39 +795,130 @@ impl eframe::App for TerminalApp {
                 });
             });
 
+        let (background_color, background_image, background_image_opacity, padding) = {
+            let s = self.focused_pane().shell_state.lock().unwrap();
+            if high_contrast {
+                (TerminalColor::BLACK, None, 0.0, egui::Margin::from(s.padding))
+            } else {
+                (s.background_color, s.background_image.clone(), s.background_image_opacity, egui::Margin::from(s.padding))
+            }
+        };
+        let background_texture = self.background_texture_id(ctx, background_image.as_deref());
+        let opacity = opacity.clamp(0.0, 1.0);
+
         egui::CentralPanel::default()
-            .frame(egui::Frame::none().fill(egui::Color32::from_black_alpha(
-                (opacity.clamp(0.0, 1.0) * 255.0) as u8,
-            )))
+            .frame(
+                egui::Frame::none()
+                    .fill(egui::Color32::from_rgba_unmultiplied(
+                        background_color.r,
+                        background_color.g,
+                        background_color.b,
+                        (opacity * 255.0) as u8,
+                    ))
+                    .inner_margin(padding),
+            )
             .show(ctx, |ui| {
-                // Delegate rendering to renderer
-                {
-                    let state = self.shell_state.lock().unwrap();
-                    self.renderer.draw(ui, &state);
+                // Background image sits behind the text and fades with the window opacity
+                if let Some(texture_id) = background_texture {
+                    let alpha = (background_image_opacity.clamp(0.0, 1.0) * opacity * 255.0) as u8;
+                    // The image fills the whole panel, padding included
+                    ui.painter().with_clip_rect(ui.max_rect() + padding).image(
+                        texture_id,
+                        ui.max_rect() + padding,
+                        egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                        egui::Color32::from_white_alpha(alpha),
+                    );
                 }
 
-                // Current Prompt/Input Line
-                ui.horizontal(|ui| {
-                    ui.label(
-                        egui::RichText::new(&prompt_text)
-                            .color(egui::Color32::from(prompt_color))
-                            .strong(),
-                    );
+                self.session_mut().draw(ui);
+            });
 
-                    let mut s = self.shell_state.lock().unwrap();
-                    let text_edit = egui::TextEdit::singleline(&mut s.input_buffer)
-                        .desired_width(ui.available_width())
-                        .frame(false)
-                        .text_color(egui::Color32::WHITE)
-                        .lock_focus(true);
+        // Events wake us up on their own; this only guards against a missed wakeup
+        ctx.request_repaint_after(IDLE_REPAINT_INTERVAL);
+    }
+}
 
-                    let re = ui.add(text_edit);
-                    if current_mode == TerminalMode::Insert {
-                        re.request_focus();
-                    }
-                });
-            });
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_debounce_waits_for_the_last_change() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut debounce = ReloadDebounce::default();
+        assert!(!debounce.take_due(start));
+        assert_eq!(debounce.wait(start), None);
+
+        // Three writes from one save, the last 300ms after the first
+        for at in [0, 150, 300] {
+            debounce.changed(start + ms(at), ms(500));
+        }
+        // Half a second after the first write, but not after the last
+        assert!(!debounce.take_due(start + ms(500)));
+        assert_eq!(debounce.wait(start + ms(500)), Some(ms(300)));
+        assert!(debounce.take_due(start + ms(800)));
+        // Reloaded once
+        assert!(!debounce.take_due(start + ms(900)));
+        assert_eq!(debounce.wait(start + ms(900)), None);
+    }
+
+    #[test]
+    fn test_reset_matches_a_new_pane() {
+        let fixed_config = FixedConfig::default();
+        let lua_engine = crate::lua_bridge::LuaEngine::new();
+        let fresh = initial_shell_state(&fixed_config, &lua_engine, ".".to_string());
+        let mut state = initial_shell_state(&fixed_config, &lua_engine, ".".to_string());
+        state.apply_config(crate::types::ConfigUpdate {
+            prompt: Some("$ ".to_string()),
+            prompt_color: Some(TerminalColor::RED),
+            text_color: Some(TerminalColor::BLACK),
+            background_color: Some(TerminalColor::BLACK),
+            font_size: Some(30.0),
+            opacity: Some(0.2),
+            clear_on_command: Some(true),
+            ..Default::default()
+        });
+        state.reset_appearance();
+
+        assert_eq!(state.prompt, fresh.prompt);
+        assert_eq!(state.prompt_color, fresh.prompt_color);
+        assert_eq!(state.text_color, fresh.text_color);
+        assert_eq!(state.background_color, fresh.background_color);
+        assert_eq!(state.cursor_color, fresh.cursor_color);
+        assert_eq!(state.directory_color, fresh.directory_color);
+        assert_eq!(state.font_size, fresh.font_size);
+        assert_eq!(state.configured_font_size, fresh.configured_font_size);
+        assert_eq!(state.opacity, fresh.opacity);
+        // Not part of the look
+        assert!(state.clear_on_command);
+    }
+
+    #[test]
+    fn test_split_opens_in_the_focused_pane_directory() {
+        let dir = std::env::temp_dir().join("test_app_split_cwd");
+        let pane_dir = dir.join("pane");
+        std::fs::create_dir_all(&pane_dir).unwrap();
+        let path = dir.join("config.lua");
+        std::fs::write(&path, format!("default_cwd = {:?}\nprompt = \"$ \"\n", dir.display())).unwrap();
+        let process_cwd = std::env::current_dir().unwrap();
+
+        let pane_dir = pane_dir.to_string_lossy().to_string();
+        let state = initial_shell_state(&FixedConfig::default(), &crate::lua_bridge::LuaEngine::new(), pane_dir.clone());
+        let state = Arc::new(std::sync::Mutex::new(state));
+        let (tx, _rx) = unbounded();
+        for action in new_pane_actions(Some(&path)) {
+            let Action::ReloadConfig { auto } = action else { panic!("unexpected {:?}", action) };
+            crate::shell::load_config(&path, auto, &state, &tx, &crate::backend::MockBackend::default());
+        }
+        let _ = std::fs::remove_dir_all(&dir);
 
-        ctx.request_repaint();
+        let s = state.lock().unwrap();
+        // The config applies, but the directory stays
+        assert_eq!(s.prompt, "$ ");
+        assert_eq!(s.current_dir, pane_dir);
+        assert_eq!(std::env::current_dir().unwrap(), process_cwd);
+        assert!(s.screen.lines.is_empty());
+        assert!(new_pane_actions(Some(&dir.join("missing.lua"))).is_empty());
     }
 }
@@ -1,38 +1,106 @@
-use crate::types::{ShellEvent, TerminalColor, Line};
+use crate::ansi::{AnsiParser, Control, Output};
+use crate::types::{Cell, Line, NotificationStyle, Notice, ShellEvent, TerminalColor};
 use crossbeam_channel::Sender;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use crate::types::ShellState;
+use crate::fixed_config::{FixedConfig, RemoteConfig};
+use crate::utils::{expand_home, shell_quote};
 
 pub trait ProcessHandle: Send + Sync {
-    #[allow(dead_code)]
-    fn wait(&mut self) -> std::io::Result<()>;
-    #[allow(dead_code)]
+    /// Blocks until the process exits and returns its exit code (see `exit_code`).
+    fn wait(&mut self) -> std::io::Result<i32>;
     fn kill(&mut self) -> std::io::Result<()>;
+    /// Whether the process is still alive; reaps it if it has exited.
+    fn is_running(&mut self) -> bool;
+    /// The OS process id, if there is a real process behind the handle.
+    fn id(&self) -> Option<u32> {
+        None
+    }
 }
 
 pub trait ProcessBackend: Send + Sync {
+    /// Starts `command args`, streaming its output to the screen. `env` holds one-off
+    /// `NAME=value` assignments for this command only, on top of the shell's environment.
     fn spawn(
         &self,
         command: &str,
         args: &[String],
+        env: &[(String, String)],
         output_tx: Sender<ShellEvent>,
         thread_state: Arc<Mutex<ShellState>>,
     ) -> std::io::Result<Box<dyn ProcessHandle>>;
+
+    /// Runs a command to completion and returns its stdout followed by its stderr,
+    /// instead of streaming them to the screen.
+    fn capture(&self, command: &str, args: &[String], thread_state: &Arc<Mutex<ShellState>>) -> std::io::Result<Vec<u8>>;
+
+    /// Whether commands run on this machine. Builtins that read or write files only act on
+    /// the local filesystem, so they are refused when this is false.
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    /// Resolves `path` against `base` (the pane's current directory) and returns the new
+    /// absolute directory. Defaults to checking it exists locally; the process cwd is left
+    /// alone, since every pane shares it.
+    fn change_directory(&self, base: &str, path: &str) -> std::io::Result<String> {
+        let dir = crate::utils::resolve(base, path).canonicalize()?;
+        if !dir.is_dir() {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotADirectory, "Not a directory"));
+        }
+        let dir = dir.to_string_lossy().to_string();
+        // canonicalize gives verbatim `\\?\C:\...` paths on Windows
+        #[cfg(windows)]
+        let dir = dir.strip_prefix(r"\\?\").map(str::to_string).unwrap_or(dir);
+        Ok(dir)
+    }
+}
+
+/// Builds the backend selected by `core.backend`. Run `cfg.validate()` first for
+/// friendlier messages about backends that are reserved but not implemented.
+pub fn make_backend(cfg: &FixedConfig) -> Result<Box<dyn ProcessBackend>, String> {
+    match cfg.core.backend.as_str() {
+        "std" => Ok(Box::new(StdBackend)),
+        "remote" => Ok(Box::new(RemoteBackend::new(cfg.remote.clone()))),
+        other => Err(format!("Unknown backend: {}", other)),
+    }
+}
+
+/// Shell-style exit code: the process's own code, or 128 + signal number if it was killed by one.
+pub fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(-1)
 }
 
 pub struct StdProcessHandle {
-    #[allow(dead_code)]
     pub child: std::process::Child,
 }
 
 impl ProcessHandle for StdProcessHandle {
-    fn wait(&mut self) -> std::io::Result<()> {
-        let _ = self.child.wait()?;
-        Ok(())
+    fn wait(&mut self) -> std::io::Result<i32> {
+        Ok(exit_code(self.child.wait()?))
     }
 
     fn kill(&mut self) -> std::io::Result<()> {
-        self.child.kill()
+        self.child.kill()?;
+        // Reap it so it doesn't linger as a zombie until we exit
+        let _ = self.child.wait();
+        Ok(())
+    }
+
+    fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    fn id(&self) -> Option<u32> {
+        Some(self.child.id())
     }
 }
 
@@ -43,50 +111,655 @@ impl ProcessBackend for StdBackend {
         &self,
         command: &str,
         args: &[String],
+        env: &[(String, String)],
         output_tx: Sender<ShellEvent>,
         thread_state: Arc<Mutex<ShellState>>,
     ) -> std::io::Result<Box<dyn ProcessHandle>> {
-        use std::process::{Command, Stdio};
-        use std::io::{BufRead, BufReader};
-        use std::thread;
+        use std::process::Stdio;
 
-        let mut child = Command::new(command)
-            .args(args)
+        let mut child = local_command(command, args, env, &thread_state)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()?;
 
-        if let Some(stdout) = child.stdout.take() {
-            let state_clone = Arc::clone(&thread_state);
-            let tx_clone = output_tx.clone();
-            thread::spawn(move || {
-                let reader = BufReader::new(stdout);
-                for line in reader.lines() {
-                    if let Ok(l) = line {
-                        let mut s = state_clone.lock().unwrap();
-                        let text_color = s.text_color;
-                        let op = s.screen.push_line(Line::from_string(&l, text_color));
-                        let _ = tx_clone.send(ShellEvent::Operation(op));
+        stream_output(&mut child, output_tx, thread_state);
+        Ok(Box::new(StdProcessHandle { child }))
+    }
+
+    fn capture(&self, command: &str, args: &[String], thread_state: &Arc<Mutex<ShellState>>) -> std::io::Result<Vec<u8>> {
+        let output = local_command(command, args, &[], thread_state).stdin(std::process::Stdio::null()).output()?;
+        Ok([output.stdout, output.stderr].concat())
+    }
+}
+
+/// `command args` set up to run in the shell's directory with its environment plus `env`,
+/// not whatever the process globals hold.
+fn local_command(command: &str, args: &[String], env: &[(String, String)], thread_state: &Arc<Mutex<ShellState>>) -> std::process::Command {
+    let (cwd, envs) = {
+        let s = thread_state.lock().unwrap();
+        (s.current_dir.clone(), s.environment())
+    };
+    let mut cmd = std::process::Command::new(command);
+    cmd.args(args).current_dir(cwd).env_clear().envs(envs).envs(env.iter().cloned());
+    cmd
+}
+
+/// Pushes the child's stdout/stderr onto the screen from reader threads.
+fn stream_output(child: &mut std::process::Child, output_tx: Sender<ShellEvent>, thread_state: Arc<Mutex<ShellState>>) {
+    use std::thread;
+
+    let budget = Arc::new(OutputBudget::new(child.id(), &thread_state));
+    if let Some(stdout) = child.stdout.take() {
+        let state_clone = Arc::clone(&thread_state);
+        let tx_clone = output_tx.clone();
+        let budget = Arc::clone(&budget);
+        thread::spawn(move || pump_output(stdout, false, &tx_clone, &state_clone, &budget));
+    }
+
+    if let Some(stderr) = child.stderr.take() {
+        let state_clone = Arc::clone(&thread_state);
+        let tx_clone = output_tx.clone();
+        thread::spawn(move || pump_output(stderr, true, &tx_clone, &state_clone, &budget));
+    }
+}
+
+/// The `max_command_lines` allowance a command's stdout and stderr readers share.
+struct OutputBudget {
+    // The process to stop once the allowance is used up
+    pid: u32,
+    max_lines: Option<usize>,
+    lines: AtomicUsize,
+}
+
+impl OutputBudget {
+    fn new(pid: u32, thread_state: &Arc<Mutex<ShellState>>) -> Self {
+        let max_lines = thread_state.lock().unwrap().max_command_lines;
+        Self { pid, max_lines, lines: AtomicUsize::new(0) }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.max_lines.is_some_and(|max| self.lines.load(Ordering::Relaxed) >= max)
+    }
+
+    // Counts one more complete line. `Err(true)` for the one line past the allowance, whose
+    // reader reports it, and `Err(false)` for any after that.
+    fn count_line(&self) -> Result<(), bool> {
+        let Some(max) = self.max_lines else { return Ok(()) };
+        match self.lines.fetch_add(1, Ordering::Relaxed) {
+            n if n < max => Ok(()),
+            n => Err(n == max),
+        }
+    }
+}
+
+// A byte of program output and the foreground it was written in (`None` for the default)
+type StyledByte = (u8, Option<TerminalColor>);
+
+/// Moves every complete line out of `pending`. A trailing `\r` is stripped.
+fn take_complete_lines(pending: &mut Vec<StyledByte>) -> Vec<Vec<StyledByte>> {
+    let mut lines = Vec::new();
+    while let Some(pos) = pending.iter().position(|&(b, _)| b == b'\n') {
+        let mut line: Vec<StyledByte> = pending.drain(..=pos).collect();
+        line.pop();
+        if line.last().is_some_and(|&(b, _)| b == b'\r') {
+            line.pop();
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Builds a screen line from output bytes, decoding lossily so invalid UTF-8 becomes
+/// U+FFFD instead of being dropped. Bytes without a color of their own get `default`.
+fn styled_line(bytes: &[StyledByte], default: TerminalColor) -> Line {
+    let mut cells = Vec::new();
+    for run in bytes.chunk_by(|a, b| a.1 == b.1) {
+        let text: Vec<u8> = run.iter().map(|&(b, _)| b).collect();
+        let color = run[0].1.unwrap_or(default);
+        cells.extend(String::from_utf8_lossy(&text).chars().map(|c| Cell::new(c, color)));
+    }
+    Line { cells }
+}
+
+/// Where a program's next line of output goes.
+struct WriteCursor {
+    // Row showing the unfinished last line, rewritten as it grows
+    partial_row: Option<usize>,
+    // Row moved to by cursor addressing; lines overwrite from there down until they
+    // reach the end of the screen
+    addressed_row: Option<usize>,
+    // Screen row that cursor-addressed row 0 maps to: where the program's output began,
+    // or the top after it erased the display
+    origin_row: usize,
+}
+
+/// Copies `reader` to the screen until EOF. Output without a trailing newline (prompts,
+/// progress) is shown right away as a partial line and rewritten in place as it grows.
+/// Escape sequences are acted on or dropped (see [`AnsiParser`]); a window title the
+/// program set is put back when its output ends. Colors are kept unless color is off.
+/// Reading stops once the command has printed more lines than `budget` allows.
+fn pump_output<R: std::io::Read>(mut reader: R, is_stderr: bool, output_tx: &Sender<ShellEvent>, thread_state: &Arc<Mutex<ShellState>>, budget: &OutputBudget) {
+    let mut buf = [0u8; 8192];
+    let (color_enabled, origin_row) = {
+        let s = thread_state.lock().unwrap();
+        (s.color_enabled, s.screen.lines.len())
+    };
+    let mut parser = AnsiParser::new(color_enabled);
+    let mut pending = Vec::new();
+    let mut foreground = None;
+    let mut cursor = WriteCursor { partial_row: None, addressed_row: None, origin_row };
+    // The title before the program first changed it
+    let mut saved_title: Option<String> = None;
+
+    'read: loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        for chunk in parser.feed(&buf[..n]) {
+            let control = match chunk {
+                Output::Text(bytes) => {
+                    pending.extend(bytes.into_iter().map(|b| (b, foreground)));
+                    continue;
+                }
+                Output::Control(control) => control,
+            };
+            // Lines finished before the sequence land where the cursor was
+            if !flush_output(&mut pending, &mut cursor, false, is_stderr, output_tx, thread_state, budget) {
+                break 'read;
+            }
+            match control {
+                Control::SetTitle(title) => {
+                    let mut s = thread_state.lock().unwrap();
+                    saved_title.get_or_insert_with(|| s.window_title_base.clone());
+                    s.window_title_base = title;
+                    s.refresh_window_title();
+                }
+                Control::EraseDisplay => {
+                    pending.clear();
+                    cursor = WriteCursor { partial_row: None, addressed_row: None, origin_row: 0 };
+                    let mut s = thread_state.lock().unwrap();
+                    let op = s.screen.clear();
+                    let _ = output_tx.send(ShellEvent::Operation(op));
+                    if let Some(op) = s.sync_prompt_cursor() {
+                        let _ = output_tx.send(ShellEvent::Operation(op));
+                    }
+                }
+                Control::CursorTo { row } => {
+                    // An unfinished line stays as it is; writing continues at the new row
+                    if !flush_output(&mut pending, &mut cursor, true, is_stderr, output_tx, thread_state, budget) {
+                        break 'read;
                     }
+                    pending.clear();
+                    cursor.partial_row = None;
+                    cursor.addressed_row = Some(cursor.origin_row + row);
                 }
-            });
-        }
-
-        if let Some(stderr) = child.stderr.take() {
-            let state_clone = Arc::clone(&thread_state);
-            let tx_clone = output_tx.clone();
-            thread::spawn(move || {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines() {
-                    if let Ok(l) = line {
-                        let mut s = state_clone.lock().unwrap();
-                        let op = s.screen.push_line(Line::from_string(&l, TerminalColor::RED));
-                        let _ = tx_clone.send(ShellEvent::Operation(op));
+                // `\r` then erase-to-end is how progress output rewrites its line; keep what came after the `\r`
+                Control::EraseLine(0) => {
+                    if let Some(pos) = pending.iter().rposition(|&(b, _)| b == b'\r') {
+                        pending.drain(..=pos);
                     }
                 }
-            });
+                Control::EraseLine(_) => pending.clear(),
+                // Only as a toast: an inline line would land in the middle of the program's output
+                Control::Bell => {
+                    if thread_state.lock().unwrap().notifications == NotificationStyle::Toast {
+                        let notice = Notice { text: "Bell".to_string(), color: TerminalColor::GOLD };
+                        let _ = output_tx.send(ShellEvent::Notification(notice));
+                    }
+                    let _ = output_tx.send(ShellEvent::Bell);
+                }
+                Control::Foreground(color) => foreground = color,
+            }
+        }
+        if !flush_output(&mut pending, &mut cursor, true, is_stderr, output_tx, thread_state, budget) {
+            break;
+        }
+    }
+
+    if let Some(title) = saved_title {
+        let mut s = thread_state.lock().unwrap();
+        s.window_title_base = title;
+        s.refresh_window_title();
+    }
+}
+
+/// Shows the complete lines in `pending`, then (if `show_partial`) whatever is left as a partial line.
+/// Returns false, showing nothing more, once the command has used up its `budget` of lines.
+fn flush_output(pending: &mut Vec<StyledByte>, cursor: &mut WriteCursor, show_partial: bool, is_stderr: bool, output_tx: &Sender<ShellEvent>, thread_state: &Arc<Mutex<ShellState>>, budget: &OutputBudget) -> bool {
+    // The other stream may have used it up
+    if budget.exhausted() {
+        return false;
+    }
+    let complete = take_complete_lines(pending);
+    let partial = (show_partial && !pending.is_empty()).then(|| pending.clone());
+    let texts = complete.into_iter().map(|l| (l, false)).chain(partial.map(|l| (l, true)));
+
+    let mut s = thread_state.lock().unwrap();
+    let color = if is_stderr { s.stderr_color } else { s.text_color };
+    for (text, is_partial) in texts {
+        if !is_partial
+            && let Err(first_over) = budget.count_line()
+        {
+            if first_over {
+                stop_runaway_command(&mut s, budget, output_tx);
+            }
+            return false;
         }
+        let line = styled_line(&text, color);
+        // Finish the partial line we showed earlier, or overwrite the addressed row, if it is still on screen
+        let target = cursor.partial_row.take().or(cursor.addressed_row);
+        let op = match target {
+            Some(row) if row < s.screen.lines.len() => s.screen.update_line(row, line),
+            _ => s.screen.push_line(line),
+        };
+        let row = match op {
+            crate::types::ScreenOperation::UpdateLine(row, _) => row,
+            _ => s.screen.lines.len() - 1,
+        };
+        if is_partial {
+            cursor.partial_row = Some(row);
+        } else if cursor.addressed_row.is_some() {
+            cursor.addressed_row = (row + 1 < s.screen.lines.len()).then_some(row + 1);
+        }
+        let _ = output_tx.send(ShellEvent::Operation(op));
+    }
+    true
+}
+
+// Says why the output stopped and kills the command. One the shell hasn't registered yet
+// (or that batch mode is waiting on) dies of the closed pipe when its reader gives up.
+fn stop_runaway_command(s: &mut ShellState, budget: &OutputBudget, output_tx: &Sender<ShellEvent>) {
+    let max = budget.max_lines.unwrap_or_default();
+    let message = format!("Output stopped after {} lines (max_command_lines); the command was killed", max);
+    let error_color = s.error_color;
+    let op = s.screen.push_line(Line::from_string(&message, error_color));
+    let _ = output_tx.send(ShellEvent::Operation(op));
+    if let Some(command) = s.running.iter_mut().find(|command| command.handle.id() == Some(budget.pid)) {
+        let _ = command.handle.kill();
+    }
+}
+
+/// Runs every command on a remote host through the system `ssh` client.
+/// The remote working directory is tracked here and prefixed to each command,
+/// since every invocation is a fresh ssh session.
+pub struct RemoteBackend {
+    config: RemoteConfig,
+    // Empty until the first `cd`, meaning the remote login directory
+    remote_cwd: Mutex<String>,
+}
+
+impl RemoteBackend {
+    pub fn new(config: RemoteConfig) -> Self {
+        Self { config, remote_cwd: Mutex::new(String::new()) }
+    }
+
+    fn ssh_command(&self, remote_script: &str) -> std::process::Command {
+        let mut cmd = std::process::Command::new("ssh");
+        // No TTY to answer prompts on, so fail instead of hanging on a password prompt
+        cmd.args(["-T", "-o", "BatchMode=yes", "-p", &self.config.port.to_string()]);
+        if let Some(key) = &self.config.key_path {
+            cmd.arg("-i").arg(expand_home(key));
+        }
+        let destination = match &self.config.user {
+            Some(user) => format!("{}@{}", user, self.config.host),
+            None => self.config.host.clone(),
+        };
+        cmd.arg(destination).arg("--").arg(remote_script);
+        cmd
+    }
+
+    // `cd <cwd> && <script>`, or just the script while still in the login directory
+    fn in_remote_cwd(&self, script: &str) -> String {
+        let cwd = self.remote_cwd.lock().unwrap();
+        if cwd.is_empty() {
+            script.to_string()
+        } else {
+            format!("cd {} && {}", shell_quote(&cwd), script)
+        }
+    }
+
+    // The remote script for `command args`, with the shell's export/unset changes and then
+    // `env` applied. Only those are forwarded; the rest is the remote login environment.
+    fn remote_script(&self, command: &str, args: &[String], env: &[(String, String)], thread_state: &Arc<Mutex<ShellState>>) -> String {
+        let mut remote_cmd = std::iter::once(command)
+            .chain(args.iter().map(String::as_str))
+            .map(shell_quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let overrides = thread_state.lock().unwrap().env_overrides.clone();
+        let mut prefix = String::new();
+        for (name, value) in &overrides {
+            if value.is_none() {
+                prefix.push_str(&format!("unset {}; ", name));
+            }
+        }
+        for (name, value) in &overrides {
+            if let Some(value) = value {
+                prefix.push_str(&format!("{}={} ", name, shell_quote(value)));
+            }
+        }
+        for (name, value) in env {
+            prefix.push_str(&format!("{}={} ", name, shell_quote(value)));
+        }
+        remote_cmd.insert_str(0, &prefix);
+        self.in_remote_cwd(&remote_cmd)
+    }
+}
 
+impl ProcessBackend for RemoteBackend {
+    fn spawn(
+        &self,
+        command: &str,
+        args: &[String],
+        env: &[(String, String)],
+        output_tx: Sender<ShellEvent>,
+        thread_state: Arc<Mutex<ShellState>>,
+    ) -> std::io::Result<Box<dyn ProcessHandle>> {
+        use std::process::Stdio;
+
+        let mut child = self
+            .ssh_command(&self.remote_script(command, args, env, &thread_state))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        stream_output(&mut child, output_tx, thread_state);
         Ok(Box::new(StdProcessHandle { child }))
     }
+
+    fn capture(&self, command: &str, args: &[String], thread_state: &Arc<Mutex<ShellState>>) -> std::io::Result<Vec<u8>> {
+        let script = self.remote_script(command, args, &[], thread_state);
+        let output = self.ssh_command(&script).stdin(std::process::Stdio::null()).output()?;
+        Ok([output.stdout, output.stderr].concat())
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    fn change_directory(&self, _base: &str, path: &str) -> std::io::Result<String> {
+        let script = self.in_remote_cwd(&format!("cd {} && pwd", shell_quote(path)));
+        let output = self.ssh_command(&script).stdin(std::process::Stdio::null()).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(std::io::Error::other(if stderr.is_empty() {
+                format!("remote cd to {} failed", path)
+            } else {
+                stderr
+            }));
+        }
+
+        let new_cwd = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        *self.remote_cwd.lock().unwrap() = new_cwd.clone();
+        Ok(new_cwd)
+    }
+}
+
+/// Scripted result of one command run through `MockBackend`.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub struct MockCommand {
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+    pub exit_code: i32,
+}
+
+/// Backend for tests: known commands print their scripted lines synchronously and
+/// "exit" with the scripted code; unknown commands fail to spawn, as if not on PATH.
+/// Every invocation is recorded so tests can assert on arguments.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockBackend {
+    pub commands: std::collections::HashMap<String, MockCommand>,
+    pub calls: Mutex<Vec<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl MockBackend {
+    pub fn with_command(mut self, name: &str, command: MockCommand) -> Self {
+        self.commands.insert(name.to_string(), command);
+        self
+    }
+}
+
+#[cfg(test)]
+struct MockProcessHandle {
+    exit_code: i32,
+}
+
+#[cfg(test)]
+impl ProcessHandle for MockProcessHandle {
+    fn wait(&mut self) -> std::io::Result<i32> {
+        Ok(self.exit_code)
+    }
+
+    fn kill(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn is_running(&mut self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+impl ProcessBackend for MockBackend {
+    fn spawn(
+        &self,
+        command: &str,
+        args: &[String],
+        env: &[(String, String)],
+        output_tx: Sender<ShellEvent>,
+        thread_state: Arc<Mutex<ShellState>>,
+    ) -> std::io::Result<Box<dyn ProcessHandle>> {
+        // Recorded the way they were typed, ahead of the command
+        let mut call: Vec<String> = env.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        call.push(command.to_string());
+        call.extend(args.iter().cloned());
+        self.calls.lock().unwrap().push(call);
+
+        let Some(scripted) = self.commands.get(command) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "program not found"));
+        };
+
+        let mut s = thread_state.lock().unwrap();
+        let (text_color, stderr_color) = (s.text_color, s.stderr_color);
+        let lines = scripted.stdout.iter().map(|l| (l, text_color))
+            .chain(scripted.stderr.iter().map(|l| (l, stderr_color)));
+        for (line, color) in lines {
+            let op = s.screen.push_line(Line::from_string(line, color));
+            let _ = output_tx.send(ShellEvent::Operation(op));
+        }
+        Ok(Box::new(MockProcessHandle { exit_code: scripted.exit_code }))
+    }
+
+    fn capture(&self, command: &str, args: &[String], _thread_state: &Arc<Mutex<ShellState>>) -> std::io::Result<Vec<u8>> {
+        let mut call = vec![command.to_string()];
+        call.extend(args.iter().cloned());
+        self.calls.lock().unwrap().push(call);
+
+        let Some(scripted) = self.commands.get(command) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "program not found"));
+        };
+        let text: String = scripted.stdout.iter().chain(&scripted.stderr).map(|l| format!("{}\n", l)).collect();
+        Ok(text.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pump_output_keeps_invalid_utf8_and_partial_lines() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+
+        // Reads arrive in two chunks: the second completes the first's partial line
+        struct Chunks(Vec<Vec<u8>>);
+        impl std::io::Read for Chunks {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.0.is_empty() {
+                    return Ok(0);
+                }
+                let chunk = self.0.remove(0);
+                buf[..chunk.len()].copy_from_slice(&chunk);
+                Ok(chunk.len())
+            }
+        }
+        let reader = Chunks(vec![b"ok\r\nbad \xFF byte\nprog".to_vec(), b"ress\ntail".to_vec()]);
+        pump_output(reader, false, &tx, &state, &OutputBudget::new(0, &state));
+
+        let text: Vec<String> = state.lock().unwrap().screen.lines.iter()
+            .map(|line| line.cells.iter().map(|c| c.ch).collect())
+            .collect();
+        assert_eq!(text, vec!["ok", "bad \u{FFFD} byte", "progress", "tail"]);
+
+        let updates = rx.try_iter()
+            .filter(|e| matches!(e, ShellEvent::Operation(crate::types::ScreenOperation::UpdateLine(2, _))))
+            .count();
+        assert_eq!(updates, 1);
+    }
+
+    #[test]
+    fn test_pump_output_title_escape() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().window_title_base = "axiomterm".to_string();
+
+        struct TitleThenCheck(u8);
+        impl std::io::Read for TitleThenCheck {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.0 += 1;
+                let chunk: &[u8] = match self.0 {
+                    1 => b"\x1b]0;vim: notes.txt\x07editing\n",
+                    _ => return Ok(0),
+                };
+                buf[..chunk.len()].copy_from_slice(chunk);
+                Ok(chunk.len())
+            }
+        }
+        pump_output(TitleThenCheck(0), false, &tx, &state, &OutputBudget::new(0, &state));
+
+        let s = state.lock().unwrap();
+        let text: String = s.screen.lines[0].cells.iter().map(|c| c.ch).collect();
+        assert_eq!(text, "editing");
+        // Restored once the program's output ended
+        assert_eq!(s.window_title_base, "axiomterm");
+    }
+
+    #[test]
+    fn test_pump_output_erase_and_cursor_home() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let lines = |state: &Arc<Mutex<ShellState>>| -> Vec<String> {
+            state.lock().unwrap().screen.lines.iter().map(|line| line.cells.iter().map(|c| c.ch).collect()).collect()
+        };
+
+        pump_output(&b"old\n\x1b[H\x1b[2Jone\ntwo\nthree\n\x1b[Hfirst\n"[..], false, &tx, &state, &OutputBudget::new(0, &state));
+        assert_eq!(lines(&state), vec!["first", "two", "three"]);
+
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        pump_output(&b"keep\n10%\r\x1b[K20%\r\x1b[Kdone\n"[..], false, &tx, &state, &OutputBudget::new(0, &state));
+        assert_eq!(lines(&state), vec!["keep", "done"]);
+    }
+
+    #[test]
+    fn test_pump_output_colors_unless_disabled() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let output = b"\x1b[31mred\x1b[0m plain\n";
+        for color_enabled in [true, false] {
+            let state = Arc::new(Mutex::new(ShellState::for_test()));
+            state.lock().unwrap().color_enabled = color_enabled;
+            pump_output(&output[..], false, &tx, &state, &OutputBudget::new(0, &state));
+
+            let s = state.lock().unwrap();
+            let cells = &s.screen.lines[0].cells;
+            assert_eq!(cells.iter().map(|c| c.ch).collect::<String>(), "red plain");
+            let red = if color_enabled { crate::utils::ansi_color(1) } else { s.text_color };
+            assert!(cells[..3].iter().all(|c| c.fg == red));
+            assert!(cells[3..].iter().all(|c| c.fg == s.text_color));
+        }
+    }
+
+    #[test]
+    fn test_pump_output_stops_at_max_command_lines() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().max_command_lines = Some(3);
+        let budget = OutputBudget::new(0, &state);
+        pump_output(&b"1\n2\n"[..], true, &tx, &state, &budget);
+        // The allowance is shared with the other stream
+        pump_output(&b"3\n4\n5\npartial"[..], false, &tx, &state, &budget);
+
+        let text: Vec<String> = state.lock().unwrap().screen.lines.iter()
+            .map(|line| line.cells.iter().map(|c| c.ch).collect())
+            .collect();
+        assert_eq!(text, vec!["1", "2", "3", "Output stopped after 3 lines (max_command_lines); the command was killed"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runaway_command_is_stopped() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().max_command_lines = Some(100);
+        let mut handle = StdBackend.spawn("yes", &[], &[], tx, Arc::clone(&state)).unwrap();
+        // Killed, or gone of the closed pipe if its reader got there before it was registered
+        assert_ne!(handle.wait().unwrap(), 0);
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while state.lock().unwrap().screen.lines.len() < 101 && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let s = state.lock().unwrap();
+        assert_eq!(s.screen.lines.len(), 101);
+        assert!(s.screen.lines[100].cells.iter().map(|c| c.ch).collect::<String>().starts_with("Output stopped"));
+    }
+
+    #[test]
+    fn test_local_command_adds_one_off_env() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().env_overrides.insert("SHARED".to_string(), Some("shell".to_string()));
+        let env = [("SHARED".to_string(), "one-off".to_string()), ("ONLY_HERE".to_string(), "1".to_string())];
+        let cmd = local_command("true", &[], &env, &state);
+        let envs: std::collections::HashMap<_, _> = cmd.get_envs().filter_map(|(k, v)| Some((k.to_str()?, v?.to_str()?))).collect();
+        assert_eq!(envs.get("SHARED"), Some(&"one-off"));
+        assert_eq!(envs.get("ONLY_HERE"), Some(&"1"));
+        // Nothing leaks into the shell itself
+        assert!(!state.lock().unwrap().environment().contains_key("ONLY_HERE"));
+    }
+
+    #[test]
+    fn test_mock_backend_scripted_exit_code() {
+        let backend = MockBackend::default().with_command("false", MockCommand { exit_code: 1, ..Default::default() });
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+
+        let mut handle = backend.spawn("false", &[], &[], tx.clone(), Arc::clone(&state)).unwrap();
+        assert_eq!(handle.wait().unwrap(), 1);
+        assert!(backend.spawn("missing", &[], &[], tx, state).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_returns_exit_code() {
+        let mut handle = StdProcessHandle {
+            child: std::process::Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap(),
+        };
+        assert_eq!(handle.wait().unwrap(), 3);
+        assert!(!handle.is_running());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_killed_process_exit_code() {
+        let mut child = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+        child.kill().unwrap();
+        // SIGKILL is 9
+        assert_eq!(exit_code(child.wait().unwrap()), 128 + 9);
+    }
 }
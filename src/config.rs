@@ -1,204 +1,852 @@
-use crate::types::{ConfigUpdate, Shortcut};
-use crate::utils::parse_hex_color;
-use std::path::Path;
+use crate::types::{ConfigUpdate, NotificationStyle, PasteMode, ScrollbarMode, Shortcut, StartupCommands, TerminalColor, MAX_FONT_SIZE, MAX_MAX_INPUT_LENGTH, MIN_FONT_SIZE, MIN_MAX_INPUT_LENGTH};
+use crate::utils::{expand_home, expand_vars, parse_hex_color};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
-pub fn parse_config(path: &Path) -> Result<ConfigUpdate, Box<dyn std::error::Error>> {
-    let code = std::fs::read_to_string(path)?;
-    let ast = match full_moon::parse(&code) {
-        Ok(ast) => ast,
-        Err(e) => {
-            let msg = e.into_iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ");
-            return Err(format!("Parse error: {}", msg).into());
+/// A Lua syntax error in a config file, positioned at the first problem full_moon found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// Text of the offending line, when the position falls inside the file
+    pub source_line: Option<String>,
+}
+
+impl ConfigParseError {
+    /// Formats the error as `config.lua:LINE:COL: msg`, followed by the offending line
+    /// and a caret under the error column when the source text is known.
+    pub fn report(&self, path: &Path) -> Vec<String> {
+        let file_name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_else(|| path.to_string_lossy());
+        let mut lines = vec![format!("{}:{}", file_name, self)];
+        if let Some(text) = &self.source_line {
+            let gutter = format!("{} | ", self.line);
+            lines.push(format!("{}{}", gutter, text));
+            lines.push(format!("{}^", " ".repeat(gutter.len() + self.column.saturating_sub(1))));
         }
+        lines
+    }
+}
+
+impl fmt::Display for ConfigParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ConfigParseError {}
+
+/// Parses a config file. Settings that were present but unusable are skipped or clamped and
+/// described in the returned warnings, so the rest of the file still applies.
+/// Files pulled in with `includes = { ... }` or `axiom.include("...")` are applied where the
+/// include appears, so settings after it override the included ones.
+pub fn parse_config(path: &Path) -> Result<(ConfigUpdate, Vec<String>), Box<dyn std::error::Error>> {
+    parse_config_with_palette(path, &BTreeMap::new())
+}
+
+/// Like [`parse_config`], with `palette` names already defined; used for themes so they can
+/// refer to colors from the main config.
+pub fn parse_config_with_palette(
+    path: &Path,
+    palette: &BTreeMap<String, TerminalColor>,
+) -> Result<(ConfigUpdate, Vec<String>), Box<dyn std::error::Error>> {
+    let ast = read_config_ast(path)?;
+
+    let mut ctx = ParseContext {
+        update: ConfigUpdate::default(),
+        warnings: Vec::new(),
+        open_files: HashSet::from([path.canonicalize().unwrap_or_else(|_| path.to_path_buf())]),
+        dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        palette: palette.clone(),
     };
+    apply_ast(&ast, &mut ctx);
+
+    let ParseContext { mut update, mut warnings, .. } = ctx;
+    warnings.extend(validate_update(&mut update));
+    Ok((update, warnings))
+}
+
+/// Settings accumulated while walking a config file and the files it includes.
+struct ParseContext {
+    update: ConfigUpdate,
+    warnings: Vec<String>,
+    /// Canonical paths of the files currently being walked, to break include cycles
+    open_files: HashSet<PathBuf>,
+    /// Directory of the file being walked; relative includes resolve against it
+    dir: PathBuf,
+    /// Named colors defined so far, shared with included files
+    palette: BTreeMap<String, TerminalColor>,
+}
 
-    let mut update = ConfigUpdate::default();
+fn read_config_ast(path: &Path) -> Result<full_moon::ast::Ast, Box<dyn std::error::Error>> {
+    let code = std::fs::read_to_string(path)?;
+    full_moon::parse(&code).map_err(|errors| -> Box<dyn std::error::Error> {
+        let Some(first) = errors.first() else {
+            return "Parse error".into();
+        };
+        let (start, _) = first.range();
+        let source_line = code.lines().nth(start.line().saturating_sub(1)).map(|l| l.to_string());
+        Box::new(ConfigParseError {
+            line: start.line(),
+            column: start.character(),
+            message: first.error_message().into_owned(),
+            source_line,
+        })
+    })
+}
 
+fn apply_ast(ast: &full_moon::ast::Ast, ctx: &mut ParseContext) {
     for stmt in ast.nodes().stmts() {
-        if let full_moon::ast::Stmt::Assignment(assign) = stmt {
-            for (var, expr) in assign.variables().iter().zip(assign.expressions().iter()) {
-                 let var_str = var.to_string();
-                 let var_name = if var_str.contains('.') {
-                     var_str.split('.').last().unwrap_or("").trim()
-                 } else {
-                     var_str.trim()
-                 };
-                 
-                 match var_name {
-                     "axiomterm_prompt" | "prompt" => {
-                        if let Some(val) = extract_string(expr) { update.prompt = Some(val); }
-                     },
-                     "axiomterm_prompt_color" | "prompt_color" => {
-                        if let Some(val) = extract_string(expr) { update.prompt_color = parse_hex_color(&val); }
-                     },
-                     "axiomterm_text_color" | "text_color" => {
-                        if let Some(val) = extract_string(expr) { update.text_color = parse_hex_color(&val); }
-                     },
-                     "axiomterm_window_title" | "window_title" => {
-                        if let Some(val) = extract_string(expr) { update.window_title = Some(val); }
-                     },
-                     "window_background_opacity" => {
-                        if let Some(val) = extract_float(expr) { update.opacity = Some(val); }
-                     },
-                     "font_size" => {
-                        if let Some(val) = extract_float(expr) { update.font_size = Some(val); }
-                     },
-                     "default_cwd" => {
-                        if let Some(val) = extract_string(expr) { update.default_cwd = Some(val); }
-                     },
-                     "directory_color" => {
-                        if let Some(val) = extract_string(expr) { update.directory_color = parse_hex_color(&val); }
-                     },
-                     "axiomterm_shortcuts" | "keys" => {
-                         if let full_moon::ast::Expression::TableConstructor(table) = expr {
-                             let mut shortcuts = Vec::new();
-                             for field in table.fields() {
-                                 if let full_moon::ast::Field::NoKey(expr) = field {
-                                     if let full_moon::ast::Expression::TableConstructor(inner) = expr {
-                                         let mut key = String::new();
-                                         let mut cmd = String::new();
-                                         for inner_field in inner.fields() {
-                                             let field_str = inner_field.to_string();
-                                             if field_str.contains('=') {
-                                                 let parts: Vec<&str> = field_str.splitn(2, '=').collect();
-                                                 let name_part = parts[0].trim();
-                                                 let value_part = parts[1].trim();
-                                                 if name_part == "key" {
-                                                     key = value_part.trim_matches(|c| c == '"' || c == '\'').to_string();
-                                                 } else if name_part == "cmd" || name_part == "action" {
-                                                     cmd = value_part.trim_matches(|c| c == '"' || c == '\'').to_string();
-                                                 }
-                                             }
-                                         }
-                                         if !key.is_empty() && !cmd.is_empty() {
-                                             shortcuts.push(Shortcut { key, cmd });
-                                         }
-                                     }
-                                 }
-                             }
-                             update.shortcuts = Some(shortcuts);
-                         }
-                     },
-                     "axiomterm_modes" | "modes" => {
-                         if let full_moon::ast::Expression::TableConstructor(table) = expr {
-                             let mut mode_definitions = Vec::new();
-                             for field in table.fields() {
-                                 // Iterate through each mode definition block
-                                 // e.g. { name = "Normal", bindings = { ... } }
-                                 if let full_moon::ast::Field::NoKey(expr) = field {
-                                     if let full_moon::ast::Expression::TableConstructor(inner) = expr {
-                                         let mut mode_name = String::new();
-                                         let mut bindings = Vec::new();
-                                         
-                                         // Parse fields of the mode definition
-                                         for inner_field in inner.fields() {
-                                            // Handle bindings table: bindings = { ... }
-                                            if let full_moon::ast::Field::NameKey { key, value, .. } = inner_field {
-                                                let key_name = key.token().to_string().trim().to_string();
-                                                
-                                                if key_name == "bindings" || key_name == "keys" {
-                                                    if let full_moon::ast::Expression::TableConstructor(b_table) = value {
-                                                        for b_field in b_table.fields() {
-                                                            // Each binding: { key = "...", action = "..." }
-                                                            if let full_moon::ast::Field::NoKey(b_expr) = b_field {
-                                                                if let full_moon::ast::Expression::TableConstructor(b_inner) = b_expr {
-                                                                    let mut key = String::new();
-                                                                    let mut action_str = String::new();
-                                                                    for bi_field in b_inner.fields() {
-                                                                        let bi_str = bi_field.to_string();
-                                                                        if bi_str.contains('=') {
-                                                                            let bi_parts: Vec<&str> = bi_str.splitn(2, '=').collect();
-                                                                            let bik = bi_parts[0].trim();
-                                                                            let biv = bi_parts[1].trim().trim_matches(|c| c == '"' || c == '\'' || c == ',' || c == ' ');
-                                                                            if bik == "key" { key = biv.to_string(); }
-                                                                            else if bik == "action" { action_str = biv.to_string(); }
-                                                                        }
-                                                                    }
-                                                                    if !key.is_empty() && !action_str.is_empty() {
-                                                                        let target = crate::types::Action::from_str(&action_str)
-                                                                            .map(crate::types::BindingTarget::Action)
-                                                                            .unwrap_or_else(|| crate::types::BindingTarget::Macro(action_str.clone()));
-
-                                                                        let mut code = key.clone();
-                                                                        let mut ctrl = false;
-                                                                        let mut alt = false;
-                                                                        let mut shift = false;
-
-                                                                        // Naive modifier parsing
-                                                                        while code.len() > 1 {
-                                                                            if code.to_lowercase().starts_with("ctrl+") {
-                                                                                ctrl = true;
-                                                                                code = code[5..].to_string();
-                                                                            } else if code.to_lowercase().starts_with("alt+") {
-                                                                                alt = true;
-                                                                                code = code[4..].to_string();
-                                                                            } else if code.to_lowercase().starts_with("shift+") {
-                                                                                shift = true;
-                                                                                code = code[6..].to_string();
-                                                                            } else {
-                                                                                break;
-                                                                            }
-                                                                        }
-                                                                        
-                                                                        bindings.push(crate::types::KeyBinding {
-                                                                            event: crate::types::InputEvent::Key { code, ctrl, alt, shift },
-                                                                            target,
-                                                                        });
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            
-                                            // Handle simple key-value pairs like name = "Normal" (fallback logic)
-                                            let field_str = inner_field.to_string();
-                                            if field_str.contains('=') {
-                                                let parts: Vec<&str> = field_str.splitn(2, '=').collect();
-                                                let k = parts[0].trim();
-                                                let v = parts[1].trim().trim_matches(|c| c == '"' || c == '\'' || c == ',' || c == ' ');
-                                                if k == "name" || k == "mode" {
-                                                    mode_name = v.to_string();
-                                                }
-                                            }
-                                         }
-                                         
-                                         if !mode_name.is_empty() {
-                                             if let Some(m) = crate::types::TerminalMode::from_str(&mode_name) {
-                                                 mode_definitions.push(crate::types::ModeDefinition { mode: m, bindings });
-                                             }
-                                         }
-                                     }
-                                 }
-                             }
-                             update.mode_definitions = Some(mode_definitions);
-                         }
-                     },
-                     _ => {}
-                 }
-            }
-        }
-    }
-    
-    Ok(update)
+        match stmt {
+            full_moon::ast::Stmt::Assignment(assign) => {
+                for (var, expr) in assign.variables().iter().zip(assign.expressions().iter()) {
+                    apply_assignment(&var.to_string(), expr, ctx);
+                }
+            }
+            full_moon::ast::Stmt::LocalAssignment(local) => {
+                for (name, expr) in local.names().iter().zip(local.expressions().iter()) {
+                    apply_assignment(&name.token().to_string(), expr, ctx);
+                }
+            }
+            full_moon::ast::Stmt::FunctionCall(call) => {
+                if let Some(name) = include_call_target(call) {
+                    apply_include(&name, ctx);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // `return { prompt = "...", ... }` works like assigning that table to `config`
+    if let Some(full_moon::ast::LastStmt::Return(ret)) = ast.nodes().last_stmt() {
+        for expr in ret.returns() {
+            if let full_moon::ast::Expression::TableConstructor(table) = expr {
+                apply_table_fields(table, ctx);
+            }
+        }
+    }
+}
+
+/// Returns the file named by an `axiom.include("file.lua")` call statement.
+fn include_call_target(call: &full_moon::ast::FunctionCall) -> Option<String> {
+    use full_moon::ast::{Call, Expression, FunctionArgs, Index, Prefix, Suffix};
+
+    let Prefix::Name(prefix) = call.prefix() else {
+        return None;
+    };
+    let mut suffixes = call.suffixes();
+    let (Some(Suffix::Index(Index::Dot { name, .. })), Some(Suffix::Call(Call::AnonymousCall(args))), None) =
+        (suffixes.next(), suffixes.next(), suffixes.next())
+    else {
+        return None;
+    };
+    if prefix.token().to_string() != "axiom" || name.token().to_string() != "include" {
+        return None;
+    }
+    match args {
+        FunctionArgs::Parentheses { arguments, .. } => arguments.iter().next().and_then(extract_string),
+        FunctionArgs::String(token) => extract_string(&Expression::String(token.clone())),
+        _ => None,
+    }
+}
+
+/// Walks an included file into the same update. Missing or broken includes only warn.
+fn apply_include(name: &str, ctx: &mut ParseContext) {
+    let path = ctx.dir.join(expand_home(name));
+    let Ok(canonical) = path.canonicalize() else {
+        ctx.warnings.push(format!("Include not found: {}", path.display()));
+        return;
+    };
+    if !ctx.open_files.insert(canonical.clone()) {
+        ctx.warnings.push(format!("Skipping include {}: include cycle", path.display()));
+        return;
+    }
+
+    match read_config_ast(&canonical) {
+        Ok(ast) => {
+            let parent = canonical.parent().map(Path::to_path_buf).unwrap_or_default();
+            let dir = std::mem::replace(&mut ctx.dir, parent);
+            apply_ast(&ast, ctx);
+            ctx.dir = dir;
+        }
+        Err(e) => match e.downcast_ref::<ConfigParseError>() {
+            Some(parse_error) => ctx.warnings.extend(parse_error.report(&path)),
+            None => ctx.warnings.push(format!("Failed to read include {}: {}", path.display(), e)),
+        },
+    }
+    ctx.open_files.remove(&canonical);
+}
+
+/// Clamps numeric settings into their usable range, returning a warning for each value changed.
+fn validate_update(update: &mut ConfigUpdate) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut clamp = |key: &str, value: &mut Option<f32>, min: f32, max: f32| {
+        if let Some(v) = value.as_mut() {
+            let clamped = if v.is_nan() { max } else { v.clamp(min, max) };
+            if clamped != *v {
+                warnings.push(format!("{} = {} is out of range ({}..{}), using {}", key, v, min, max, clamped));
+                *v = clamped;
+            }
+        }
+    };
+    clamp("window_background_opacity", &mut update.opacity, 0.0, 1.0);
+    clamp("background_image_opacity", &mut update.background_image_opacity, 0.0, 1.0);
+    clamp("hidden_file_dim", &mut update.hidden_file_dim, 0.0, 1.0);
+    clamp("notify_after_secs", &mut update.notify_after_secs, 0.0, 86400.0);
+    clamp("font_size", &mut update.font_size, MIN_FONT_SIZE, MAX_FONT_SIZE);
+    clamp("min_font_size", &mut update.min_font_size, MIN_FONT_SIZE, MAX_FONT_SIZE);
+    clamp("scrollbar_width", &mut update.scrollbar_width, 1.0, 32.0);
+    clamp("key_repeat_delay", &mut update.key_repeat_delay, 0.0, 5000.0);
+    clamp("key_repeat_rate", &mut update.key_repeat_rate, 1.0, 120.0);
+    clamp("config_reload_debounce", &mut update.config_reload_debounce, 0.0, 10000.0);
+    if let Some(len) = update.max_input_length.as_mut() {
+        let clamped = (*len).clamp(MIN_MAX_INPUT_LENGTH, MAX_MAX_INPUT_LENGTH);
+        if clamped != *len {
+            warnings.push(format!("max_input_length = {} is out of range ({}..{}), using {}", len, MIN_MAX_INPUT_LENGTH, MAX_MAX_INPUT_LENGTH, clamped));
+            *len = clamped;
+        }
+    }
+    warnings
+}
+
+// The conventional name of the table holding all settings (`config = {...}`, `local config = {...}`)
+const CONFIG_TABLE: &str = "config";
+// Key listing extra config files to merge, relative to the including file
+const INCLUDES_KEY: &str = "includes";
+
+/// Handles `target = expr`, where `target` may be a plain or dotted name (`config.prompt`).
+fn apply_assignment(target: &str, expr: &full_moon::ast::Expression, ctx: &mut ParseContext) {
+    let var_name = target.rsplit('.').next().unwrap_or("").trim();
+    match expr {
+        full_moon::ast::Expression::TableConstructor(table) if var_name == CONFIG_TABLE => {
+            apply_table_fields(table, ctx);
+        }
+        _ if var_name == INCLUDES_KEY => {
+            // `includes = { "colors.lua", "keys.lua" }` or a single `includes = "colors.lua"`
+            let names: Vec<String> = match expr {
+                full_moon::ast::Expression::TableConstructor(table) => table
+                    .fields()
+                    .iter()
+                    .filter_map(|field| match field {
+                        full_moon::ast::Field::NoKey(value) => extract_string(value),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => extract_string(expr).into_iter().collect(),
+            };
+            for name in names {
+                apply_include(&name, ctx);
+            }
+        }
+        _ => apply_setting(var_name, expr, ctx),
+    }
+}
+
+/// Applies each `name = value` field of a config table literal as if assigned at top level.
+fn apply_table_fields(table: &full_moon::ast::TableConstructor, ctx: &mut ParseContext) {
+    for field in table.fields() {
+        if let full_moon::ast::Field::NameKey { key, value, .. } = field {
+            apply_assignment(&key.token().to_string(), value, ctx);
+        }
+    }
+}
+
+/// A path setting with `~` and `$VARS` expanded, resolved against the directory of the
+/// file that set it if it is still relative.
+fn config_path(dir: &Path, value: &str) -> PathBuf {
+    dir.join(expand_home(&expand_vars(value, |name| std::env::var(name).ok())))
+}
+
+fn apply_setting(var_name: &str, expr: &full_moon::ast::Expression, ctx: &mut ParseContext) {
+    let ParseContext { update, warnings, palette, dir, .. } = ctx;
+    match var_name {
+        "axiomterm_prompt" | "prompt" => {
+           if let Some(val) = extract_string(expr) { update.prompt = Some(val); }
+        },
+        "axiomterm_prompt_color" | "prompt_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.prompt_color = Some(val); }
+        },
+        "axiomterm_text_color" | "text_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.text_color = Some(val); }
+        },
+        "axiomterm_window_title" | "window_title" => {
+           if let Some(val) = extract_string(expr) { update.window_title = Some(val); }
+        },
+        "window_title_format" => {
+           if let Some(val) = extract_string(expr) { update.window_title_format = Some(val); }
+        },
+        "window_background_opacity" => {
+           if let Some(val) = extract_float(expr) { update.opacity = Some(val); }
+        },
+        "font_size" => {
+           if let Some(val) = extract_float(expr) { update.font_size = Some(val); }
+        },
+        "min_font_size" => {
+           if let Some(val) = extract_float(expr) { update.min_font_size = Some(val); }
+        },
+        "high_contrast" => {
+           if let Some(val) = extract_bool(expr) { update.high_contrast = Some(val); }
+        },
+        "no_color" => {
+           if let Some(val) = extract_bool(expr) { update.no_color = Some(val); }
+        },
+        "clear_on_command" => {
+           if let Some(val) = extract_bool(expr) { update.clear_on_command = Some(val); }
+        },
+        "key_repeat_delay" => {
+           if let Some(val) = extract_float(expr) { update.key_repeat_delay = Some(val); }
+        },
+        "key_repeat_rate" => {
+           if let Some(val) = extract_float(expr) { update.key_repeat_rate = Some(val); }
+        },
+        "clickable_links" => {
+           if let Some(val) = extract_bool(expr) { update.clickable_links = Some(val); }
+        },
+        "word_chars" => {
+           if let Some(val) = extract_string(expr) { update.word_chars = Some(val); }
+        },
+        "max_input_length" => {
+           if let Some(val) = count_setting("max_input_length", expr, 1, warnings) { update.max_input_length = Some(val); }
+        },
+        "max_command_lines" => {
+           // false turns the limit off
+           if extract_bool(expr) == Some(false) {
+               update.max_command_lines = Some(None);
+           } else if let Some(val) = count_setting("max_command_lines", expr, 1, warnings) {
+               update.max_command_lines = Some(Some(val));
+           }
+        },
+        "copy_on_select" => {
+           if let Some(val) = extract_bool(expr) { update.copy_on_select = Some(val); }
+        },
+        "config_reload_quiet" => {
+           if let Some(val) = extract_bool(expr) { update.config_reload_quiet = Some(val); }
+        },
+        "config_reload_debounce" => {
+           if let Some(val) = extract_float(expr) { update.config_reload_debounce = Some(val); }
+        },
+        "multiline_paste" => {
+           if let Some(val) = extract_string(expr) {
+               match PasteMode::from_str(&val) {
+                   Some(mode) => update.multiline_paste = Some(mode),
+                   None => {
+                       let names: Vec<&str> = PasteMode::ALL.iter().map(|m| m.name()).collect();
+                       warnings.push(format!("Ignoring multiline_paste: {:?} is not one of {}", val, names.join(", ")));
+                   }
+               }
+           }
+        },
+        "notifications" => {
+           if let Some(val) = extract_string(expr) {
+               match NotificationStyle::from_str(&val) {
+                   Some(style) => update.notifications = Some(style),
+                   None => {
+                       let names: Vec<&str> = NotificationStyle::ALL.iter().map(|s| s.name()).collect();
+                       warnings.push(format!("Ignoring notifications: {:?} is not one of {}", val, names.join(", ")));
+                   }
+               }
+           }
+        },
+        "notify_after_secs" => {
+           if let Some(val) = extract_float(expr) { update.notify_after_secs = Some(val); }
+        },
+        "confirm_destructive" => {
+           if let Some(val) = extract_bool(expr) { update.confirm_destructive = Some(val); }
+        },
+        "default_cwd" => {
+           if let Some(val) = extract_string(expr) { update.default_cwd = Some(config_path(dir, &val).to_string_lossy().into_owned()); }
+        },
+        "on_startup" => {
+            // A list of commands, or the path of an rc file relative to this config file
+            if let full_moon::ast::Expression::TableConstructor(table) = expr {
+                let commands = table
+                    .fields()
+                    .iter()
+                    .filter_map(|field| match field {
+                        full_moon::ast::Field::NoKey(value) => extract_string(value),
+                        _ => None,
+                    })
+                    .collect();
+                update.on_startup = Some(StartupCommands::List(commands));
+            } else if let Some(path) = extract_string(expr) {
+                update.on_startup = Some(StartupCommands::File(dir.join(expand_home(&path))));
+            }
+        },
+        "directory_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.directory_color = Some(val); }
+        },
+        "cursor_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.cursor_color = Some(val); }
+        },
+        "background_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.background_color = Some(val); }
+        },
+        "background_image" => {
+           if let Some(val) = extract_string(expr) { update.background_image = Some(val); }
+        },
+        "background_image_opacity" => {
+           if let Some(val) = extract_float(expr) { update.background_image_opacity = Some(val); }
+        },
+        "current_line_highlight" => {
+           // "none", "" or false turn the highlight off
+           let disabled = extract_bool(expr) == Some(false)
+               || extract_string(expr).is_some_and(|val| val.is_empty() || val.eq_ignore_ascii_case("none"));
+           if disabled {
+               update.current_line_highlight = Some(None);
+           } else if let Some(val) = color_setting(var_name, expr, palette, warnings) {
+               update.current_line_highlight = Some(Some(val));
+           }
+        },
+        "padding" => {
+           if let Some(val) = extract_padding(expr) { update.padding = Some(val); }
+        },
+        "theme" => {
+           if let Some(val) = extract_string(expr) { update.theme = Some(val); }
+        },
+        "stderr_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.stderr_color = Some(val); }
+        },
+        "error_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.error_color = Some(val); }
+        },
+        "colors" => {
+            // Palette entries may refer to entries defined before them
+            if let full_moon::ast::Expression::TableConstructor(table) = expr {
+                for field in table.fields() {
+                    if let full_moon::ast::Field::NameKey { key, value, .. } = field {
+                        let name = key.token().to_string().trim().to_string();
+                        if let Some(color) = color_setting(&format!("colors.{}", name), value, palette, warnings) {
+                            palette.insert(name, color);
+                        }
+                    }
+                }
+                update.palette = Some(palette.clone());
+            }
+        },
+        "scrollbar" => {
+           if let Some(val) = extract_string(expr) {
+               match ScrollbarMode::from_str(&val) {
+                   Some(mode) => update.scrollbar = Some(mode),
+                   None => {
+                       let names: Vec<&str> = ScrollbarMode::ALL.iter().map(|m| m.name()).collect();
+                       warnings.push(format!("Ignoring scrollbar: {:?} is not one of {}", val, names.join(", ")));
+                   }
+               }
+           }
+        },
+        "scrollbar_width" => {
+           if let Some(val) = extract_float(expr) { update.scrollbar_width = Some(val); }
+        },
+        "scrollbar_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.scrollbar_color = Some(val); }
+        },
+        "symlink_color" => {
+           if let Some(val) = color_setting(var_name, expr, palette, warnings) { update.symlink_color = Some(val); }
+        },
+        "hidden_file_dim" => {
+           if let Some(val) = extract_float(expr) { update.hidden_file_dim = Some(val); }
+        },
+        "ls_colors" => {
+            // `rs = "#DEA584"`, `image = "accent"` or `["tar.gz"] = "#FF0000"`
+            if let full_moon::ast::Expression::TableConstructor(table) = expr {
+                let mut colors = BTreeMap::new();
+                for field in table.fields() {
+                    let (key, value) = match field {
+                        full_moon::ast::Field::NameKey { key, value, .. } => (key.token().to_string(), value),
+                        full_moon::ast::Field::ExpressionKey { key, value, .. } => match extract_string(key) {
+                            Some(key) => (key, value),
+                            None => continue,
+                        },
+                        _ => continue,
+                    };
+                    let key = key.trim().trim_start_matches("*.").trim_start_matches('.').to_lowercase();
+                    if let Some(color) = color_setting(&format!("ls_colors.{}", key), value, palette, warnings) {
+                        colors.insert(key, color);
+                    }
+                }
+                update.ls_colors = Some(colors);
+            }
+        },
+        "axiomterm_shortcuts" | "keys" => {
+            if let full_moon::ast::Expression::TableConstructor(table) = expr {
+                let mut shortcuts = Vec::new();
+                for field in table.fields() {
+                    if let full_moon::ast::Field::NoKey(expr) = field {
+                        if let full_moon::ast::Expression::TableConstructor(inner) = expr {
+                            let mut key = String::new();
+                            let mut cmd = String::new();
+                            for inner_field in inner.fields() {
+                                let field_str = inner_field.to_string();
+                                if field_str.contains('=') {
+                                    let parts: Vec<&str> = field_str.splitn(2, '=').collect();
+                                    let name_part = parts[0].trim();
+                                    let value_part = parts[1].trim();
+                                    if name_part == "key" {
+                                        key = value_part.trim_matches(|c| c == '"' || c == '\'').to_string();
+                                    } else if name_part == "cmd" || name_part == "action" {
+                                        cmd = value_part.trim_matches(|c| c == '"' || c == '\'').to_string();
+                                    }
+                                }
+                            }
+                            if !key.is_empty() && !cmd.is_empty() {
+                                shortcuts.push(Shortcut { key, cmd });
+                            }
+                        }
+                    }
+                }
+                update.shortcuts = Some(shortcuts);
+            }
+        },
+        "axiomterm_modes" | "modes" => {
+            if let full_moon::ast::Expression::TableConstructor(table) = expr {
+                let mut mode_definitions = Vec::new();
+                for field in table.fields() {
+                    // Iterate through each mode definition block
+                    // e.g. { name = "Normal", bindings = { ... } }
+                    if let full_moon::ast::Field::NoKey(expr) = field {
+                        if let full_moon::ast::Expression::TableConstructor(inner) = expr {
+                            let mut mode_name = String::new();
+                            let mut bindings = Vec::new();
+                            let mut appearance = crate::types::ModeAppearance::default();
+                            
+                            // Parse fields of the mode definition
+                            for inner_field in inner.fields() {
+                               // Handle bindings table: bindings = { ... }
+                               if let full_moon::ast::Field::NameKey { key, value, .. } = inner_field {
+                                   let key_name = key.token().to_string().trim().to_string();
+
+                                   // The mode's name, and per-mode prompt overrides
+                                   match key_name.as_str() {
+                                       "name" | "mode" => {
+                                           if let Some(name) = extract_string(value) {
+                                               mode_name = name;
+                                           }
+                                       }
+                                       "prompt" => appearance.prompt = extract_string(value),
+                                       "prompt_color" => appearance.prompt_color = color_setting("modes.prompt_color", value, palette, warnings),
+                                       "text_color" => appearance.text_color = color_setting("modes.text_color", value, palette, warnings),
+                                       _ => {}
+                                   }
+
+                                   if key_name == "bindings" || key_name == "keys" {
+                                       if let full_moon::ast::Expression::TableConstructor(b_table) = value {
+                                           for b_field in b_table.fields() {
+                                               // Each binding: { key = "...", action = "..." }
+                                               if let full_moon::ast::Field::NoKey(b_expr) = b_field {
+                                                   if let full_moon::ast::Expression::TableConstructor(b_inner) = b_expr {
+                                                       let mut key = String::new();
+                                                       let mut action_str = String::new();
+                                                       for bi_field in b_inner.fields() {
+                                                           if let full_moon::ast::Field::NameKey { key: bi_key, value: bi_value, .. } = bi_field
+                                                               && let Some(text) = extract_string(bi_value)
+                                                           {
+                                                               match bi_key.token().to_string().trim() {
+                                                                   "key" => key = text,
+                                                                   "action" => action_str = text,
+                                                                   _ => {}
+                                                               }
+                                                           }
+                                                       }
+                                                       if !key.is_empty() && !action_str.is_empty() {
+                                                           let target = crate::types::Action::from_str(&action_str)
+                                                               .map(crate::types::BindingTarget::Action)
+                                                               .unwrap_or_else(|| crate::types::BindingTarget::Macro(action_str.clone()));
+
+                                                           bindings.push(crate::types::KeyBinding {
+                                                               event: parse_binding_key(&key),
+                                                               target,
+                                                           });
+                                                       }
+                                                   }
+                                               }
+                                           }
+                                       }
+                                   }
+                               }
+                            }
+                            
+                            if !mode_name.is_empty() {
+                                if let Some(m) = crate::types::TerminalMode::from_str(&mode_name) {
+                                    mode_definitions.push(crate::types::ModeDefinition { mode: m, bindings, appearance });
+                                }
+                            }
+                        }
+                    }
+                }
+                update.mode_definitions = Some(mode_definitions);
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Parses a binding key such as `"Ctrl+L"`, or a space-separated chord such as `"g g"`.
+fn parse_binding_key(key: &str) -> crate::types::InputEvent {
+    let mut events: Vec<_> = key.split_whitespace().map(parse_single_key).collect();
+    if events.len() == 1 {
+        events.remove(0)
+    } else {
+        crate::types::InputEvent::Sequence(events)
+    }
 }
 
+// Modifier prefixes accepted in binding keys, matched case-insensitively
+const MODIFIER_PREFIXES: &[(&str, char)] = &[
+    ("ctrl+", 'c'),
+    ("control+", 'c'),
+    ("c-", 'c'),
+    ("alt+", 'a'),
+    ("meta+", 'a'),
+    ("a-", 'a'),
+    ("m-", 'a'),
+    ("shift+", 's'),
+    ("s-", 's'),
+];
+
+fn parse_single_key(key: &str) -> crate::types::InputEvent {
+    let mut rest = key;
+    let mut ctrl = false;
+    let mut alt = false;
+    let mut shift = false;
+
+    // Strip modifier prefixes, always leaving at least one character as the key itself
+    'strip: loop {
+        let lower = rest.to_ascii_lowercase();
+        for (prefix, modifier) in MODIFIER_PREFIXES {
+            if lower.starts_with(prefix) && rest.len() > prefix.len() {
+                match modifier {
+                    'c' => ctrl = true,
+                    'a' => alt = true,
+                    _ => shift = true,
+                }
+                rest = &rest[prefix.len()..];
+                continue 'strip;
+            }
+        }
+        break;
+    }
+
+    crate::types::InputEvent::Key {
+        code: crate::input::normalize_key_code(rest),
+        ctrl,
+        alt,
+        shift,
+    }
+}
+
+/// Returns the value of a Lua string literal, with escapes decoded for quoted strings.
 fn extract_string(expr: &full_moon::ast::Expression) -> Option<String> {
-    if let full_moon::ast::Expression::String(s) = expr {
-        let val = s.token().to_string();
-        if val.len() >= 2 {
-            return Some(val[1..val.len()-1].to_string());
+    use full_moon::tokenizer::{StringLiteralQuoteType, TokenType};
+
+    let full_moon::ast::Expression::String(s) = expr else {
+        return None;
+    };
+    match s.token().token_type() {
+        TokenType::StringLiteral { literal, quote_type: StringLiteralQuoteType::Brackets, .. } => {
+            // Long brackets take their contents verbatim, except that a leading newline is skipped
+            let literal = literal.as_str();
+            let body = literal.strip_prefix("\r\n").or_else(|| literal.strip_prefix('\n')).unwrap_or(literal);
+            Some(body.to_string())
+        }
+        TokenType::StringLiteral { literal, .. } => Some(unescape_lua_string(literal)),
+        _ => None,
+    }
+}
+
+/// Decodes the escape sequences of a quoted Lua string body.
+/// Unknown escapes are kept verbatim (backslash included), so `"C:\Users"`-style paths written
+/// without doubled backslashes still come through intact.
+fn unescape_lua_string(raw: &str) -> String {
+    // Bytes, since `\xNN` and `\ddd` escapes can spell out multi-byte UTF-8 sequences
+    let mut out = Vec::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            push_char(&mut out, c);
+            continue;
+        }
+        let Some(esc) = chars.next() else {
+            out.push(b'\\');
+            break;
+        };
+        match esc {
+            'n' => out.push(b'\n'),
+            't' => out.push(b'\t'),
+            'r' => out.push(b'\r'),
+            'a' => out.push(0x07),
+            'b' => out.push(0x08),
+            'f' => out.push(0x0C),
+            'v' => out.push(0x0B),
+            '\\' | '"' | '\'' => push_char(&mut out, esc),
+            '\n' => out.push(b'\n'),
+            // \z skips the following whitespace, including line breaks
+            'z' => {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            }
+            'x' => {
+                let hex: String = (0..2).filter_map(|_| chars.next_if(char::is_ascii_hexdigit)).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 => out.push(byte),
+                    _ => {
+                        out.extend_from_slice(b"\\x");
+                        out.extend_from_slice(hex.as_bytes());
+                    }
+                }
+            }
+            'u' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let hex: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_hexdigit)).collect();
+                let closed = chars.next_if_eq(&'}').is_some();
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(ch) if closed => push_char(&mut out, ch),
+                    _ => {
+                        out.extend_from_slice(b"\\u{");
+                        out.extend_from_slice(hex.as_bytes());
+                        if closed {
+                            out.push(b'}');
+                        }
+                    }
+                }
+            }
+            d if d.is_ascii_digit() => {
+                // Up to three decimal digits
+                let mut digits = d.to_string();
+                while digits.len() < 3 && let Some(next) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(next);
+                }
+                match digits.parse::<u8>() {
+                    Ok(byte) => out.push(byte),
+                    Err(_) => {
+                        out.push(b'\\');
+                        out.extend_from_slice(digits.as_bytes());
+                    }
+                }
+            }
+            other => {
+                out.push(b'\\');
+                push_char(&mut out, other);
+            }
         }
     }
-    None
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn push_char(out: &mut Vec<u8>, c: char) {
+    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
 }
 
 fn extract_float(expr: &full_moon::ast::Expression) -> Option<f32> {
-    if let full_moon::ast::Expression::Number(n) = expr {
-        return n.token().to_string().parse::<f32>().ok();
+    match expr {
+        full_moon::ast::Expression::Number(n) => {
+            let text = n.token().to_string();
+            text.trim().parse::<f32>().ok().or_else(|| parse_lua_int(&text).map(|v| v as f32))
+        }
+        full_moon::ast::Expression::UnaryOperator { unop: full_moon::ast::UnOp::Minus(_), expression } => {
+            extract_float(expression).map(|v| -v)
+        }
+        _ => None,
+    }
+}
+
+/// Reads an integer literal, including hex (`0xFF8800`) and negated (`-4`) forms.
+fn extract_int(expr: &full_moon::ast::Expression) -> Option<i64> {
+    match expr {
+        full_moon::ast::Expression::Number(n) => parse_lua_int(&n.token().to_string()),
+        full_moon::ast::Expression::UnaryOperator { unop: full_moon::ast::UnOp::Minus(_), expression } => {
+            extract_int(expression).map(|v| -v)
+        }
+        _ => None,
+    }
+}
+
+fn parse_lua_int(text: &str) -> Option<i64> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
     }
-    None
+}
+
+fn extract_bool(expr: &full_moon::ast::Expression) -> Option<bool> {
+    let full_moon::ast::Expression::Symbol(token) = expr else {
+        return None;
+    };
+    match token.token().to_string().trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Like [`extract_color`], but records a warning naming `key` when the value isn't a color.
+fn color_setting(
+    key: &str,
+    expr: &full_moon::ast::Expression,
+    palette: &BTreeMap<String, TerminalColor>,
+    warnings: &mut Vec<String>,
+) -> Option<TerminalColor> {
+    let color = extract_color(expr, palette);
+    if color.is_none() {
+        let value = expr.to_string();
+        let value = value.trim();
+        match extract_string(expr) {
+            Some(name) if !name.starts_with('#') => {
+                warnings.push(format!("Ignoring {}: {} is not a hex color or a name defined in `colors`", key, value));
+            }
+            _ => warnings.push(format!("Ignoring {}: {} is not a valid color (expected \"#RRGGBB\")", key, value)),
+        }
+    }
+    color
+}
+
+/// Reads a whole-number setting of at least `min`, warning about anything else (fractions,
+/// negative numbers, strings) instead of letting it wrap around to 0.
+fn count_setting(key: &str, expr: &full_moon::ast::Expression, min: i64, warnings: &mut Vec<String>) -> Option<usize> {
+    let count = extract_int(expr).filter(|v| *v >= min).and_then(|v| usize::try_from(v).ok());
+    if count.is_none() {
+        warnings.push(format!("Ignoring {}: {} is not a whole number of at least {}", key, expr.to_string().trim(), min));
+    }
+    count
+}
+
+/// Reads a color given as a `"#RRGGBB"` string, a `0xRRGGBB` number, or the name of a palette
+/// entry (`"accent"` after `colors = { accent = "#FF8800" }`).
+fn extract_color(expr: &full_moon::ast::Expression, palette: &BTreeMap<String, TerminalColor>) -> Option<TerminalColor> {
+    if let Some(val) = extract_string(expr) {
+        return palette.get(&val).copied().or_else(|| parse_hex_color(&val));
+    }
+    let rgb = u32::try_from(extract_int(expr)?).ok().filter(|v| *v <= 0xFF_FFFF)?;
+    Some(TerminalColor::from_rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+/// Reads `padding = 8` or a per-side table such as `padding = { top = 4, left = 10 }`.
+/// Sides missing from the table keep the default padding.
+fn extract_padding(expr: &full_moon::ast::Expression) -> Option<crate::types::Padding> {
+    if let Some(val) = extract_float(expr) {
+        return Some(crate::types::Padding::uniform(val.max(0.0)));
+    }
+
+    let full_moon::ast::Expression::TableConstructor(table) = expr else {
+        return None;
+    };
+    let mut padding = crate::types::Padding::default();
+    for field in table.fields() {
+        if let full_moon::ast::Field::NameKey { key, value, .. } = field
+            && let Some(val) = extract_float(value)
+        {
+            let val = val.max(0.0);
+            match key.token().to_string().trim() {
+                "top" => padding.top = val,
+                "right" => padding.right = val,
+                "bottom" => padding.bottom = val,
+                "left" => padding.left = val,
+                _ => {}
+            }
+        }
+    }
+    Some(padding)
 }
 
 #[cfg(test)]
@@ -227,7 +875,7 @@ mod tests {
         let temp_file = temp_dir.join("test_config_modes.lua");
         std::fs::write(&temp_file, config).unwrap();
 
-        let update = parse_config(&temp_file).unwrap();
+        let (update, _) = parse_config(&temp_file).unwrap();
         // Clean up
         let _ = std::fs::remove_file(&temp_file);
 
@@ -242,7 +890,7 @@ mod tests {
         // Check bindings
         let has_insert = def.bindings.iter().any(|b| 
             matches!(&b.target, crate::types::BindingTarget::Action(Action::ChangeMode(TerminalMode::Insert))) && 
-            matches!(&b.event, InputEvent::Key { code, .. } if code == "i")
+            matches!(&b.event, InputEvent::Key { code, .. } if code == "I")
         );
         assert!(has_insert);
 
@@ -252,4 +900,434 @@ mod tests {
         );
         assert!(has_clear);
     }
+
+    #[test]
+    fn test_sequence_key_parsing() {
+        let event = parse_binding_key("g g");
+        assert_eq!(
+            event,
+            InputEvent::Sequence(vec![
+                InputEvent::Key { code: "G".to_string(), ctrl: false, alt: false, shift: false },
+                InputEvent::Key { code: "G".to_string(), ctrl: false, alt: false, shift: false },
+            ])
+        );
+        assert!(matches!(parse_binding_key("Escape"), InputEvent::Key { .. }));
+    }
+
+    #[test]
+    fn test_modifier_key_parsing() {
+        let key = |code: &str, ctrl, alt, shift| InputEvent::Key { code: code.to_string(), ctrl, alt, shift };
+
+        assert_eq!(parse_binding_key("C-l"), key("L", true, false, false));
+        assert_eq!(parse_binding_key("Ctrl+L"), key("L", true, false, false));
+        assert_eq!(parse_binding_key("A-x"), key("X", false, true, false));
+        assert_eq!(parse_binding_key("M-x"), key("X", false, true, false));
+        assert_eq!(parse_binding_key("S-Tab"), key("Tab", false, false, true));
+        assert_eq!(parse_binding_key("ctrl+shift+esc"), key("Escape", true, false, true));
+        assert_eq!(parse_binding_key("C-"), key("C-", false, false, false));
+        assert_eq!(parse_binding_key("Ctrl+-"), key("Minus", true, false, false));
+    }
+
+    #[test]
+    fn test_mode_appearance_parsing() {
+        let config = r##"
+            config = {}
+            config.modes = {
+                {
+                    name = "Normal",
+                    prompt = "N> ",
+                    prompt_color = "#FF0000",
+                    bindings = {
+                        { key = "i", action = "ChangeMode(Insert)" }
+                    }
+                }
+            }
+            return config
+        "##;
+
+        let temp_file = std::env::temp_dir().join("test_config_mode_appearance.lua");
+        std::fs::write(&temp_file, config).unwrap();
+        let (update, _) = parse_config(&temp_file).unwrap();
+        let _ = std::fs::remove_file(&temp_file);
+
+        let modes = update.mode_definitions.unwrap();
+        let appearance = &modes[0].appearance;
+        assert_eq!(appearance.prompt.as_deref(), Some("N> "));
+        assert_eq!(appearance.prompt_color, Some(crate::types::TerminalColor::RED));
+        assert_eq!(appearance.text_color, None);
+    }
+
+    #[test]
+    fn test_padding_parsing() {
+        let config = r#"
+            padding = 12
+            config = {}
+            config.padding = { top = 2, left = 20 }
+        "#;
+
+        let temp_file = std::env::temp_dir().join("test_config_padding.lua");
+        std::fs::write(&temp_file, config).unwrap();
+        let (update, _) = parse_config(&temp_file).unwrap();
+        let _ = std::fs::remove_file(&temp_file);
+
+        // The later per-side assignment wins; unspecified sides keep the default
+        let default = crate::types::DEFAULT_PADDING;
+        assert_eq!(
+            update.padding,
+            Some(crate::types::Padding { top: 2.0, right: default, bottom: default, left: 20.0 })
+        );
+    }
+
+    #[test]
+    fn test_current_line_highlight_parsing() {
+        let parse = |name: &str, config: &str| {
+            let temp_file = std::env::temp_dir().join(name);
+            std::fs::write(&temp_file, config).unwrap();
+            let (update, _) = parse_config(&temp_file).unwrap();
+            let _ = std::fs::remove_file(&temp_file);
+            update.current_line_highlight
+        };
+
+        assert_eq!(
+            parse("test_config_highlight_on.lua", r##"current_line_highlight = "#FF0000""##),
+            Some(Some(crate::types::TerminalColor::RED))
+        );
+        assert_eq!(parse("test_config_highlight_off.lua", "current_line_highlight = false"), Some(None));
+        assert_eq!(parse("test_config_highlight_none.lua", r#"current_line_highlight = "none""#), Some(None));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let config = r#"
+            prompt = "say \"hi\"\t> "
+            window_title = 'it\'s \x41\065\u{3bb} caf\xC3\xA9 caf\195\169'
+            default_cwd = "C:\\Users\\axiom"
+            window_title_format = [[
+{mode} "raw" \n {title}]]
+            modes = {
+                { name = [[Normal]], bindings = { { key = 'g g', action = "say \"hi\"" } } },
+            }
+        "#;
+
+        let temp_file = std::env::temp_dir().join("test_config_string_escapes.lua");
+        std::fs::write(&temp_file, config).unwrap();
+        let (update, _) = parse_config(&temp_file).unwrap();
+        let _ = std::fs::remove_file(&temp_file);
+
+        assert_eq!(update.prompt.as_deref(), Some("say \"hi\"\t> "));
+        // Byte escapes spelling out UTF-8 decode to the character, not one char per byte
+        assert_eq!(update.window_title.as_deref(), Some("it's AA\u{3bb} café café"));
+        // Relative outside Windows, where it is resolved against the config's directory
+        assert!(update.default_cwd.unwrap().ends_with("C:\\Users\\axiom"));
+        assert_eq!(update.window_title_format.as_deref(), Some("{mode} \"raw\" \\n {title}"));
+        let modes = update.mode_definitions.unwrap();
+        assert_eq!(modes[0].mode, crate::types::TerminalMode::Normal);
+        assert_eq!(modes[0].bindings[0].target, crate::types::BindingTarget::Macro("say \"hi\"".to_string()));
+    }
+
+    #[test]
+    fn test_default_cwd_is_expanded_and_resolved() {
+        let dir = std::env::temp_dir().join("test_config_default_cwd");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.lua");
+        let parse = |value: &str| {
+            std::fs::write(&path, format!("default_cwd = {:?}\n", value)).unwrap();
+            parse_config(&path).unwrap().0.default_cwd.map(PathBuf::from)
+        };
+
+        assert_eq!(parse("projects/axiom"), Some(dir.join("projects/axiom")));
+        assert_eq!(parse("../elsewhere"), Some(dir.join("../elsewhere")));
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(parse("~"), Some(home.clone()));
+            assert_eq!(parse("~/projects"), Some(home.join("projects")));
+        }
+        if let Ok(home) = std::env::var("HOME") {
+            assert_eq!(parse("$HOME/projects"), Some(Path::new(&home).join("projects")));
+            assert_eq!(parse("${HOME}"), Some(PathBuf::from(&home)));
+        }
+        let absolute = std::env::temp_dir().join("axiom");
+        assert_eq!(parse(&absolute.to_string_lossy()), Some(absolute));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_unescape_keeps_unknown_escapes() {
+        assert_eq!(unescape_lua_string(r"C:\Users\me"), r"C:\Users\me");
+        assert_eq!(unescape_lua_string(r"a\z   b"), "ab");
+        assert_eq!(unescape_lua_string(r"trailing\"), r"trailing\");
+    }
+
+    #[test]
+    fn test_config_table_literals() {
+        let parse = |name: &str, config: &str| {
+            let temp_file = std::env::temp_dir().join(name);
+            std::fs::write(&temp_file, config).unwrap();
+            let (update, _) = parse_config(&temp_file).unwrap();
+            let _ = std::fs::remove_file(&temp_file);
+            update
+        };
+
+        let update = parse("test_config_local_table.lua", r#"
+            local config = {
+                prompt = "L> ",
+                font_size = 18,
+                modes = {
+                    { name = "Normal", bindings = { { key = "i", action = "ChangeMode(Insert)" } } },
+                },
+            }
+            config.window_title = "dotted"
+            return config
+        "#);
+        assert_eq!(update.prompt.as_deref(), Some("L> "));
+        assert_eq!(update.font_size, Some(18.0));
+        assert_eq!(update.window_title.as_deref(), Some("dotted"));
+        assert_eq!(update.mode_definitions.map(|m| m.len()), Some(1));
+
+        let update = parse("test_config_return_table.lua", r#"return { prompt = "R> " }"#);
+        assert_eq!(update.prompt.as_deref(), Some("R> "));
+    }
+
+    #[test]
+    fn test_on_startup_list_and_rc_file() {
+        let dir = std::env::temp_dir().join("test_config_on_startup");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.lua");
+
+        std::fs::write(&path, "on_startup = { \"export EDITOR=vim\", \"echo welcome\" }\n").unwrap();
+        let (update, _) = parse_config(&path).unwrap();
+        let commands = update.on_startup.unwrap().commands().unwrap();
+        assert_eq!(commands, vec!["export EDITOR=vim", "echo welcome"]);
+
+        // A path is read as an rc file next to the config
+        std::fs::write(dir.join("startup.rc"), "# banner\necho hi\n\n  cd /tmp  \n").unwrap();
+        std::fs::write(&path, "on_startup = \"startup.rc\"\n").unwrap();
+        let (update, _) = parse_config(&path).unwrap();
+        let startup = update.on_startup.unwrap();
+        assert_eq!(startup, StartupCommands::File(dir.join("startup.rc")));
+        assert_eq!(startup.commands().unwrap(), vec!["echo hi", "cd /tmp"]);
+    }
+
+    #[test]
+    fn test_bool_and_int_values() {
+        let code = "a = true\nb = false\nc = 0x10\nd = -4\ne = -0.5\nf = 12\ng = \"true\"";
+        let ast = full_moon::parse(code).unwrap();
+        let values: Vec<_> = ast.nodes().stmts().map(|stmt| match stmt {
+            full_moon::ast::Stmt::Assignment(assign) => assign.expressions().iter().next().unwrap().clone(),
+            _ => unreachable!(),
+        }).collect();
+
+        assert_eq!(extract_bool(&values[0]), Some(true));
+        assert_eq!(extract_bool(&values[1]), Some(false));
+        assert_eq!(extract_int(&values[2]), Some(16));
+        assert_eq!(extract_float(&values[2]), Some(16.0));
+        assert_eq!(extract_int(&values[3]), Some(-4));
+        assert_eq!(extract_float(&values[4]), Some(-0.5));
+        assert_eq!(extract_int(&values[4]), None);
+        assert_eq!(extract_int(&values[5]), Some(12));
+        assert_eq!(extract_bool(&values[6]), None);
+    }
+
+    #[test]
+    fn test_numeric_config_values() {
+        let path = std::env::temp_dir().join("test_config_numeric.lua");
+        std::fs::write(&path, "font_size = 0x10\npadding = -2\ncursor_color = 0xFF8800\ncurrent_line_highlight = false\nclickable_links = false\ncopy_on_select = true\nconfirm_destructive = true\n").unwrap();
+        let (update, _) = parse_config(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(update.font_size, Some(16.0));
+        assert_eq!(update.padding, Some(crate::types::Padding::uniform(0.0)));
+        assert_eq!(update.cursor_color, Some(TerminalColor::from_rgb(0xFF, 0x88, 0x00)));
+        assert_eq!(update.current_line_highlight, Some(None));
+        assert_eq!(update.clickable_links, Some(false));
+        assert_eq!(update.copy_on_select, Some(true));
+        assert_eq!(update.confirm_destructive, Some(true));
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let path = std::env::temp_dir().join("test_config_syntax_error.lua");
+        std::fs::write(&path, "prompt = \"> \"\nfont_size = = 14\n").unwrap();
+        let Err(err) = parse_config(&path) else { panic!("expected a syntax error") };
+        let _ = std::fs::remove_file(&path);
+
+        let err = err.downcast_ref::<ConfigParseError>().expect("syntax errors carry a position");
+        assert_eq!(err.line, 2);
+        assert_eq!(err.column, 11);
+        assert_eq!(err.source_line.as_deref(), Some("font_size = = 14"));
+
+        let report = err.report(Path::new("/home/user/.config/axiomterm/config.lua"));
+        assert!(report[0].starts_with("config.lua:2:11: "));
+        assert_eq!(report[1], "2 | font_size = = 14");
+        assert_eq!(report[2], "              ^");
+    }
+
+    #[test]
+    fn test_out_of_range_values_are_clamped_with_warnings() {
+        let path = std::env::temp_dir().join("test_config_validation.lua");
+        std::fs::write(&path, "window_background_opacity = 5.0\nfont_size = -3\ncursor_color = \"#GGHHII\"\nprompt = \"$ \"\n").unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+
+        // Counts never wrap a negative, fractional or NaN value around to 0
+        for value in ["-5", "0", "300.5", "0/0", "\"big\""] {
+            std::fs::write(&path, format!("max_input_length = {}\n", value)).unwrap();
+            let (update, warnings) = parse_config(&path).unwrap();
+            assert_eq!(update.max_input_length, None, "{}", value);
+            assert_eq!(warnings, vec![format!("Ignoring max_input_length: {} is not a whole number of at least 1", value)]);
+        }
+        std::fs::write(&path, "max_input_length = 10\n").unwrap();
+        let (small, small_warnings) = parse_config(&path).unwrap();
+        assert_eq!(small.max_input_length, Some(MIN_MAX_INPUT_LENGTH));
+        assert!(small_warnings[0].starts_with("max_input_length = 10 is out of range"));
+        for (value, expected) in [("-1", None), ("0", None), ("2.5", None), ("500", Some(Some(500))), ("false", Some(None))] {
+            std::fs::write(&path, format!("max_command_lines = {}\n", value)).unwrap();
+            let (update, warnings) = parse_config(&path).unwrap();
+            assert_eq!(update.max_command_lines, expected, "{}", value);
+            assert_eq!(warnings.len(), usize::from(expected.is_none()), "{}", value);
+        }
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(update.opacity, Some(1.0));
+        assert_eq!(update.font_size, Some(MIN_FONT_SIZE));
+        assert_eq!(update.cursor_color, None);
+        assert_eq!(update.prompt.as_deref(), Some("$ "));
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings.iter().any(|w| w.starts_with("window_background_opacity = 5")));
+        assert!(warnings.iter().any(|w| w.starts_with("font_size = -3")));
+        assert!(warnings.iter().any(|w| w.contains("cursor_color")));
+    }
+
+    #[test]
+    fn test_min_font_size_raises_display_size() {
+        let path = std::env::temp_dir().join("test_config_accessibility.lua");
+        std::fs::write(&path, "font_size = 10\nmin_font_size = 18\nhigh_contrast = true\n").unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(warnings.is_empty());
+
+        let mut state = crate::types::ShellState::for_test();
+        state.apply_config(update);
+        assert!(state.high_contrast);
+        assert_eq!(state.font_size, 10.0);
+        assert_eq!(state.display_font_size(), 18.0);
+    }
+
+    #[test]
+    fn test_no_color_setting_and_override() {
+        let path = std::env::temp_dir().join("test_config_no_color.lua");
+        std::fs::write(&path, "no_color = false\n").unwrap();
+        let (update, _) = parse_config(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(update.no_color, Some(false));
+
+        let mut state = crate::types::ShellState::for_test();
+        state.apply_config(ConfigUpdate { no_color: Some(true), ..Default::default() });
+        assert!(!state.color_enabled);
+        state.apply_config(update);
+        assert!(state.color_enabled);
+        // NO_COLOR / --no-color win over the config
+        state.force_no_color();
+        state.apply_config(ConfigUpdate { no_color: Some(false), ..Default::default() });
+        assert!(!state.color_enabled);
+    }
+
+    #[test]
+    fn test_scrollbar_settings() {
+        let path = std::env::temp_dir().join("test_config_scrollbar.lua");
+        std::fs::write(&path, "scrollbar = \"Hidden\"\nscrollbar_width = 100\nscrollbar_color = \"#336699\"\n").unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+        assert_eq!(update.scrollbar, Some(ScrollbarMode::Hidden));
+        assert_eq!(update.scrollbar_width, Some(32.0));
+        assert_eq!(update.scrollbar_color, Some(TerminalColor::from_rgb(0x33, 0x66, 0x99)));
+        assert_eq!(warnings.len(), 1);
+
+        std::fs::write(&path, "scrollbar = \"sometimes\"\n").unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+        assert_eq!(update.scrollbar, None);
+        assert!(warnings[0].contains("overlay, solid, always, hidden"));
+
+        std::fs::write(&path, "notifications = \"Inline\"\n").unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+        assert_eq!(update.notifications, Some(NotificationStyle::Inline));
+        assert!(warnings.is_empty());
+
+        std::fs::write(&path, "notifications = \"popup\"\n").unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+        assert_eq!(update.notifications, None);
+        assert!(warnings[0].contains("toast, inline"));
+
+        std::fs::write(&path, "multiline_paste = \"confirm\"\n").unwrap();
+        let (update, _) = parse_config(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(update.multiline_paste, Some(PasteMode::Confirm));
+    }
+
+    #[test]
+    fn test_ls_colors_table() {
+        let path = std::env::temp_dir().join("test_config_ls_colors.lua");
+        std::fs::write(
+            &path,
+            "colors = { accent = \"#FF8800\" }\nls_colors = { RS = \"#DEA584\", image = \"accent\", [\"*.tar.gz\"] = \"#FF0000\", code = \"nope\" }\n",
+        )
+        .unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let colors = update.ls_colors.unwrap();
+        assert_eq!(colors.len(), 3);
+        assert_eq!(colors["rs"], TerminalColor::from_rgb(0xDE, 0xA5, 0x84));
+        assert_eq!(colors["image"], TerminalColor::from_rgb(0xFF, 0x88, 0x00));
+        assert_eq!(colors["tar.gz"], TerminalColor::from_rgb(0xFF, 0x00, 0x00));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("ls_colors.code"));
+    }
+
+    #[test]
+    fn test_includes_merge_in_order() {
+        let dir = std::env::temp_dir().join("axiomterm_test_config_includes");
+        std::fs::create_dir_all(dir.join("machines")).unwrap();
+        std::fs::write(dir.join("colors.lua"), "prompt_color = \"#112233\"\ntext_color = \"#445566\"\n").unwrap();
+        // Includes resolve relative to the including file, and cycles back to the main file are skipped
+        std::fs::write(dir.join("machines/laptop.lua"), "font_size = 18\naxiom.include(\"../config.lua\")\n").unwrap();
+        std::fs::write(
+            dir.join("config.lua"),
+            "font_size = 12\nincludes = { \"colors.lua\", \"missing.lua\" }\ntext_color = \"#FFFFFF\"\naxiom.include(\"machines/laptop.lua\")\n",
+        ).unwrap();
+
+        let (update, warnings) = parse_config(&dir.join("config.lua")).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(update.prompt_color, Some(TerminalColor::from_rgb(0x11, 0x22, 0x33)));
+        assert_eq!(update.text_color, Some(TerminalColor::WHITE));
+        assert_eq!(update.font_size, Some(18.0));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("Include not found:") && warnings[0].ends_with("missing.lua"));
+        assert!(warnings[1].contains("include cycle"));
+    }
+
+    #[test]
+    fn test_palette_names_resolve_in_color_settings() {
+        let path = std::env::temp_dir().join("test_config_palette.lua");
+        std::fs::write(&path, r##"
+            colors = { accent = "#FF8800", muted = 0x808080, link = "accent" }
+            prompt_color = "accent"
+            text_color = "muted"
+            directory_color = "link"
+            cursor_color = "missing"
+        "##).unwrap();
+        let (update, warnings) = parse_config(&path).unwrap();
+
+        let accent = TerminalColor::from_rgb(0xFF, 0x88, 0x00);
+        assert_eq!(update.prompt_color, Some(accent));
+        assert_eq!(update.text_color, Some(TerminalColor::GRAY));
+        assert_eq!(update.directory_color, Some(accent));
+        assert_eq!(update.cursor_color, None);
+        assert_eq!(update.palette.as_ref().map(|p| p.len()), Some(3));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("cursor_color") && warnings[0].contains("colors"));
+
+        // Themes see the palette already on the shell state
+        std::fs::write(&path, "cursor_color = \"accent\"\n").unwrap();
+        let (update, _) = parse_config_with_palette(&path, update.palette.as_ref().unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(update.cursor_color, Some(accent));
+    }
 }
@@ -9,6 +9,8 @@ pub struct FixedConfig {
     pub security: SecurityConfig,
     #[serde(default)]
     pub window: WindowConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +21,9 @@ pub struct CoreConfig {
     pub renderer: String,
     #[serde(default = "default_initial_mode")]
     pub initial_mode: String,
+    /// Log render invalidation and macro resolution to stderr
+    #[serde(default = "default_false")]
+    pub debug_render: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +44,25 @@ pub struct WindowConfig {
     pub transparent: bool,
 }
 
+/// SSH target for the `"remote"` backend
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    /// Private key passed to `ssh -i`; `~/` is expanded
+    #[serde(default)]
+    pub key_path: Option<String>,
+}
+
+// Window size bounds; anything outside is clamped at startup
+const MIN_WINDOW_WIDTH: u32 = 320;
+const MIN_WINDOW_HEIGHT: u32 = 200;
+const MAX_WINDOW_DIMENSION: u32 = 16384;
+
 // Default functions
 fn default_backend() -> String { "std".to_string() }
 fn default_renderer() -> String { "egui".to_string() }
@@ -47,6 +71,7 @@ fn default_false() -> bool { false }
 fn default_true() -> bool { true }
 fn default_width() -> u32 { 800 }
 fn default_height() -> u32 { 600 }
+fn default_ssh_port() -> u16 { 22 }
 
 impl Default for CoreConfig {
     fn default() -> Self {
@@ -54,6 +79,7 @@ impl Default for CoreConfig {
             backend: default_backend(),
             renderer: default_renderer(),
             initial_mode: default_initial_mode(),
+            debug_render: default_false(),
         }
     }
 }
@@ -77,12 +103,23 @@ impl Default for WindowConfig {
     }
 }
 
+impl WindowConfig {
+    /// Initial inner size, clamped so a bad config can't create an unusable window
+    pub fn clamped_size(&self) -> [f32; 2] {
+        [
+            self.initial_width.clamp(MIN_WINDOW_WIDTH, MAX_WINDOW_DIMENSION) as f32,
+            self.initial_height.clamp(MIN_WINDOW_HEIGHT, MAX_WINDOW_DIMENSION) as f32,
+        ]
+    }
+}
+
 impl Default for FixedConfig {
     fn default() -> Self {
         Self {
             core: CoreConfig::default(),
             security: SecurityConfig::default(),
             window: WindowConfig::default(),
+            remote: RemoteConfig::default(),
         }
     }
 }
@@ -142,7 +179,11 @@ impl FixedConfig {
         match self.core.backend.as_str() {
             "std" => {},
             "wasm" => return Err("WASM backend not yet implemented".to_string()),
-            "remote" => return Err("Remote backend not yet implemented".to_string()),
+            "remote" => {
+                if self.remote.host.trim().is_empty() {
+                    return Err("Remote backend requires [remote] host".to_string());
+                }
+            }
             other => return Err(format!("Unknown backend: {}", other)),
         }
 
@@ -173,6 +214,7 @@ mod tests {
         assert_eq!(config.core.backend, "std");
         assert_eq!(config.core.renderer, "egui");
         assert_eq!(config.core.initial_mode, "insert");
+        assert!(!config.core.debug_render);
         assert_eq!(config.security.lua_allow_io, false);
         assert_eq!(config.security.lua_allow_network, false);
         assert_eq!(config.window.initial_width, 800);
@@ -193,6 +235,43 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_remote_backend_requires_host() {
+        let toml_str = r#"
+[core]
+backend = "remote"
+"#;
+        let config: FixedConfig = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_err());
+
+        let toml_str = r#"
+[core]
+backend = "remote"
+
+[remote]
+host = "example.com"
+user = "axiom"
+key_path = "~/.ssh/id_ed25519"
+"#;
+        let config: FixedConfig = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.remote.port, 22);
+        assert_eq!(config.remote.user.as_deref(), Some("axiom"));
+    }
+
+    #[test]
+    fn test_window_size_clamped() {
+        let mut config = FixedConfig::default();
+        assert_eq!(config.window.clamped_size(), [800.0, 600.0]);
+
+        config.window.initial_width = 0;
+        config.window.initial_height = 1_000_000;
+        assert_eq!(
+            config.window.clamped_size(),
+            [MIN_WINDOW_WIDTH as f32, MAX_WINDOW_DIMENSION as f32]
+        );
+    }
+
     #[test]
     fn test_toml_parsing() {
         let toml_str = r#"
@@ -1,63 +1,426 @@
 use eframe::egui;
-use crate::types::{Action, BindingTarget, InputEvent, ModeDefinition, TerminalMode};
-
-pub fn poll_and_map(ctx: &egui::Context, current_mode: &TerminalMode, definitions: &[ModeDefinition]) -> Vec<BindingTarget> {
-    let mut targets = Vec::new();
-    let mut events = Vec::new();
-
-    // 1. Capture raw egui events and convert to InputEvents
-    ctx.input(|i| {
-        for event in &i.events {
-            match event {
-                egui::Event::Key { key, pressed: true, modifiers, .. } => {
-                    events.push(InputEvent::Key {
-                        code: format!("{:?}", key),
-                        ctrl: modifiers.command, // command maps to ctrl on Windows/Linux, cmd on Mac
-                        alt: modifiers.alt,
-                        shift: modifiers.shift,
-                    });
-                }
-                egui::Event::Text(text) => {
-                    if !text.is_empty() {
+use crate::types::{Action, BindingTarget, InputEvent, ModeDefinition, TerminalMode, DEFAULT_KEY_REPEAT_DELAY, DEFAULT_KEY_REPEAT_RATE};
+use std::time::{Duration, Instant};
+
+/// How long a partial key sequence waits for its next key before being discarded.
+pub const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(600);
+
+// Human-friendly key names (lowercase) that egui doesn't recognize on its own
+const KEY_ALIASES: &[(&str, &str)] = &[
+    ("esc", "Escape"),
+    ("cr", "Enter"),
+    ("ret", "Enter"),
+    ("bs", "Backspace"),
+    ("del", "Delete"),
+    ("ins", "Insert"),
+    ("spc", "Space"),
+    ("pgup", "PageUp"),
+    ("pgdn", "PageDown"),
+    ("pgdown", "PageDown"),
+    ("minus", "Minus"),
+    ("equal", "Equals"),
+];
+
+/// Normalizes a key name to egui's canonical `{:?}` naming (e.g. `"esc"` -> `"Escape"`, `"l"` -> `"L"`).
+/// Used for both config bindings and polled events so the two always compare equal.
+/// Unknown names are passed through unchanged.
+pub fn normalize_key_code(code: &str) -> String {
+    let lower = code.to_ascii_lowercase();
+    if let Some((_, canonical)) = KEY_ALIASES.iter().find(|(alias, _)| *alias == lower) {
+        return canonical.to_string();
+    }
+
+    egui::Key::from_name(code)
+        .or_else(|| {
+            egui::Key::ALL.iter().copied().find(|key| {
+                key.name().eq_ignore_ascii_case(code) || format!("{:?}", key).eq_ignore_ascii_case(code)
+            })
+        })
+        .map(|key| format!("{:?}", key))
+        .unwrap_or_else(|| code.to_string())
+}
+
+/// Maps raw input to binding targets, holding partially typed key sequences between frames.
+pub struct InputMapper {
+    pending: Vec<InputEvent>,
+    last_key_at: Option<Instant>,
+    timeout: Duration,
+    held: Option<HeldKey>,
+    repeat_delay: Duration,
+    repeat_interval: Duration,
+}
+
+impl Default for InputMapper {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            last_key_at: None,
+            timeout: SEQUENCE_TIMEOUT,
+            held: None,
+            repeat_delay: DEFAULT_KEY_REPEAT_DELAY,
+            repeat_interval: Duration::from_secs_f32(1.0 / DEFAULT_KEY_REPEAT_RATE),
+        }
+    }
+}
+
+/// The most recently pressed key while it stays down. OS auto-repeat is ignored in favor of
+/// repeating this at the configured delay and rate.
+struct HeldKey {
+    key: egui::Key,
+    event: InputEvent,
+    pressed_at: Instant,
+    repeats_sent: u32,
+}
+
+impl HeldKey {
+    /// Repeats that have come due by `now` and not been sent yet.
+    fn due_repeats(&self, now: Instant, delay: Duration, interval: Duration) -> u32 {
+        let Some(held_for) = now.checked_duration_since(self.pressed_at + delay) else {
+            return 0;
+        };
+        let due = (held_for.as_secs_f64() / interval.as_secs_f64().max(f64::EPSILON)) as u32 + 1;
+        due.saturating_sub(self.repeats_sent)
+    }
+
+    /// When the next repeat comes due.
+    fn next_repeat_at(&self, delay: Duration, interval: Duration) -> Instant {
+        self.pressed_at + delay + interval * self.repeats_sent
+    }
+}
+
+enum SequenceMatch<'a> {
+    Complete(&'a BindingTarget),
+    Prefix,
+    None,
+}
+
+impl InputMapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies the `key_repeat_delay` / `key_repeat_rate` settings.
+    pub fn set_key_repeat(&mut self, delay: Duration, rate: f32) {
+        self.repeat_delay = delay;
+        self.repeat_interval = Duration::from_secs_f32(1.0 / rate.max(1.0));
+    }
+
+    /// Reads this frame's key and text events and maps them to binding targets.
+    /// While `searching` (reverse history search), editing keys go to the search first.
+    pub fn poll_and_map(&mut self, ctx: &egui::Context, current_mode: &TerminalMode, definitions: &[ModeDefinition], searching: bool) -> Vec<BindingTarget> {
+        let mut events = Vec::new();
+        let mut pasted = Vec::new();
+
+        // 1. Capture raw egui events and convert to InputEvents
+        let now = Instant::now();
+        ctx.input(|i| {
+            for event in &i.events {
+                match event {
+                    egui::Event::Key { key, pressed: true, repeat: false, modifiers, .. } => {
+                        let event = InputEvent::Key {
+                            code: normalize_key_code(&format!("{:?}", key)),
+                            ctrl: modifiers.command, // command maps to ctrl on Windows/Linux, cmd on Mac
+                            alt: modifiers.alt,
+                            shift: modifiers.shift,
+                        };
+                        self.held = Some(HeldKey { key: *key, event: event.clone(), pressed_at: now, repeats_sent: 0 });
+                        events.push(event);
+                    }
+                    egui::Event::Text(text) if !text.is_empty() => {
                         events.push(InputEvent::Text(text.clone()));
                     }
+                    // egui turns Ctrl+C into Copy before it becomes a key event; bring the key back so it can be bound
+                    egui::Event::Copy => {
+                        events.push(InputEvent::Key { code: "C".to_string(), ctrl: true, alt: false, shift: false });
+                    }
+                    // Several lines go to the shell whole, so none of them runs without its own
+                    // Enter (see `multiline_paste`). Otherwise the line break of a single line, or
+                    // any pasted into a search query, becomes a space.
+                    egui::Event::Paste(text) if !text.is_empty() => {
+                        let lines = text.lines().filter(|line| !line.trim().is_empty()).count();
+                        if lines > 1 && !searching && *current_mode == TerminalMode::Insert {
+                            pasted.push(text.clone());
+                        } else {
+                            events.push(InputEvent::Text(text.lines().collect::<Vec<_>>().join(" ")));
+                        }
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
+        });
+
+        // A pending sequence may need to wake us up to expire
+        if !self.pending.is_empty() {
+            ctx.request_repaint_after(self.timeout);
+        }
+
+        // Repeat a held key, but only one bound on its own; repeating a sequence prefix such
+        // as the first `g` of `gg` would fire the sequence instead
+        if self.held.as_ref().is_some_and(|held| !ctx.input(|i| i.key_down(held.key))) {
+            self.held = None;
+        }
+        if let Some(held) = &mut self.held {
+            let repeatable = definitions.iter().find(|d| d.mode == *current_mode).is_some_and(|def| {
+                matches!(Self::match_sequence(def, std::slice::from_ref(&held.event), false), SequenceMatch::Complete(_))
+            });
+            if repeatable {
+                let due = held.due_repeats(now, self.repeat_delay, self.repeat_interval);
+                events.extend(std::iter::repeat_n(held.event.clone(), due as usize));
+                held.repeats_sent += due;
+                let next = held.next_repeat_at(self.repeat_delay, self.repeat_interval);
+                ctx.request_repaint_after(next.saturating_duration_since(now));
+            }
+        }
+
+        // 2. Map InputEvents to BindingTargets
+        let mut targets = Vec::new();
+        if searching {
+            events = Self::take_search_events(events, &mut targets);
+        }
+        targets.extend(self.map_events(events, now, current_mode, definitions));
+        targets.extend(pasted.into_iter().map(|text| BindingTarget::Action(Action::Paste(text))));
+        targets
+    }
+
+    /// Turns the events a history search owns (text, Backspace, Enter, Escape) into search
+    /// actions in any mode, returning the rest for the mode bindings (e.g. Ctrl+R to step back).
+    fn take_search_events(events: Vec<InputEvent>, targets: &mut Vec<BindingTarget>) -> Vec<InputEvent> {
+        let mut rest = Vec::new();
+        for event in events {
+            let action = match &event {
+                InputEvent::Text(text) => {
+                    targets.extend(text.chars().map(|ch| BindingTarget::Action(Action::AppendChar(ch))));
+                    continue;
+                }
+                InputEvent::Key { code, ctrl: false, alt: false, shift: false } => match code.as_str() {
+                    "Backspace" => Some(Action::Backspace),
+                    "Enter" => Some(Action::AcceptSearch),
+                    "Escape" => Some(Action::CancelSearch),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match action {
+                Some(action) => targets.push(BindingTarget::Action(action)),
+                None => rest.push(event),
+            }
+        }
+        rest
+    }
+
+    pub fn map_events(&mut self, events: Vec<InputEvent>, now: Instant, current_mode: &TerminalMode, definitions: &[ModeDefinition]) -> Vec<BindingTarget> {
+        let mut targets = Vec::new();
+        let Some(def) = definitions.iter().find(|d| d.mode == *current_mode) else {
+            self.pending.clear();
+            return targets;
+        };
+
+        // Resolve or drop a sequence whose follow-up key never arrived
+        if let Some(last) = self.last_key_at
+            && now.duration_since(last) >= self.timeout
+            && !self.pending.is_empty()
+        {
+            if let SequenceMatch::Complete(target) = Self::match_sequence(def, &self.pending, true) {
+                targets.push(target.clone());
+            }
+            self.pending.clear();
         }
-    });
-
-    // 2. Map InputEvents to BindingTargets
-    for event in events {
-        if let Some(def) = definitions.iter().find(|d| d.mode == *current_mode) {
-            for binding in &def.bindings {
-                if binding.event == event {
-                    // Prevent duplicate processing in Insert mode where TextEdit is active
-                    if *current_mode == TerminalMode::Insert {
-                        match &binding.target {
-                            BindingTarget::Action(action) => {
-                                match action {
-                                    Action::Backspace | Action::Delete | Action::MoveCursor(_, _) => {
-                                        // These are handled by TextEdit
-                                    },
-                                    _ => {
-                                        targets.push(binding.target.clone());
-                                    }
-                                }
-                            },
-                            BindingTarget::Macro(_) => {
-                                // Macros are always allowed in Insert mode (for now)
-                                targets.push(binding.target.clone());
-                            }
+
+        for event in events {
+            // Text events never take part in sequences; they only match standalone bindings.
+            // Unbound text is typed into the input line while inserting.
+            if let InputEvent::Text(text) = &event {
+                if let Some(binding) = def.bindings.iter().find(|b| b.event == event) {
+                    targets.push(binding.target.clone());
+                } else if *current_mode == TerminalMode::Insert {
+                    targets.extend(text.chars().map(|ch| BindingTarget::Action(Action::AppendChar(ch))));
+                }
+                continue;
+            }
+
+            self.pending.push(event);
+            self.last_key_at = Some(now);
+
+            loop {
+                match Self::match_sequence(def, &self.pending, false) {
+                    SequenceMatch::Complete(target) => {
+                        targets.push(target.clone());
+                        self.pending.clear();
+                    }
+                    SequenceMatch::Prefix => {}
+                    SequenceMatch::None => {
+                        // Retry the newest key on its own so a stray prefix doesn't swallow it
+                        if self.pending.len() > 1 {
+                            self.pending.drain(..self.pending.len() - 1);
+                            continue;
                         }
-                    } else {
-                        targets.push(binding.target.clone());
+                        self.pending.clear();
                     }
-                    break; 
                 }
+                break;
             }
         }
+
+        targets
+    }
+
+    fn match_sequence<'a>(def: &'a ModeDefinition, pending: &[InputEvent], expired: bool) -> SequenceMatch<'a> {
+        let mut exact = None;
+        let mut is_prefix = false;
+
+        for binding in &def.bindings {
+            let sequence: &[InputEvent] = match &binding.event {
+                InputEvent::Sequence(seq) => seq,
+                single => std::slice::from_ref(single),
+            };
+            if sequence == pending {
+                exact.get_or_insert(&binding.target);
+            } else if sequence.len() > pending.len() && sequence.starts_with(pending) {
+                is_prefix = true;
+            }
+        }
+
+        // A longer binding sharing this prefix keeps us waiting until it times out
+        match (exact, is_prefix && !expired) {
+            (_, true) => SequenceMatch::Prefix,
+            (Some(target), false) => SequenceMatch::Complete(target),
+            (None, false) => SequenceMatch::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{KeyBinding, ModeAppearance};
+
+    fn key(code: &str) -> InputEvent {
+        InputEvent::Key { code: code.to_string(), ctrl: false, alt: false, shift: false }
+    }
+
+    fn normal_mode() -> Vec<ModeDefinition> {
+        vec![ModeDefinition {
+            mode: TerminalMode::Normal,
+            bindings: vec![
+                KeyBinding { event: InputEvent::Sequence(vec![key("G"), key("G")]), target: BindingTarget::Action(Action::Clear) },
+                KeyBinding { event: key("I"), target: BindingTarget::Action(Action::ChangeMode(TerminalMode::Insert)) },
+            ],
+            appearance: ModeAppearance::default(),
+        }]
+    }
+
+    #[test]
+    fn test_normalize_key_code() {
+        let cases = [
+            ("i", "I"),
+            ("I", "I"),
+            ("esc", "Escape"),
+            ("ESC", "Escape"),
+            ("escape", "Escape"),
+            ("enter", "Enter"),
+            ("Return", "Enter"),
+            ("tab", "Tab"),
+            ("space", "Space"),
+            ("up", "ArrowUp"),
+            ("arrowleft", "ArrowLeft"),
+            ("pgup", "PageUp"),
+            ("f5", "F5"),
+            ("1", "Num1"),
+            ("-", "Minus"),
+            ("NoSuchKey", "NoSuchKey"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_key_code(input), expected, "normalizing {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_sequence_completes() {
+        let defs = normal_mode();
+        let mut mapper = InputMapper::new();
+        let now = Instant::now();
+
+        let targets = mapper.map_events(vec![key("G")], now, &TerminalMode::Normal, &defs);
+        assert!(targets.is_empty());
+
+        let targets = mapper.map_events(vec![key("G")], now + Duration::from_millis(100), &TerminalMode::Normal, &defs);
+        assert_eq!(targets, vec![BindingTarget::Action(Action::Clear)]);
+    }
+
+    #[test]
+    fn test_sequence_times_out() {
+        let defs = normal_mode();
+        let mut mapper = InputMapper::new();
+        let now = Instant::now();
+
+        mapper.map_events(vec![key("G")], now, &TerminalMode::Normal, &defs);
+        let targets = mapper.map_events(vec![key("G")], now + SEQUENCE_TIMEOUT, &TerminalMode::Normal, &defs);
+        assert!(targets.is_empty());
     }
 
-    targets
+    #[test]
+    fn test_unmatched_prefix_falls_through() {
+        let defs = normal_mode();
+        let mut mapper = InputMapper::new();
+        let now = Instant::now();
+
+        mapper.map_events(vec![key("G")], now, &TerminalMode::Normal, &defs);
+        let targets = mapper.map_events(vec![key("I")], now, &TerminalMode::Normal, &defs);
+        assert_eq!(targets, vec![BindingTarget::Action(Action::ChangeMode(TerminalMode::Insert))]);
+    }
+
+    #[test]
+    fn test_text_types_into_input_only_in_insert_mode() {
+        let mut defs = normal_mode();
+        defs.push(ModeDefinition {
+            mode: TerminalMode::Insert,
+            bindings: vec![KeyBinding { event: key("Backspace"), target: BindingTarget::Action(Action::Backspace) }],
+            appearance: ModeAppearance::default(),
+        });
+        let mut mapper = InputMapper::new();
+        let now = Instant::now();
+
+        let events = vec![InputEvent::Text("hé".to_string()), key("Backspace")];
+        let targets = mapper.map_events(events.clone(), now, &TerminalMode::Insert, &defs);
+        assert_eq!(targets, vec![
+            BindingTarget::Action(Action::AppendChar('h')),
+            BindingTarget::Action(Action::AppendChar('é')),
+            BindingTarget::Action(Action::Backspace),
+        ]);
+
+        let targets = mapper.map_events(events, now, &TerminalMode::Normal, &defs);
+        assert!(targets.is_empty());
+    }
+
+    #[test]
+    fn test_search_takes_editing_keys() {
+        let ctrl_r = InputEvent::Key { code: "R".to_string(), ctrl: true, alt: false, shift: false };
+        let events = vec![InputEvent::Text("ls".to_string()), key("Backspace"), ctrl_r.clone(), key("Enter")];
+        let mut targets = Vec::new();
+        let rest = InputMapper::take_search_events(events, &mut targets);
+
+        assert_eq!(targets, vec![
+            BindingTarget::Action(Action::AppendChar('l')),
+            BindingTarget::Action(Action::AppendChar('s')),
+            BindingTarget::Action(Action::Backspace),
+            BindingTarget::Action(Action::AcceptSearch),
+        ]);
+        assert_eq!(rest, vec![ctrl_r]);
+    }
+
+    #[test]
+    fn test_held_key_repeat_schedule() {
+        let start = Instant::now();
+        let delay = Duration::from_millis(500);
+        let interval = Duration::from_millis(100);
+        let mut held = HeldKey { key: egui::Key::J, event: key("J"), pressed_at: start, repeats_sent: 0 };
+
+        assert_eq!(held.due_repeats(start + Duration::from_millis(499), delay, interval), 0);
+        assert_eq!(held.due_repeats(start + delay, delay, interval), 1);
+        assert_eq!(held.due_repeats(start + Duration::from_millis(750), delay, interval), 3);
+
+        held.repeats_sent = 3;
+        assert_eq!(held.due_repeats(start + Duration::from_millis(750), delay, interval), 0);
+        assert_eq!(held.next_repeat_at(delay, interval), start + Duration::from_millis(800));
+    }
 }
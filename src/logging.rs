@@ -0,0 +1,32 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Minimal stderr logger behind the `log` facade.
+/// Warnings and errors are always shown; axiomterm's own debug output (render
+/// invalidation, macro resolution) only when `core.debug_render` is enabled.
+struct StderrLogger {
+    debug_render: bool,
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Dependencies (eframe, winit, ...) stay at warn so debug mode isn't drowned out
+        let ours = metadata.target().starts_with(env!("CARGO_CRATE_NAME"));
+        metadata.level() <= Level::Warn || (self.debug_render && ours)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}: [{}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger. Safe to call more than once; later calls are ignored.
+pub fn init(debug_render: bool) {
+    let max_level = if debug_render { LevelFilter::Debug } else { LevelFilter::Warn };
+    if log::set_boxed_logger(Box::new(StderrLogger { debug_render })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
@@ -39,6 +39,20 @@ pub struct MacroInvocation {
     pub last_error: Option<MacroError>,
 }
 
+impl MacroInvocation {
+    /// Human-readable summary for `macro stats`.
+    pub fn report_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{}: {} invocation(s), {} action(s) emitted, max {} per call",
+            self.macro_name, self.total_invocations, self.total_actions_emitted, self.max_actions_emitted
+        )];
+        if let Some(error) = &self.last_error {
+            lines.push(format!("  last error: {}", error));
+        }
+        lines
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct MacroMetrics {
     invocations: std::collections::HashMap<String, MacroInvocation>,
@@ -92,7 +106,8 @@ impl MacroMetrics {
 
 pub struct LuaEngine {
     lua: Lua,
-    pub(crate) metrics: std::sync::Mutex<MacroMetrics>,
+    // Shared with the shell state so `macro stats` can read it from the shell thread
+    pub(crate) metrics: std::sync::Arc<std::sync::Mutex<MacroMetrics>>,
 }
 
 impl LuaEngine {
@@ -109,18 +124,48 @@ impl LuaEngine {
 
         Self { 
             lua,
-            metrics: std::sync::Mutex::new(MacroMetrics::new()),
+            metrics: std::sync::Arc::new(std::sync::Mutex::new(MacroMetrics::new())),
         }
     }
 
     pub fn load_config(&self, path: &Path) -> Result<()> {
         if path.exists() {
+            self.install_include(path)?;
             let code = std::fs::read_to_string(path).map_err(mlua::Error::external)?;
-            self.lua.load(&code).exec()?;
+            self.lua.load(&code).set_name(path.to_string_lossy()).exec()?;
         }
         Ok(())
     }
 
+    /// Defines `axiom.include(file)`, which runs another config file (so macros defined there
+    /// are registered). Paths resolve relative to the including file; missing files and files
+    /// already being loaded are skipped, matching the static config parser.
+    fn install_include(&self, config_path: &Path) -> Result<()> {
+        let root = config_path.canonicalize().unwrap_or_else(|_| config_path.to_path_buf());
+        // Files currently executing, innermost last; relative includes resolve against the last one
+        let open_files = std::sync::Arc::new(std::sync::Mutex::new(vec![root]));
+
+        let include = self.lua.create_function(move |lua, name: String| {
+            let path = {
+                let open = open_files.lock().unwrap();
+                let dir = open.last().and_then(|p| p.parent()).map(Path::to_path_buf).unwrap_or_default();
+                match dir.join(crate::utils::expand_home(&name)).canonicalize() {
+                    Ok(path) if !open.contains(&path) => path,
+                    _ => return Ok(()),
+                }
+            };
+            let code = std::fs::read_to_string(&path).map_err(mlua::Error::external)?;
+
+            open_files.lock().unwrap().push(path.clone());
+            let result = lua.load(&code).set_name(path.to_string_lossy()).exec();
+            open_files.lock().unwrap().pop();
+            result
+        })?;
+
+        let axiom = self.lua.globals().get::<Table>("axiom")?;
+        axiom.set("include", include)
+    }
+
     pub fn resolve_macro(&self, name: &str) -> std::result::Result<Vec<Action>, MacroError> {
         let result = self.resolve_macro_internal(name);
         
@@ -174,24 +219,30 @@ impl LuaEngine {
         
         for pair in table.pairs::<Value, Value>() {
             if let Ok((_k, v)) = pair {
-                if let Value::String(s) = v {
-                    if let Ok(s_str) = s.to_str() {
-                        if actions.len() >= MAX_MACRO_ACTIONS {
-                            return Err(MacroError::ActionLimitExceeded {
-                                macro_name: macro_name.to_string(),
-                                limit: MAX_MACRO_ACTIONS,
-                            });
-                        }
-                        
-                        match Action::from_str(&s_str) {
-                            Some(action) => actions.push(action),
-                            None => {
-                                return Err(MacroError::ActionParseError {
-                                    macro_name: macro_name.to_string(),
-                                    value: s_str.to_string(),
-                                });
-                            }
-                        }
+                // Each entry is an action string or a table; the `Err` holds what failed to parse
+                let parsed = match v {
+                    Value::String(s) => match s.to_str() {
+                        Ok(s_str) => Action::from_str(&s_str).ok_or_else(|| s_str.to_string()),
+                        Err(_) => continue,
+                    },
+                    Value::Table(t) => parse_action_entry(&t),
+                    _ => continue,
+                };
+
+                if actions.len() >= MAX_MACRO_ACTIONS {
+                    return Err(MacroError::ActionLimitExceeded {
+                        macro_name: macro_name.to_string(),
+                        limit: MAX_MACRO_ACTIONS,
+                    });
+                }
+
+                match parsed {
+                    Ok(action) => actions.push(action),
+                    Err(value) => {
+                        return Err(MacroError::ActionParseError {
+                            macro_name: macro_name.to_string(),
+                            value,
+                        });
                     }
                 }
             }
@@ -241,6 +292,35 @@ impl LuaEngine {
     }
 }
 
+/// Keys a `{ type = ..., ... }` action entry may carry its argument under.
+const ACTION_ARG_KEYS: [&str; 4] = ["arg", "char", "command", "mode"];
+
+/// Reads a table action entry: positional `{ "RunCommand", "git status" }` or keyed
+/// `{ type = "AppendChar", char = "x" }`. On failure returns the entry as text for the error.
+fn parse_action_entry(entry: &Table) -> std::result::Result<Action, String> {
+    let text = |value: Value| match value {
+        Value::String(s) => s.to_str().ok().map(|s| s.to_string()),
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    };
+
+    let (name, arg) = match entry.get::<Value>("type") {
+        Ok(Value::Nil) | Err(_) => (entry.get::<Value>(1).ok().and_then(text), entry.get::<Value>(2).ok().and_then(text)),
+        Ok(name) => (
+            text(name),
+            ACTION_ARG_KEYS.iter().find_map(|key| entry.get::<Value>(*key).ok().and_then(text)),
+        ),
+    };
+
+    let action = name.as_deref().and_then(|name| Action::from_parts(name, arg.as_deref()));
+    action.ok_or_else(|| match (name, arg) {
+        (Some(name), Some(arg)) => format!("{{ {:?}, {:?} }}", name, arg),
+        (Some(name), None) => format!("{{ {:?} }}", name),
+        (None, _) => "{ <no action name> }".to_string(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -264,6 +344,67 @@ mod tests {
         assert_eq!(actions[1], Action::Submit);
     }
 
+    #[test]
+    fn test_macro_tab_and_pane_actions() {
+        let engine = LuaEngine::new();
+        let script = r#"
+            axiom.macros.workspace = function()
+                return { "NewTab", "SplitPane", "FocusPrevPane", "SelectTab(2)", { "SelectTab", "1" }, "CloseTab" }
+            end
+        "#;
+        engine.lua.load(script).exec().expect("Failed to define macros");
+
+        assert_eq!(
+            engine.resolve_macro("workspace").unwrap(),
+            vec![
+                Action::NewTab,
+                Action::SplitPane,
+                Action::FocusPrevPane,
+                Action::SelectTab(2),
+                Action::SelectTab(1),
+                Action::CloseTab,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macro_table_actions() {
+        let engine = LuaEngine::new();
+        let script = r#"
+            axiom.macros.structured = function()
+                return {
+                    { "RunCommand", "echo (done)" },
+                    { type = "AppendChar", char = "x" },
+                    { type = "ChangeMode", mode = "Normal" },
+                    { "Submit" },
+                    "InsertChar(y)",
+                }
+            end
+            axiom.macros.bad_char = function() return { { type = "AppendChar", char = "xy" } } end
+            axiom.macros.unknown = function() return { { "Explode", "now" } } end
+        "#;
+        engine.lua.load(script).exec().expect("Failed to define macros");
+
+        assert_eq!(
+            engine.resolve_macro("structured").unwrap(),
+            vec![
+                Action::RunCommand("echo (done)".to_string()),
+                Action::AppendChar('x'),
+                Action::ChangeMode(crate::types::TerminalMode::Normal),
+                Action::Submit,
+                Action::AppendChar('y'),
+            ]
+        );
+        match engine.resolve_macro("bad_char") {
+            Err(MacroError::ActionParseError { value, .. }) => assert_eq!(value, r#"{ "AppendChar", "xy" }"#),
+            other => panic!("Expected ActionParseError, got {:?}", other),
+        }
+        match engine.resolve_macro("unknown") {
+            Err(MacroError::ActionParseError { value, .. }) => assert_eq!(value, r#"{ "Explode", "now" }"#),
+            other => panic!("Expected ActionParseError, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_macro_not_found() {
         let engine = LuaEngine::new();
@@ -347,4 +488,19 @@ mod tests {
         assert_eq!(invocation.total_actions_emitted, 0);
         assert!(invocation.last_error.is_some());
     }
+
+    #[test]
+    fn test_include_loads_macros_relative_to_config() {
+        let dir = std::env::temp_dir().join("axiomterm_test_lua_include");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("macros.lua"), "axiom.macros.included = function() return { \"Submit\" } end\naxiom.include(\"config.lua\")\n").unwrap();
+        std::fs::write(dir.join("config.lua"), "axiom.include(\"macros.lua\")\naxiom.include(\"missing.lua\")\n").unwrap();
+
+        let engine = LuaEngine::new();
+        let result = engine.load_config(&dir.join("config.lua"));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(result.is_ok());
+        assert_eq!(engine.resolve_macro("included").unwrap(), vec![Action::Submit]);
+    }
 }
@@ -1,3 +1,4 @@
+mod ansi;
 mod app;
 mod config;
 mod shell;
@@ -8,6 +9,9 @@ mod renderer;
 mod input;
 mod lua_bridge;
 mod fixed_config;
+mod logging;
+mod pane;
+mod session;
 
 use crate::app::TerminalApp;
 use crate::fixed_config::FixedConfig;
@@ -20,21 +24,40 @@ fn main() -> eframe::Result<()> {
     let fixed_config = FixedConfig::load()
         .expect("FATAL: Failed to load fixed configuration (terminal.toml)");
     
-    // Validate FixedConfig
+    // Validate FixedConfig before anything is built from it
     if let Err(e) = fixed_config.validate() {
-        panic!("FATAL: Invalid fixed configuration: {}", e);
+        eprintln!("FATAL: Invalid fixed configuration (terminal.toml): {}", e);
+        std::process::exit(1);
     }
 
+    logging::init(fixed_config.core.debug_render);
+
+    let cli = utils::CliArgs::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("axiomterm: {}", e);
+        eprintln!("Usage: axiomterm [--cwd <dir>] [--batch | --exec <command>]");
+        std::process::exit(2);
+    });
+
     // Initialize Backend based on FixedConfig
-    // Currently only StdBackend is supported
-    let backend = Box::new(backend::StdBackend);
+    let backend = backend::make_backend(&fixed_config).unwrap_or_else(|e| {
+        eprintln!("FATAL: {}", e);
+        std::process::exit(1);
+    });
+
+    if let Some(command) = &cli.exec {
+        std::process::exit(run_headless(std::slice::from_ref(command), backend, &fixed_config, &cli));
+    }
+    if cli.batch {
+        std::process::exit(run_batch(backend, &fixed_config, &cli));
+    }
 
     // Initialize Renderer based on FixedConfig
     // Currently only egui is supported
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([fixed_config.window.initial_width as f32, fixed_config.window.initial_height as f32])
-            .with_title(&format!("[INSERT] axiomterm"))
+            .with_inner_size(fixed_config.window.clamped_size())
+            // The full title is sent from the shell state on the first frame
+            .with_title("axiomterm")
             .with_transparent(fixed_config.window.transparent),
         ..Default::default()
     };
@@ -42,13 +65,47 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "axiomterm",
         options,
-        Box::new(move |cc| Ok(Box::new(TerminalApp::new(cc, backend, &fixed_config)))),
+        Box::new(move |cc| Ok(Box::new(TerminalApp::new(cc, backend, &fixed_config, &cli)))),
     )
 }
 
+// `--batch`: runs the commands piped to stdin without opening a window
+fn run_batch(backend: Box<dyn backend::ProcessBackend>, fixed_config: &FixedConfig, cli: &utils::CliArgs) -> i32 {
+    use std::io::{BufRead, IsTerminal};
+
+    if std::io::stdin().is_terminal() {
+        eprintln!("axiomterm: --batch runs the commands piped to stdin, but stdin is a terminal");
+        return 2;
+    }
+    let commands: Vec<String> = std::io::stdin().lock().lines().map_while(Result::ok).collect();
+    run_headless(&commands, backend, fixed_config, cli)
+}
+
+// Runs `commands` through the shell without a window, prints what they wrote, and returns
+// the last command's exit status. Shared by `--batch` and `--exec`.
+fn run_headless(commands: &[String], backend: Box<dyn backend::ProcessBackend>, fixed_config: &FixedConfig, cli: &utils::CliArgs) -> i32 {
+    // As in the window, `--cwd` wins over the config's `default_cwd`
+    let default_cwd = utils::get_default_config_path()
+        .and_then(|path| config::parse_config(&path).ok())
+        .and_then(|(update, _)| update.default_cwd);
+    let (current_dir, cwd_source, cwd_errors) = shell::resolve_initial_cwd(backend.as_ref(), cli.cwd.as_deref(), default_cwd.as_deref());
+    for error in cwd_errors {
+        eprintln!("axiomterm: {}", error);
+    }
+    let mut state = app::initial_shell_state(fixed_config, &lua_bridge::LuaEngine::new(), current_dir);
+    state.cwd_source = cwd_source;
+
+    let state = std::sync::Arc::new(std::sync::Mutex::new(state));
+    let code = shell::run_batch(commands, &state, backend);
+    for line in &state.lock().unwrap().screen.lines {
+        println!("{}", line.cells.iter().map(|c| c.ch).collect::<String>());
+    }
+    code
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::{parse_hex_color, tokenize_command};
+    use crate::utils::{delete_char_at, delete_char_before, delete_word_before, format_date, fuzzy_score, is_word_char, CliArgs, format_progress_bar, format_size, format_system_time, format_window_title, glob_match, expand_home, expand_vars, ls_color, parse_hex_color, parse_ls_colors, resolve, shell_quote, tokenize_command, truncate_chars, tokenize_command_checked, TokenizeError};
     use crate::types::TerminalColor;
 
     #[test]
@@ -58,6 +115,41 @@ mod tests {
         assert_eq!(tokens, vec!["ls", "-la"]);
     }
 
+    #[test]
+    fn test_very_long_lines() {
+        // A megabyte of words, and a megabyte-long quoted word, tokenize in one pass
+        let words = "ab ".repeat(350_000);
+        assert_eq!(tokenize_command(&words).len(), 350_000);
+        let quoted = format!("echo \"{}\"", "x".repeat(1_000_000));
+        let tokens = tokenize_command(&quoted);
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].len(), 1_000_000);
+
+        let mut line = "é".repeat(1_000_000);
+        assert!(truncate_chars(&mut line, 1000));
+        assert_eq!(line.chars().count(), 1000);
+        assert!(!truncate_chars(&mut line, 1000));
+        let mut short = "éé".to_string();
+        assert!(!truncate_chars(&mut short, 3));
+        assert_eq!(short, "éé");
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(tokenize_command("# just a note"), Vec::<String>::new());
+        assert_eq!(tokenize_command("   # indented note"), Vec::<String>::new());
+        assert_eq!(tokenize_command("ls -l # long listing"), vec!["ls", "-l"]);
+        assert_eq!(tokenize_command("echo hi #it's fine"), vec!["echo", "hi"]);
+        // Quoted, escaped or inside a word, `#` is just a character
+        assert_eq!(tokenize_command("echo \"# not a comment\" '#'"), vec!["echo", "# not a comment", "#"]);
+        assert_eq!(tokenize_command("echo \\#literal"), vec!["echo", "#literal"]);
+        assert_eq!(tokenize_command("echo issue#42 C#"), vec!["echo", "issue#42", "C#"]);
+        // A quote or backslash in a comment doesn't leave the line unfinished
+        assert_eq!(tokenize_command_checked("echo ok # don't \\"), Ok(vec!["echo".to_string(), "ok".to_string()]));
+        // Only to the end of its line
+        assert_eq!(tokenize_command("echo a # one\nb"), vec!["echo", "a", "b"]);
+    }
+
     #[test]
     fn test_double_quotes() {
         let input = "echo \"hello world\"";
@@ -93,6 +185,19 @@ mod tests {
         assert_eq!(tokens, vec!["echo", ""]);
     }
 
+    #[test]
+    fn test_unterminated_input() {
+        for input in ["echo 'hello", "echo \"hello", "echo \"it's", "echo hello\\", "echo \"a\\\""] {
+            assert_eq!(tokenize_command_checked(input), Err(TokenizeError::Unterminated), "{}", input);
+        }
+        // The lenient tokenizer keeps what it has
+        assert_eq!(tokenize_command("echo 'hello"), vec!["echo", "hello"]);
+
+        // Continuation lines joined with newlines: quotes keep them, backslash-newline drops them
+        assert_eq!(tokenize_command_checked("echo \"hello\nworld\""), Ok(vec!["echo".to_string(), "hello\nworld".to_string()]));
+        assert_eq!(tokenize_command_checked("echo hel\\\nlo"), Ok(vec!["echo".to_string(), "hello".to_string()]));
+    }
+
     #[test]
     fn test_hex_parsing() {
         assert_eq!(
@@ -106,10 +211,212 @@ mod tests {
         assert_eq!(parse_hex_color("invalid"), None);
     }
 
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(2048), "2.0KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0MB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0GB");
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/me".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        assert_eq!(expand_vars("$HOME/projects", lookup), "/home/me/projects");
+        assert_eq!(expand_vars("${HOME}_old/$EMPTY.", lookup), "/home/me_old/.");
+        // Unknown names, a bare `$` and an unclosed brace stay as written
+        assert_eq!(expand_vars("$NOPE/${NOPE}/a$/$", lookup), "$NOPE/${NOPE}/a$/$");
+        assert_eq!(expand_vars("${HOME", lookup), "${HOME");
+        assert_eq!(expand_vars("no vars", lookup), "no vars");
+
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_home("~"), home);
+            assert_eq!(expand_home("~/src"), home.join("src"));
+        }
+        assert_eq!(expand_home("~user/src"), std::path::PathBuf::from("~user/src"));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("ls"), "ls");
+        assert_eq!(shell_quote("/var/log"), "/var/log");
+        assert_eq!(shell_quote("my file"), "'my file'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("/home/me", "notes.txt"), std::path::PathBuf::from("/home/me/notes.txt"));
+        assert_eq!(resolve("/home/me", "../other"), std::path::PathBuf::from("/home/me/../other"));
+        assert_eq!(resolve("/home/me", "/etc/hosts"), std::path::PathBuf::from("/etc/hosts"));
+    }
+
+    #[test]
+    fn test_format_date() {
+        use chrono::TimeZone;
+        let time = chrono::Utc.with_ymd_and_hms(2024, 3, 5, 7, 8, 9).unwrap();
+        assert_eq!(format_date(&time, "%Y-%m-%d %H:%M").as_deref(), Some("2024-03-05 07:08"));
+        assert_eq!(format_date(&time, crate::utils::DEFAULT_DATE_FORMAT).as_deref(), Some("Tue Mar  5 07:08:09 UTC 2024"));
+        assert_eq!(format_date(&time, "%Q"), None);
+        assert_eq!(format_date(&time, "trailing %"), None);
+    }
+
+    #[test]
+    fn test_format_progress_bar() {
+        assert_eq!(format_progress_bar(0, 4, 8), "[        ]   0%");
+        assert_eq!(format_progress_bar(2, 4, 8), "[####    ]  50%");
+        assert_eq!(format_progress_bar(9, 4, 8), "[########] 100%");
+    }
+
+    #[test]
+    fn test_format_system_time() {
+        use std::time::{Duration, UNIX_EPOCH};
+        assert_eq!(format_system_time(UNIX_EPOCH), "1970-01-01 00:00:00");
+        assert_eq!(
+            format_system_time(UNIX_EPOCH + Duration::from_secs(951_782_400 + 3661)),
+            "2000-02-29 01:01:01"
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "ls"), Some(0));
+        assert_eq!(fuzzy_score("xyz", "ls"), None);
+        assert_eq!(fuzzy_score("sl", "ls"), None);
+        assert!(fuzzy_score("CD", "cd").is_some());
+        // A prefix beats the same letters further in
+        assert!(fuzzy_score("st", "stat") > fuzzy_score("st", "test"));
+        // So does a match at the start of a word
+        assert!(fuzzy_score("ms", "macro stats") > fuzzy_score("ms", "mismatch"));
+    }
+
+    #[test]
+    fn test_ls_color() {
+        let red = TerminalColor::from_rgb(255, 0, 0);
+        let green = TerminalColor::from_rgb(0, 255, 0);
+        let colors = std::collections::BTreeMap::from([
+            ("image".to_string(), red),
+            ("svg".to_string(), green),
+            ("tar.gz".to_string(), green),
+            ("archive".to_string(), red),
+        ]);
+        assert_eq!(ls_color("photo.PNG", &colors), Some(red));
+        // The extension itself beats its category, and a longer extension beats a shorter one
+        assert_eq!(ls_color("logo.svg", &colors), Some(green));
+        assert_eq!(ls_color("src.tar.gz", &colors), Some(green));
+        assert_eq!(ls_color("old.gz", &colors), Some(red));
+        assert_eq!(ls_color(".png", &colors), None);
+        assert_eq!(ls_color("Makefile", &colors), None);
+    }
+
+    #[test]
+    fn test_parse_ls_colors() {
+        let colors = parse_ls_colors("di=01;34:ln=01;36:*.tar=01;31:*.PNG=38;5;208:*.rs=38;2;222;165;132:*.md=00:*.py=01;92");
+        assert_eq!(colors.len(), 4);
+        assert_eq!(colors["tar"], TerminalColor::from_rgb(205, 0, 0));
+        assert_eq!(colors["png"], TerminalColor::from_rgb(255, 135, 0));
+        assert_eq!(colors["rs"], TerminalColor::from_rgb(222, 165, 132));
+        assert_eq!(colors["py"], TerminalColor::from_rgb(0, 255, 0));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(glob_match("ma?n.*", "main.rs"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("*.rs", "main.rs.bak"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn test_format_window_title() {
+        assert_eq!(
+            format_window_title("[{mode}] {title}", "INSERT", "axiomterm", "/tmp", "ls"),
+            "[INSERT] axiomterm"
+        );
+        assert_eq!(
+            format_window_title("{cmd} - {cwd}", "NORMAL", "axiomterm", "/tmp", "cargo build"),
+            "cargo build - /tmp"
+        );
+    }
+
+    #[test]
+    fn test_delete_char_at_and_before() {
+        let cases = [
+            ("abc", 0, "bc", "abc", 0),
+            ("abc", 1, "ac", "bc", 0),
+            ("abc", 3, "abc", "ab", 2),
+            ("", 0, "", "", 0),
+            ("aé漢", 1, "a漢", "é漢", 0),
+        ];
+        for (text, cursor, after_delete, after_backspace, backspace_cursor) in cases {
+            let mut buf = text.to_string();
+            assert_eq!(delete_char_at(&mut buf, cursor), cursor);
+            assert_eq!(buf, after_delete, "delete in {:?} at {}", text, cursor);
+
+            let mut buf = text.to_string();
+            assert_eq!(delete_char_before(&mut buf, cursor), backspace_cursor);
+            assert_eq!(buf, after_backspace, "backspace in {:?} at {}", text, cursor);
+        }
+    }
+
+    #[test]
+    fn test_delete_word_before() {
+        let mut buf = "git commit  -m".to_string();
+        let cursor = delete_word_before(&mut buf, 14, "_");
+        assert_eq!((buf.as_str(), cursor), ("git commit  -", 13));
+        let cursor = delete_word_before(&mut buf, cursor, "_");
+        assert_eq!((buf.as_str(), cursor), ("git ", 4));
+
+        let mut buf = "ｅｃｈｏ あいう".to_string();
+        let cursor = delete_word_before(&mut buf, 4, "_");
+        assert_eq!((buf.as_str(), cursor), (" あいう", 0));
+        assert_eq!(delete_word_before(&mut buf, 0, "_"), 0);
+    }
+
+    #[test]
+    fn test_custom_word_chars() {
+        assert!(is_word_char('a', ""));
+        assert!(is_word_char('_', "_"));
+        assert!(!is_word_char('_', ""));
+        assert!(!is_word_char('/', "_"));
+
+        // With path characters in the set, a whole path goes at once
+        let mut buf = "cat ~/src/main.rs".to_string();
+        assert_eq!(delete_word_before(&mut buf.clone(), 17, "_"), 15);
+        let cursor = delete_word_before(&mut buf, 17, "_~/.");
+        assert_eq!((buf.as_str(), cursor), ("cat ", 4));
+    }
+
+    #[test]
+    fn test_history_search() {
+        use crate::types::HistorySearch;
+        let history: Vec<String> = ["cargo build", "ls -la", "cargo test", "git status"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let mut search = HistorySearch { query: "cargo".to_string(), skip: 0 };
+        assert_eq!(search.find(&history), Some(2));
+        search.skip = 1;
+        assert_eq!(search.find(&history), Some(0));
+        search.skip = 2;
+        assert_eq!(search.find(&history), None);
+
+        let empty = HistorySearch::default();
+        assert_eq!(empty.find(&history), Some(3));
+    }
+
     #[test]
     fn test_headless_operation() {
         use crate::shell::spawn_shell_thread;
-        use crate::types::{ShellState, TerminalMode, Screen, ShellEvent, ScreenOperation, TerminalColor};
+        use crate::types::{Padding, ShellState, TerminalMode, Screen, ShellEvent, ScreenOperation, TerminalColor};
         use crossbeam_channel::unbounded;
         use std::sync::{Arc, Mutex};
         use std::time::Duration;
@@ -122,15 +429,67 @@ mod tests {
             text_color: TerminalColor::LIGHT_GRAY,
             window_title_base: "Test".to_string(),
             window_title_full: "Test".to_string(),
+            window_title_format: crate::types::DEFAULT_WINDOW_TITLE_FORMAT.to_string(),
             title_updated: false,
             mode: TerminalMode::Insert,
             shortcuts: Vec::new(),
             opacity: 1.0,
             font_size: 14.0,
+            configured_font_size: 14.0,
+            min_font_size: crate::types::MIN_FONT_SIZE,
+            high_contrast: false,
+            color_enabled: true,
+            color_forced_off: false,
+            clear_on_command: false,
+            key_repeat_delay: crate::types::DEFAULT_KEY_REPEAT_DELAY,
+            key_repeat_rate: crate::types::DEFAULT_KEY_REPEAT_RATE,
             current_dir: ".".to_string(),
+            cwd_source: Default::default(),
             directory_color: TerminalColor::BLUE,
+            symlink_color: TerminalColor::CYAN,
+            hidden_file_dim: 1.0,
+            ls_colors: Default::default(),
+            scrollbar: Default::default(),
+            scrollbar_width: None,
+            scrollbar_color: None,
+            stderr_color: TerminalColor::RED,
+            error_color: TerminalColor::RED,
+            cursor_color: TerminalColor::WHITE,
+            background_color: TerminalColor::BLACK,
+            background_image: None,
+            background_image_opacity: 1.0,
+            padding: Padding::default(),
+            current_line_highlight: None,
+            clickable_links: true,
+            copy_on_select: crate::types::DEFAULT_COPY_ON_SELECT,
+            word_chars: crate::types::DEFAULT_WORD_CHARS.to_string(),
+            max_input_length: crate::types::DEFAULT_MAX_INPUT_LENGTH,
+            max_command_lines: None,
+            confirm_destructive: false,
+            notify_after_secs: None,
+            window_focused: true,
+            notifications: Default::default(),
+            config_reload_quiet: false,
+            config_reload_debounce: crate::types::DEFAULT_CONFIG_RELOAD_DEBOUNCE,
+            multiline_paste: Default::default(),
+            pasted_lines: Default::default(),
+            pending_confirmation: None,
+            continuation: None,
             screen: Screen::new(),
             input_buffer: String::new(),
+            input_cursor: 0,
+            macro_names: Vec::new(),
+            macro_metrics: Default::default(),
+            render_metrics: Default::default(),
+            last_command: String::new(),
+            history: Vec::new(),
+            history_search: None,
+            running: Vec::new(),
+            sleep_until: None,
+            interrupts: 0,
+            last_status: 0,
+            env_overrides: Default::default(),
+            palette: Default::default(),
             mode_definitions: vec![
                 crate::types::ModeDefinition {
                     mode: TerminalMode::Insert,
@@ -140,11 +499,12 @@ mod tests {
                             target: crate::types::BindingTarget::Action(crate::types::Action::Submit) 
                         },
                     ],
+                    appearance: crate::types::ModeAppearance::default(),
                 },
             ],
         }));
 
-        spawn_shell_thread(cmd_rx, out_tx, Arc::clone(&state), Box::new(crate::backend::StdBackend));
+        spawn_shell_thread(cmd_rx, out_tx, Arc::clone(&state), Box::new(crate::backend::StdBackend), || {});
 
         use crate::types::Action;
         // Simulate typing "echo hello" and submitting
@@ -153,8 +513,16 @@ mod tests {
         }
         cmd_tx.send(Action::Submit).unwrap();
 
+        // Typing also moves the screen cursor; only the line operations matter here
+        let next_line_event = || loop {
+            let event = out_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+            if !matches!(event, ShellEvent::Operation(ScreenOperation::SetCursor(_))) {
+                return event;
+            }
+        };
+
         // 1st operation should be the echo of the command
-        let event = out_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        let event = next_line_event();
         if let ShellEvent::Operation(ScreenOperation::PushLine(line)) = event {
             let text: String = line.cells.iter().map(|c| c.ch).collect();
             assert!(text.contains("> echo hello"));
@@ -163,7 +531,7 @@ mod tests {
         }
 
         // 2nd operation should be the output of the echo command
-        let event = out_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        let event = next_line_event();
         if let ShellEvent::Operation(ScreenOperation::PushLine(line)) = event {
             let text: String = line.cells.iter().map(|c| c.ch).collect();
             assert_eq!(text, "hello");
@@ -171,4 +539,21 @@ mod tests {
             panic!("Expected PushLine operation for command output");
         }
     }
+
+    #[test]
+    fn test_cli_args() {
+        let parse = |args: &[&str]| CliArgs::parse(args.iter().map(|a| a.to_string()));
+        assert_eq!(parse(&[]), Ok(CliArgs::default()));
+        assert_eq!(parse(&["--cwd", "/tmp"]).unwrap().cwd.as_deref(), Some("/tmp"));
+        assert_eq!(parse(&["--cwd=~/src"]).unwrap().cwd.as_deref(), Some("~/src"));
+        assert!(parse(&["--batch", "--cwd", "/tmp"]).unwrap().batch);
+        assert!(!parse(&["--cwd", "--batch"]).unwrap().batch);
+        assert_eq!(parse(&["--exec", "ls -l"]).unwrap().exec.as_deref(), Some("ls -l"));
+        assert_eq!(parse(&["--exec=echo hi"]).unwrap().exec.as_deref(), Some("echo hi"));
+        assert!(parse(&["--exec"]).is_err());
+        assert!(parse(&["--exec", "ls", "--batch"]).is_err());
+        assert!(parse(&["--cwd"]).is_err());
+        assert!(parse(&["--no-color", "--exec", "ls"]).unwrap().no_color);
+        assert!(parse(&["--bogus"]).is_err());
+    }
 }
@@ -0,0 +1,340 @@
+use crate::backend::ProcessBackend;
+use crate::renderer::{char_columns, column_at_x, column_offsets, grid_shapes, ScrollRequest, TerminalRenderer, FAUX_BOLD_OFFSET};
+use crate::shell::spawn_shell_thread;
+use crate::types::{Action, Cell, Notice, ScreenOperation, ShellEvent, ShellState, TerminalColor, TerminalMode};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use eframe::egui;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How long a toast stays up, the last part of it spent fading out
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+const TOAST_FADE: Duration = Duration::from_millis(500);
+// Streaming output repaints at most this often, about once per display refresh
+const OUTPUT_REPAINT_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Spaces out the repaints that shell output asks for. Output after a quiet spell is shown
+/// at once; while it keeps streaming, repaints come at most once per interval, and the last
+/// one lands within an interval of the output stopping.
+struct RepaintThrottle {
+    interval: Duration,
+    // When the latest repaint happened or is scheduled to
+    next: Option<Instant>,
+}
+
+impl RepaintThrottle {
+    fn new(interval: Duration) -> Self {
+        Self { interval, next: None }
+    }
+
+    // How long from `now` to repaint for output that just arrived (zero for right away)
+    fn delay(&mut self, now: Instant) -> Duration {
+        let next = match self.next {
+            // The scheduled repaint will show this output too
+            Some(next) if next > now => next,
+            Some(last) => (last + self.interval).max(now),
+            None => now,
+        };
+        self.next = Some(next);
+        next - now
+    }
+}
+
+/// One terminal in the window: its shell state, the shell thread that drives it, and the view
+/// that draws it. Every split is a `Pane` with its own screen, input line and cwd.
+pub struct Pane {
+    pub shell_state: Arc<Mutex<ShellState>>,
+    pub action_tx: Sender<Action>,
+    pub output_rx: Receiver<ShellEvent>,
+    pub renderer: TerminalRenderer,
+    // Notices shown over the top-right corner, with when each arrived
+    toasts: Vec<(Notice, Instant)>,
+}
+
+impl Pane {
+    /// Starts a shell thread for `state`. `startup` commands are queued ahead of any input.
+    pub fn spawn(ctx: &egui::Context, state: ShellState, backend: Box<dyn ProcessBackend>, startup: Vec<Action>) -> Self {
+        let (action_tx, action_rx) = unbounded::<Action>();
+        let (output_tx, output_rx) = unbounded::<ShellEvent>();
+        let state = Arc::new(Mutex::new(state));
+
+        // Shell output can come from any thread (including backend readers), so relay it
+        // through one forwarder that wakes the UI, throttled while output floods in
+        let (shell_tx, shell_rx) = unbounded::<ShellEvent>();
+        let forward_ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let mut throttle = RepaintThrottle::new(OUTPUT_REPAINT_INTERVAL);
+            for event in shell_rx {
+                if output_tx.send(event).is_err() {
+                    break;
+                }
+                match throttle.delay(Instant::now()) {
+                    Duration::ZERO => forward_ctx.request_repaint(),
+                    delay => forward_ctx.request_repaint_after(delay),
+                }
+            }
+        });
+
+        for action in startup {
+            let _ = action_tx.send(action);
+        }
+        let action_ctx = ctx.clone();
+        spawn_shell_thread(action_rx, shell_tx, Arc::clone(&state), backend, move || action_ctx.request_repaint());
+
+        Self {
+            shell_state: state,
+            action_tx,
+            output_rx,
+            renderer: TerminalRenderer::new(),
+            toasts: Vec::new(),
+        }
+    }
+
+    /// Applies the screen operations the shell thread sent since the last frame.
+    pub fn poll_events(&mut self, ctx: &egui::Context) {
+        // Structural and visual ops are gathered so a burst of output costs one re-layout per frame
+        let mut screen_changes = Vec::new();
+        let before = self.renderer.metrics;
+        while let Ok(event) = self.output_rx.try_recv() {
+            match event {
+                ShellEvent::Operation(op) => {
+                    use crate::types::OperationCategory;
+                    match op.category() {
+                        OperationCategory::Structural | OperationCategory::Visual => screen_changes.push(op),
+                        OperationCategory::Cursor => self.on_cursor_change(ctx, &op),
+                    }
+                }
+                ShellEvent::Notification(notice) => {
+                    self.toasts.push((notice, Instant::now()));
+                }
+                ShellEvent::Bell => {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(egui::UserAttentionType::Informational));
+                }
+            }
+        }
+        if !screen_changes.is_empty() {
+            self.on_screen_changes(ctx, &screen_changes);
+        }
+        // Published for the `metrics` builtin, which runs on the shell thread
+        if self.renderer.metrics != before {
+            self.shell_state.lock().unwrap().render_metrics = self.renderer.metrics;
+        }
+    }
+
+    fn on_screen_changes(&mut self, ctx: &egui::Context, ops: &[ScreenOperation]) {
+        self.renderer.on_operation_batch(ctx, ops);
+    }
+
+    fn on_cursor_change(&mut self, ctx: &egui::Context, _op: &ScreenOperation) {
+        self.renderer.on_cursor_change(ctx);
+    }
+
+    // Scroll actions only touch the view, so they stay on the UI thread; everything else goes to the shell
+    pub fn dispatch_action(&mut self, ctx: &egui::Context, action: Action) {
+        let scroll = match action {
+            Action::ScrollTop => ScrollRequest::Top,
+            Action::ScrollBottom => ScrollRequest::Bottom,
+            Action::ScrollPageUp => ScrollRequest::PageUp,
+            Action::ScrollPageDown => ScrollRequest::PageDown,
+            // With a selection, Ctrl+C copies it instead (see the renderer)
+            Action::Interrupt if self.renderer.selection.is_some() => return,
+            action => {
+                let _ = self.action_tx.send(action);
+                return;
+            }
+        };
+        self.renderer.request_scroll(ctx, scroll);
+    }
+
+    /// Draws the scrollback and the prompt line. Only the focused pane shows a live cursor.
+    pub fn draw(&mut self, ui: &mut egui::Ui, focused: bool) {
+        let pane_rect = ui.max_rect();
+        self.draw_lines(ui, focused);
+        self.draw_toasts(ui, pane_rect);
+    }
+
+    fn draw_lines(&mut self, ui: &mut egui::Ui, focused: bool) {
+        let (mode, font_size, mut text_color, prompt_text, mut prompt_color, high_contrast, color_enabled) = {
+            let s = self.shell_state.lock().unwrap();
+            (
+                s.mode.clone(),
+                s.display_font_size(),
+                s.active_text_color(),
+                s.active_prompt().to_string(),
+                s.active_prompt_color(),
+                s.high_contrast,
+                s.color_enabled,
+            )
+        };
+        if !color_enabled {
+            prompt_color = text_color;
+        }
+        // High contrast overrides whatever the config, theme and mode resolved to
+        if high_contrast {
+            text_color = TerminalColor::WHITE;
+            prompt_color = TerminalColor::WHITE;
+        }
+
+        // Delegate rendering to renderer
+        {
+            let state = self.shell_state.lock().unwrap();
+            self.renderer.draw(ui, &state);
+        }
+
+        if self.shell_state.lock().unwrap().history_search.is_some() {
+            self.draw_history_search(ui, text_color);
+            return;
+        }
+
+        // Current Prompt/Input Line, over a slot for the current-line tint
+        let highlight_slot = ui.painter().add(egui::Shape::Noop);
+        let prompt_row = ui.horizontal(|ui| {
+            ui.label(
+                egui::RichText::new(&prompt_text)
+                    .color(egui::Color32::from(prompt_color))
+                    .strong(),
+            );
+
+            let s = self.shell_state.lock().unwrap();
+            let font_id = egui::FontId::monospace(font_size);
+            let (char_width, row_height) = ui.fonts(|f| (f.glyph_width(&font_id, 'M'), f.row_height(&font_id)));
+            // The typed command sits on the same character grid as the output
+            let cells: Vec<Cell> = s.input_buffer.chars().map(|c| Cell::new(c, TerminalColor::WHITE)).collect();
+            let offsets = column_offsets(s.input_buffer.chars());
+            let width = offsets[cells.len()] as f32 * char_width;
+            let (rect, response) = ui.allocate_exact_size(
+                egui::vec2(ui.available_width().max(width), row_height),
+                egui::Sense::click(),
+            );
+            let shapes = grid_shapes(ui.painter(), &cells, rect.min, char_width, &font_id);
+            if high_contrast {
+                let bold: Vec<_> = shapes.iter().cloned().map(|mut shape| {
+                    shape.translate(FAUX_BOLD_OFFSET);
+                    shape
+                }).collect();
+                ui.painter().extend(bold);
+            }
+            ui.painter().extend(shapes);
+
+            // Clicking in the typed command moves the insertion point there
+            if response.clicked()
+                && let Some(pos) = response.interact_pointer_pos()
+            {
+                let col = column_at_x(pos.x, rect.min.x, char_width, &offsets);
+                let _ = self.action_tx.send(Action::SetInputCursor(col));
+            }
+
+            // The caret covers the whole of a wide character under it
+            let col = s.screen.cursor.col.min(cells.len());
+            let cursor_cols = cells.get(col).map_or(1, |cell| char_columns(cell.ch).max(1));
+            let caret = egui::Rect::from_min_size(
+                rect.min + egui::vec2(offsets[col] as f32 * char_width, 0.0),
+                egui::vec2(cursor_cols as f32 * char_width, row_height),
+            );
+            self.renderer.draw_prompt_cursor(ui, caret, &s, focused && mode == TerminalMode::Insert);
+            s.current_line_highlight
+        });
+
+        if let Some(hl) = prompt_row.inner {
+            let row = prompt_row.response.rect;
+            let rect = egui::Rect::from_x_y_ranges(ui.max_rect().x_range(), row.y_range());
+            ui.painter().set(
+                highlight_slot,
+                egui::Shape::rect_filled(rect, 0.0, egui::Color32::from_rgba_unmultiplied(hl.r, hl.g, hl.b, 40)),
+            );
+        }
+    }
+
+    // Stacks unexpired toasts down from the top-right corner of the pane, above the scrollback
+    fn draw_toasts(&mut self, ui: &mut egui::Ui, pane_rect: egui::Rect) {
+        self.toasts.retain(|(_, shown)| shown.elapsed() < TOAST_DURATION);
+        if self.toasts.is_empty() {
+            return;
+        }
+        let monochrome = {
+            let s = self.shell_state.lock().unwrap();
+            (!s.color_enabled).then(|| s.active_text_color())
+        };
+        let painter = ui.ctx().layer_painter(egui::LayerId::new(egui::Order::Foreground, ui.id().with("toasts")));
+        let painter = painter.with_clip_rect(pane_rect);
+        let font_id = egui::FontId::proportional(14.0);
+        let margin = 8.0;
+        let padding = egui::vec2(10.0, 6.0);
+        let max_width = (pane_rect.width() * 0.6).max(100.0);
+        let mut top = pane_rect.top() + margin;
+        for (notice, shown) in &self.toasts {
+            let left = TOAST_DURATION.saturating_sub(shown.elapsed());
+            let alpha = (left.as_secs_f32() / TOAST_FADE.as_secs_f32()).min(1.0);
+            let text_color = egui::Color32::from(monochrome.unwrap_or(notice.color)).gamma_multiply(alpha);
+            let galley = painter.layout(notice.text.clone(), font_id.clone(), text_color, max_width);
+            let size = galley.size() + padding * 2.0;
+            let rect = egui::Rect::from_min_size(egui::pos2(pane_rect.right() - margin - size.x, top), size);
+            let fill = ui.visuals().extreme_bg_color.gamma_multiply(0.9 * alpha);
+            let stroke = egui::Stroke::new(1.0, text_color.gamma_multiply(0.5));
+            painter.rect(rect, 4.0, fill, stroke);
+            painter.galley(rect.min + padding, galley, text_color);
+            top = rect.bottom() + margin / 2.0;
+        }
+        // Keep repainting while anything is fading, and until the next one expires
+        let next = self.toasts.iter().map(|(_, shown)| TOAST_DURATION.saturating_sub(shown.elapsed())).min();
+        match next {
+            Some(left) if left > TOAST_FADE => ui.ctx().request_repaint_after(left - TOAST_FADE),
+            _ => ui.ctx().request_repaint(),
+        }
+    }
+
+    // Replaces the prompt line with `(reverse-i-search)`query': match` while a search is active
+    fn draw_history_search(&mut self, ui: &mut egui::Ui, text_color: TerminalColor) {
+        let s = self.shell_state.lock().unwrap();
+        let Some(search) = &s.history_search else { return };
+        let history = &s.history;
+
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            let label_color = egui::Color32::from(TerminalColor::GOLD);
+            ui.label(egui::RichText::new("(reverse-i-search)`").color(label_color));
+            ui.label(egui::RichText::new(&search.query).color(egui::Color32::WHITE));
+            ui.label(egui::RichText::new("': ").color(label_color));
+
+            let Some(idx) = search.find(history) else { return };
+            let entry = &history[idx];
+            let color = egui::Color32::from(text_color);
+            match entry.find(&search.query).filter(|_| !search.query.is_empty()) {
+                Some(start) => {
+                    let end = start + search.query.len();
+                    ui.label(egui::RichText::new(&entry[..start]).color(color));
+                    ui.label(
+                        egui::RichText::new(&entry[start..end])
+                            .color(egui::Color32::BLACK)
+                            .background_color(label_color),
+                    );
+                    ui.label(egui::RichText::new(&entry[end..]).color(color));
+                }
+                None => {
+                    ui.label(egui::RichText::new(entry).color(color));
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repaint_throttle() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut throttle = RepaintThrottle::new(ms(16));
+        // The first output after a quiet spell shows at once
+        assert_eq!(throttle.delay(start), Duration::ZERO);
+        // A burst waits for one repaint at the end of the interval
+        assert_eq!(throttle.delay(start + ms(1)), ms(15));
+        assert_eq!(throttle.delay(start + ms(10)), ms(6));
+        // Still streaming after that repaint: the next one is an interval later
+        assert_eq!(throttle.delay(start + ms(20)), ms(12));
+        // Quiet again
+        assert_eq!(throttle.delay(start + ms(500)), Duration::ZERO);
+    }
+}
@@ -1,5 +1,21 @@
 use eframe::egui;
-use crate::types::{ScreenOperation, LineImpact, ShellState};
+use crate::types::{Cell, CellAttr, Cursor, Line, ScreenOperation, LineImpact, RenderMetrics, ScrollbarMode, ShellState, TerminalColor};
+use crate::utils::is_word_char;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthChar;
+
+// Minimum time between full re-layouts caused by appended output alone
+const MIN_APPEND_RELAYOUT_INTERVAL: Duration = Duration::from_millis(50);
+
+// Prompt cursor blink: on for one interval, off for the next
+const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(530);
+const CURSOR_BAR_WIDTH: f32 = 2.0;
+
+// The default fonts have no bold face, so high-contrast text is drawn twice, this far apart
+pub const FAUX_BOLD_OFFSET: egui::Vec2 = egui::vec2(1.0, 0.0);
+
+// Detected URLs are recolored and underlined (see `clickable_links`)
+const LINK_COLOR: TerminalColor = TerminalColor::CYAN;
 
 pub struct LineRenderCache {
     #[allow(dead_code)]
@@ -7,12 +23,246 @@ pub struct LineRenderCache {
     pub shapes: Vec<egui::Shape>,
 }
 
-#[derive(Default, Debug)]
-pub struct RenderMetrics {
-    pub structural_ops: usize,
-    pub visual_ops: usize,
-    pub cursor_ops: usize,
-    pub dirty_line_count: usize,
+/// Decodes an image file into a texture for painting behind the terminal text.
+pub fn load_background_texture(ctx: &egui::Context, path: &str) -> Result<egui::TextureHandle, String> {
+    let img = image::open(path).map_err(|e| e.to_string())?.to_rgba8();
+    let size = [img.width() as usize, img.height() as usize];
+    let color_image = egui::ColorImage::from_rgba_unmultiplied(size, img.as_raw());
+    Ok(ctx.load_texture(format!("background:{}", path), color_image, egui::TextureOptions::LINEAR))
+}
+
+// Extra rows laid out above and below the viewport so short scrolls don't expose empty rows
+const VIEWPORT_MARGIN_ROWS: usize = 8;
+
+/// Rows intersecting the viewport `[top, bottom)` (content coordinates), padded by `VIEWPORT_MARGIN_ROWS`.
+fn visible_rows(top: f32, bottom: f32, row_height: f32, total: usize) -> std::ops::Range<usize> {
+    if row_height <= 0.0 || total == 0 {
+        return 0..total;
+    }
+    let first = (top.max(0.0) / row_height).floor() as usize;
+    let last = (bottom.max(0.0) / row_height).ceil() as usize;
+    let start = first.saturating_sub(VIEWPORT_MARGIN_ROWS).min(total);
+    let end = (last + VIEWPORT_MARGIN_ROWS).min(total);
+    start..end
+}
+
+/// Grid cells `ch` takes up: 2 for wide characters such as CJK and most emoji, 0 for combining
+/// marks and other zero-width characters, 1 for everything else.
+pub fn char_columns(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+/// The grid column each char starts at, followed by the width of the whole run.
+pub fn column_offsets(chars: impl IntoIterator<Item = char>) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for ch in chars {
+        offsets.push(offsets.last().unwrap() + char_columns(ch));
+    }
+    offsets
+}
+
+/// Char index of the boundary nearest `x` on a row starting at `origin_x`, where `offsets` comes
+/// from [`column_offsets`]. Clicks between two characters snap to whichever is closer.
+pub fn column_at_x(x: f32, origin_x: f32, char_width: f32, offsets: &[usize]) -> usize {
+    let len = offsets.len().saturating_sub(1);
+    if char_width <= 0.0 {
+        return len;
+    }
+    let col = (x - origin_x) / char_width;
+    // The first boundary at or past `col`, or the one before it if that is closer
+    let after = offsets.partition_point(|&c| (c as f32) < col).min(len);
+    match after.checked_sub(1) {
+        Some(before) if col - offsets[before] as f32 <= offsets[after] as f32 - col => before,
+        _ => after,
+    }
+}
+
+/// Char index of the character drawn under `x`, clamped to `0..=len` like [`column_at_x`].
+pub fn char_at_x(x: f32, origin_x: f32, char_width: f32, offsets: &[usize]) -> usize {
+    let len = offsets.len().saturating_sub(1);
+    if char_width <= 0.0 || x < origin_x {
+        return 0;
+    }
+    let col = ((x - origin_x) / char_width).floor() as usize;
+    // The last char starting at or before `col`; zero-width ones share their neighbour's cell
+    offsets.partition_point(|&c| c <= col).saturating_sub(1).min(len)
+}
+
+/// Shapes for `cells` laid out on the character grid from `origin`. Every character starts at
+/// its grid column rather than where the font's advance would put it, so wide characters take
+/// two cells, zero-width ones ride on the character before them, and fallback glyphs can't
+/// push the rest of the row out of line. Runs of ASCII in one style share a galley. Bold
+/// text is drawn twice, a pixel apart, and underlined text gets a line along the bottom.
+pub fn grid_shapes(painter: &egui::Painter, cells: &[Cell], origin: egui::Pos2, char_width: f32, font_id: &egui::FontId) -> Vec<egui::Shape> {
+    let mut shapes = Vec::new();
+    let mut run = String::new();
+    let mut run_start = 0;
+    let mut run_style = None;
+    let mut col = 0;
+    let mut flush = |run: &mut String, start: usize, end: usize, (fg, attrs): (TerminalColor, CellAttr)| {
+        if !run.is_empty() {
+            let color = egui::Color32::from(fg);
+            let galley = painter.layout_no_wrap(std::mem::take(run), font_id.clone(), color);
+            let pos = origin + egui::vec2(start as f32 * char_width, 0.0);
+            if attrs.underline {
+                let baseline = pos.y + galley.size().y - 1.0;
+                let right = origin.x + end as f32 * char_width;
+                shapes.push(egui::Shape::line_segment(
+                    [egui::pos2(pos.x, baseline), egui::pos2(right, baseline)],
+                    egui::Stroke::new(1.0, color),
+                ));
+            }
+            if attrs.bold {
+                shapes.push(egui::Shape::galley(pos + FAUX_BOLD_OFFSET, galley.clone(), color));
+            }
+            shapes.push(egui::Shape::galley(pos, galley, color));
+        }
+    };
+    for cell in cells {
+        let width = char_columns(cell.ch);
+        let style = (cell.fg, cell.attrs);
+        // Zero-width characters join whatever came before them
+        let joins = !run.is_empty() && (width == 0 || (cell.ch.is_ascii() && run.is_ascii() && run_style == Some(style)));
+        if !joins {
+            if let Some(style) = run_style {
+                flush(&mut run, run_start, col, style);
+            }
+            run_start = col;
+            run_style = Some(style);
+        }
+        run.push(cell.ch);
+        col += width;
+    }
+    if let Some(style) = run_style {
+        flush(&mut run, run_start, col, style);
+    }
+    shapes
+}
+
+/// A selected span of the output, from `start` up to (not including) `end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub start: Cursor,
+    pub end: Cursor,
+}
+
+impl Selection {
+    /// The whole of `row`.
+    pub fn line(row: usize, lines: &[Line]) -> Self {
+        let len = lines.get(row).map_or(0, |l| l.cells.len());
+        Self { start: Cursor { row, col: 0 }, end: Cursor { row, col: len } }
+    }
+
+    /// The word under `col` on `row`, or just that character if it is a separator.
+    pub fn word(row: usize, col: usize, lines: &[Line], word_chars: &str) -> Self {
+        let chars: Vec<char> = lines.get(row).map_or_else(Vec::new, |l| l.cells.iter().map(|c| c.ch).collect());
+        let (start, end) = word_bounds(&chars, col, word_chars);
+        Self { start: Cursor { row, col: start }, end: Cursor { row, col: end } }
+    }
+
+    /// Columns of `row` covered by the selection; rows in the middle are covered up to `len`.
+    fn cols_on(&self, row: usize, len: usize) -> Option<std::ops::Range<usize>> {
+        if row < self.start.row || row > self.end.row {
+            return None;
+        }
+        let from = if row == self.start.row { self.start.col } else { 0 };
+        let to = if row == self.end.row { self.end.col } else { len };
+        Some(from.min(len)..to.min(len))
+    }
+
+    /// The selected text, rows joined by newlines.
+    pub fn text(&self, lines: &[Line]) -> String {
+        (self.start.row..=self.end.row)
+            .filter_map(|row| {
+                let cells = &lines.get(row)?.cells;
+                let cols = self.cols_on(row, cells.len())?;
+                Some(cells[cols].iter().map(|c| c.ch).collect::<String>())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Column range of the word containing `col`: a run of word characters (see [`is_word_char`]),
+/// or the single separator character at `col`. Empty past the end of the line.
+fn word_bounds(chars: &[char], col: usize, word_chars: &str) -> (usize, usize) {
+    let Some(&ch) = chars.get(col) else {
+        return (chars.len(), chars.len());
+    };
+    if !is_word_char(ch, word_chars) {
+        return (col, col + 1);
+    }
+    let start = chars[..col].iter().rposition(|&c| !is_word_char(c, word_chars)).map_or(0, |i| i + 1);
+    let end = chars[col..].iter().position(|&c| !is_word_char(c, word_chars)).map_or(chars.len(), |i| col + i);
+    (start, end)
+}
+
+/// Char ranges of the `http://` / `https://` URLs in `chars`. A URL runs to the end of its
+/// whitespace-delimited token, minus trailing punctuation; a closing paren is kept if the URL
+/// opened one.
+fn find_links(chars: &[char]) -> Vec<std::ops::Range<usize>> {
+    let mut links = Vec::new();
+    let mut col = 0;
+    while col < chars.len() {
+        let starts_with = |prefix: &str| chars[col..].iter().copied().take(prefix.len()).eq(prefix.chars());
+        let scheme_len = if starts_with("https://") {
+            8
+        } else if starts_with("http://") {
+            7
+        } else {
+            col += 1;
+            continue;
+        };
+
+        let mut end = chars[col..].iter().position(|c| c.is_whitespace()).map_or(chars.len(), |i| col + i);
+        while end > col {
+            let last = chars[end - 1];
+            let keep_paren = last == ')' && chars[col..end].contains(&'(');
+            if keep_paren || !matches!(last, '.' | ',' | ';' | ':' | '!' | '?' | '\'' | '"' | ')' | ']' | '}' | '>') {
+                break;
+            }
+            end -= 1;
+        }
+        // A bare scheme with nothing after it isn't a link
+        if end > col + scheme_len {
+            links.push(col..end);
+        }
+        col = end.max(col + 1);
+    }
+    links
+}
+
+/// The URL covering `col` on `line`, if any.
+fn link_at(line: &Line, col: usize) -> Option<String> {
+    let chars: Vec<char> = line.cells.iter().map(|c| c.ch).collect();
+    find_links(&chars).into_iter().find(|link| link.contains(&col)).map(|link| chars[link].iter().collect())
+}
+
+/// Hands `url` to the platform's default handler.
+fn open_link(url: &str) {
+    #[cfg(target_os = "windows")]
+    let child = std::process::Command::new("cmd").args(["/C", "start", "", url]).spawn();
+    #[cfg(target_os = "macos")]
+    let child = std::process::Command::new("open").arg(url).spawn();
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let child = std::process::Command::new("xdg-open").arg(url).spawn();
+
+    match child {
+        // Reap the opener in the background so it doesn't linger as a zombie
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => log::warn!("Failed to open {}: {}", url, e),
+    }
+}
+
+/// Scrollback movement requested by a Normal-mode action, applied on the next draw.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollRequest {
+    Top,
+    Bottom,
+    PageUp,
+    PageDown,
 }
 
 pub struct TerminalRenderer {
@@ -20,7 +270,17 @@ pub struct TerminalRenderer {
     pub screen_cache: Vec<Option<LineRenderCache>>,
     pub last_render_dims: (f32, f32),
     pub cached_origin: egui::Pos2,
+    // Font size, high-contrast flag and no-color text color the cache was laid out with
+    pub cached_style: (f32, bool, Option<TerminalColor>),
     pub cursor_optimization_mode: bool,
+    pub pending_scroll: Option<ScrollRequest>,
+    pub scroll_offset: f32,
+    pub viewport_height: f32,
+    pub content_height: f32,
+    pub last_relayout: Option<Instant>,
+    // Restarts the blink cycle so a moving cursor is always drawn
+    pub last_cursor_change: Instant,
+    pub selection: Option<Selection>,
 }
 
 impl Default for TerminalRenderer {
@@ -30,7 +290,15 @@ impl Default for TerminalRenderer {
             screen_cache: Vec::new(),
             last_render_dims: (0.0, 0.0),
             cached_origin: egui::pos2(0.0, 0.0),
+            cached_style: (0.0, false, None),
             cursor_optimization_mode: true,
+            pending_scroll: None,
+            scroll_offset: 0.0,
+            viewport_height: 0.0,
+            content_height: 0.0,
+            last_relayout: None,
+            last_cursor_change: Instant::now(),
+            selection: None,
         }
     }
 }
@@ -40,12 +308,39 @@ impl TerminalRenderer {
         Self::default()
     }
 
+    /// Applies one frame's worth of structural/visual operations with at most one re-layout.
+    pub fn on_operation_batch(&mut self, ctx: &egui::Context, ops: &[ScreenOperation]) {
+        use crate::types::OperationCategory;
+
+        let structural = ops.iter().filter(|op| op.category() == OperationCategory::Structural).count();
+        let append_only = ops.iter().all(|op| matches!(op, ScreenOperation::PushLine(_) | ScreenOperation::UpdateLine(_, _)));
+
+        // Appended rows get fresh (empty) cache slots on the next draw, so existing rows stay valid;
+        // only re-layout for appends once in a while as a safety net
+        let recently_relaid = self.last_relayout.is_some_and(|t| t.elapsed() < MIN_APPEND_RELAYOUT_INTERVAL);
+        let relayout = structural > 0 && !(append_only && recently_relaid);
+
+        if relayout {
+            self.on_structural_change(ctx);
+            // Everything else in the batch is covered by that re-layout
+            self.metrics.coalesced_ops += ops.len() - 1;
+            return;
+        }
+
+        self.metrics.coalesced_ops += structural;
+        for op in ops.iter().filter(|op| op.category() == OperationCategory::Visual) {
+            self.on_visual_change(ctx, op);
+        }
+        ctx.request_repaint();
+    }
+
     pub fn on_structural_change(&mut self, ctx: &egui::Context) {
         self.metrics.structural_ops += 1;
+        self.last_relayout = Some(Instant::now());
         self.screen_cache.clear();
         self.metrics.dirty_line_count = usize::MAX;
         
-        println!("DEBUG: [Structural] Re-layout triggered. Metrics: {:?}", self.metrics);
+        log::debug!("[Structural] Re-layout triggered. Metrics: {:?}", self.metrics);
         ctx.request_repaint();
     }
 
@@ -70,37 +365,86 @@ impl TerminalRenderer {
             }
         }
 
-        // Optimization: Single Line Invalidation
-        if self.metrics.dirty_line_count == 1 {
-            if let LineImpact::Single(row) = metadata.impact {
-                if row < self.screen_cache.len() {
-                    println!("DEBUG: [Visual] Optimized: Invalidating only row {}", row);
-                    self.screen_cache[row] = None;
-                } else {
-                     self.screen_cache.clear();
-                }
-            } else {
-                 self.screen_cache.clear();
+        // Optimization: only drop the cached rows this operation touched, so several
+        // line updates in one frame still keep the rest of the cache
+        let rows: &[usize] = match metadata.impact {
+            LineImpact::Single(ref row) => std::slice::from_ref(row),
+            LineImpact::Multi(ref rows) => rows,
+            LineImpact::Unbounded => &[],
+        };
+        let in_cache = |row: &usize| *row < self.screen_cache.len();
+        if self.metrics.dirty_line_count != usize::MAX && !rows.is_empty() && rows.iter().all(in_cache) {
+            log::debug!("[Visual] Optimized: Invalidating only rows {:?}", rows);
+            for &row in rows {
+                self.screen_cache[row] = None;
             }
         } else {
             self.screen_cache.clear();
         }
 
-        println!("DEBUG: [Visual] Paint update. Impact: {:?}, Metrics: {:?}", metadata.impact, self.metrics);
+        log::debug!("[Visual] Paint update. Impact: {:?}, Metrics: {:?}", metadata.impact, self.metrics);
         ctx.request_repaint();
     }
 
     pub fn on_cursor_change(&mut self, ctx: &egui::Context) {
         self.metrics.cursor_ops += 1;
-        println!("DEBUG: [Cursor] Cursor update. Total: {}", self.metrics.cursor_ops);
+        self.last_cursor_change = Instant::now();
+        log::debug!("[Cursor] Cursor update. Total: {}", self.metrics.cursor_ops);
         ctx.request_repaint();
     }
 
+    /// Whether the blinking cursor is in its visible phase at `now`, and how long until it toggles.
+    /// The cycle restarts whenever the cursor moves, so it stays solid while typing.
+    fn cursor_blink_phase(&self, now: Instant) -> (bool, Duration) {
+        let period = CURSOR_BLINK_INTERVAL.as_millis();
+        let elapsed = now.saturating_duration_since(self.last_cursor_change).as_millis();
+        let visible = (elapsed / period).is_multiple_of(2);
+        (visible, Duration::from_millis((period - elapsed % period) as u64))
+    }
+
+    /// Paints the prompt cursor over `caret`, the grid cells of the character at `screen.cursor`:
+    /// a bar while inserting, a block over the whole character otherwise. Schedules the repaint for the next blink toggle.
+    pub fn draw_prompt_cursor(&self, ui: &egui::Ui, caret: egui::Rect, state: &ShellState, bar: bool) {
+        let (visible, until_toggle) = self.cursor_blink_phase(Instant::now());
+        ui.ctx().request_repaint_after(until_toggle);
+        if !visible {
+            return;
+        }
+
+        let cc = state.cursor_color;
+        let (width, alpha) = if bar {
+            (CURSOR_BAR_WIDTH, 255)
+        } else {
+            // Translucent so the character underneath shows through, unless contrast matters more
+            (caret.width(), if state.high_contrast { 255 } else { 100 })
+        };
+        ui.painter().rect_filled(
+            egui::Rect::from_min_size(caret.min, egui::vec2(width, caret.height())),
+            0.0,
+            egui::Color32::from_rgba_unmultiplied(cc.r, cc.g, cc.b, alpha),
+        );
+    }
+
+    pub fn request_scroll(&mut self, ctx: &egui::Context, request: ScrollRequest) {
+        self.pending_scroll = Some(request);
+        ctx.request_repaint();
+    }
+
+    fn take_scroll_offset(&mut self) -> Option<f32> {
+        let max_offset = (self.content_height - self.viewport_height).max(0.0);
+        let offset = match self.pending_scroll.take()? {
+            ScrollRequest::Top => 0.0,
+            ScrollRequest::Bottom => max_offset,
+            ScrollRequest::PageUp => self.scroll_offset - self.viewport_height,
+            ScrollRequest::PageDown => self.scroll_offset + self.viewport_height,
+        };
+        Some(offset.clamp(0.0, max_offset))
+    }
+
     // This method encapsulates the main rendering loop
     pub fn draw(&mut self, ui: &mut egui::Ui, state: &ShellState) {
-         let font_size = state.font_size;
+         let font_size = state.display_font_size();
          let lines = &state.screen.lines;
-         let cursor = &state.screen.cursor;
          
          // Visual style override
          ui.style_mut().visuals.extreme_bg_color = egui::Color32::BLACK;
@@ -113,6 +457,15 @@ impl TerminalRenderer {
              self.last_render_dims = curr_dims;
          }
 
+         // Safety Net: text style changes re-lay out every row
+         // Without colors every row takes the text color, so that is part of the style too
+         let monochrome = (!state.color_enabled).then(|| state.active_text_color());
+         let style = (font_size, state.high_contrast, monochrome);
+         if style != self.cached_style {
+             self.screen_cache.clear();
+             self.cached_style = style;
+         }
+
          if !self.cursor_optimization_mode {
              self.screen_cache.clear();
          }
@@ -122,44 +475,102 @@ impl TerminalRenderer {
              self.screen_cache.resize_with(lines.len(), || None);
          }
 
-         egui::ScrollArea::vertical()
+         // Scrollbar look, per the `scrollbar*` settings
+         let style = ui.style_mut();
+         style.spacing.scroll = match state.scrollbar {
+             ScrollbarMode::Overlay => egui::style::ScrollStyle::floating(),
+             _ => egui::style::ScrollStyle::solid(),
+         };
+         if let Some(width) = state.scrollbar_width {
+             style.spacing.scroll.bar_width = width;
+         }
+         if let Some(color) = state.scrollbar_color {
+             let widgets = &mut style.visuals.widgets;
+             for visuals in [&mut widgets.inactive, &mut widgets.hovered, &mut widgets.active] {
+                 visuals.bg_fill = egui::Color32::from(color);
+             }
+         }
+         let visibility = match state.scrollbar {
+             ScrollbarMode::Overlay | ScrollbarMode::Solid => egui::scroll_area::ScrollBarVisibility::VisibleWhenNeeded,
+             ScrollbarMode::Always => egui::scroll_area::ScrollBarVisibility::AlwaysVisible,
+             ScrollbarMode::Hidden => egui::scroll_area::ScrollBarVisibility::AlwaysHidden,
+         };
+
+         let mut scroll_area = egui::ScrollArea::vertical()
              .auto_shrink([false; 2])
              .stick_to_bottom(true)
-             .show(ui, |ui| {
+             .scroll_bar_visibility(visibility);
+         if let Some(offset) = self.take_scroll_offset() {
+             scroll_area = scroll_area.vertical_scroll_offset(offset);
+         }
+
+         let output = scroll_area.show_viewport(ui, |ui, viewport| {
                  let font_id = egui::FontId::monospace(font_size);
                  
                  // 1. Calculate metrics
-                 let (row_height, char_width) = {
-                     let painter = ui.painter();
-                     let char_dims = painter.layout_no_wrap("A".to_string(), font_id.clone(), egui::Color32::WHITE).size();
-                     (char_dims.y, char_dims.x)
-                 };
+                 let glyph = ui.painter().layout_no_wrap("A".to_string(), font_id.clone(), egui::Color32::WHITE).size();
+                 let (char_width, row_height) = (glyph.x, glyph.y);
 
                  // 2. Check Safety Nets (Origin/Scroll)
-                 let curr_origin = ui.cursor().min;
-                 if curr_origin != self.cached_origin {
+                 // The origin already sits inside the panel's padding, so a padding change re-lays out the cache
+                 let origin = ui.cursor().min;
+                 if origin != self.cached_origin {
                       self.screen_cache.clear();
                       self.screen_cache.resize_with(lines.len(), || None);
-                      self.cached_origin = curr_origin;
+                      self.cached_origin = origin;
                  }
 
-                 // 3. Rebuild Cache (Row-based)
-                 let start_y = ui.cursor().min.y;
-                 
-                 for (i, line) in lines.iter().enumerate() {
+                 // 3. Rebuild Cache (Row-based), only for rows in or near the viewport
+                 let visible = visible_rows(viewport.min.y, viewport.max.y, row_height, lines.len());
+
+                 for (i, line) in lines.iter().enumerate().take(visible.end).skip(visible.start) {
                      if self.screen_cache[i].is_none() {
                          let painter = ui.painter();
                          let mut shapes = Vec::new();
-                         let y = start_y + (i as f32 * row_height);
-                         let mut x = ui.cursor().min.x;
-
-                         for cell in &line.cells {
-                             let color = egui::Color32::from(cell.fg);
-                             let galley = painter.layout_no_wrap(cell.ch.to_string(), font_id.clone(), color);
-                             let rect = egui::Rect::from_min_size(egui::pos2(x, y), galley.size());
-                             
-                             shapes.push(egui::Shape::galley(rect.min, galley, color));
-                             x += rect.width();
+                         let y = origin.y + (i as f32 * row_height);
+
+                         // High contrast whitens all text and no-color mode draws it in the text color;
+                         // links get their own color (if colors are on) plus an underline
+                         let links = if state.clickable_links {
+                             find_links(&line.cells.iter().map(|c| c.ch).collect::<Vec<_>>())
+                         } else {
+                             Vec::new()
+                         };
+                         let linked;
+                         let forced_color = if state.high_contrast { Some(TerminalColor::WHITE) } else { monochrome };
+                         let link_fg = monochrome.and(forced_color).unwrap_or(LINK_COLOR);
+                         let line = if links.is_empty() && forced_color.is_none() {
+                             line
+                         } else {
+                             let mut recolored = line.clone();
+                             if let Some(color) = forced_color {
+                                 for cell in &mut recolored.cells {
+                                     cell.fg = color;
+                                 }
+                             }
+                             for link in &links {
+                                 for cell in &mut recolored.cells[link.clone()] {
+                                     cell.fg = link_fg;
+                                     cell.attrs.underline = true;
+                                 }
+                             }
+                             linked = recolored;
+                             &linked
+                         };
+
+                         // Plain ASCII lines in one style (the common case) become a single galley
+                         shapes.extend(grid_shapes(painter, &line.cells, egui::pos2(origin.x, y), char_width, &font_id));
+                         if state.high_contrast {
+                             let bold: Vec<_> = shapes
+                                 .iter()
+                                 .filter(|shape| matches!(shape, egui::Shape::Text(_)))
+                                 .map(|shape| {
+                                     let mut shape = shape.clone();
+                                     shape.translate(FAUX_BOLD_OFFSET);
+                                     shape
+                                 })
+                                 .collect();
+                             shapes.extend(bold);
                          }
                          self.screen_cache[i] = Some(LineRenderCache {
                              line_index: i,
@@ -168,31 +579,256 @@ impl TerminalRenderer {
                      }
                  }
 
-                 // 4. Draw Cache
+                 // 4. Draw Cache, over the selection highlight
                  let painter = ui.painter();
-                 for cache_opt in &self.screen_cache {
-                     if let Some(cache) = cache_opt {
-                         painter.extend(cache.shapes.iter().cloned());
+                 if let Some(selection) = self.selection {
+                     let fill = ui.visuals().selection.bg_fill;
+                     for (i, line) in lines.iter().enumerate().take(visible.end).skip(visible.start) {
+                         if let Some(cols) = selection.cols_on(i, line.cells.len()).filter(|c| !c.is_empty()) {
+                             let offsets = column_offsets(line.cells.iter().map(|c| c.ch));
+                             let (start, end) = (offsets[cols.start], offsets[cols.end]);
+                             let min = origin + egui::vec2(start as f32 * char_width, i as f32 * row_height);
+                             let size = egui::vec2((end - start) as f32 * char_width, row_height);
+                             painter.rect_filled(egui::Rect::from_min_size(min, size), 0.0, fill);
+                         }
                      }
                  }
+                 for cache in self.screen_cache[visible.clone()].iter().flatten() {
+                     painter.extend(cache.shapes.iter().cloned());
+                 }
 
-                 // 5. Allocate Space
-                 let (_id, allocated_rect) = ui.allocate_space(egui::vec2(ui.available_width(), row_height * lines.len() as f32));
-                 
-                 // 6. Draw Cursor Layer
-                 let cursor_rect = egui::Rect::from_min_size(
-                     egui::pos2(
-                         allocated_rect.min.x + cursor.col as f32 * char_width,
-                         allocated_rect.min.y + cursor.row as f32 * row_height
-                     ),
-                     egui::vec2(char_width, row_height)
-                 );
-                 ui.painter().rect_filled(cursor_rect, 0.0, egui::Color32::from_white_alpha(100)); // Semi-transparent cursor
-                 
-                 // Prompt drawing is handled by caller or we can move it here too?
-                 // Caller handles prompt input line for now as it contains TextEdit logic.
+                 // 5. Allocate Space (the full buffer, so the scrollbar reflects all lines)
+                 let size = egui::vec2(ui.available_width(), row_height * lines.len() as f32);
+                 let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+
+                 let cell_at = |pos: egui::Pos2| {
+                     let row = ((pos.y - rect.min.y) / row_height).floor().max(0.0) as usize;
+                     let offsets = column_offsets(lines.get(row).into_iter().flat_map(|l| l.cells.iter().map(|c| c.ch)));
+                     (row, char_at_x(pos.x, rect.min.x, char_width, &offsets))
+                 };
+                 let link_under = |pos: egui::Pos2| {
+                     let (row, col) = cell_at(pos);
+                     lines.get(row).filter(|_| state.clickable_links).and_then(|line| link_at(line, col))
+                 };
+
+                 if response.hover_pos().and_then(link_under).is_some() {
+                     ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                 }
+
+                 // Double-click selects a word, triple-click the whole line, a single click clears
+                 // (or opens the link under the pointer)
+                 if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+                     let (row, col) = cell_at(pos);
+                     self.selection = if response.triple_clicked() {
+                         Some(Selection::line(row, lines))
+                     } else if response.double_clicked() {
+                         Some(Selection::word(row, col, lines, &state.word_chars))
+                     } else {
+                         if let Some(url) = link_under(pos) {
+                             open_link(&url);
+                         }
+                         None
+                     };
+                     if state.copy_on_select
+                         && let Some(selection) = self.selection
+                     {
+                         ui.ctx().copy_text(selection.text(lines));
+                     }
+                 }
+
+                 // The cursor lives on the prompt row, which the caller draws below the buffer
+                 // (see `draw_prompt_cursor`)
              });
+
+         // Ctrl+C copies the selection
+         if let Some(selection) = self.selection
+             && ui.input(|i| i.events.iter().any(|e| matches!(e, egui::Event::Copy)))
+         {
+             ui.ctx().copy_text(selection.text(lines));
+         }
+
+         // Remember the scroll geometry so the next ScrollRequest can be resolved
+         self.scroll_offset = output.state.offset.y;
+         self.viewport_height = output.inner_rect.height();
+         self.content_height = output.content_size.y;
              
          self.metrics.dirty_line_count = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Line, Screen, TerminalColor};
+
+    fn cached_renderer(rows: usize) -> TerminalRenderer {
+        let mut renderer = TerminalRenderer::new();
+        renderer.screen_cache = (0..rows)
+            .map(|i| Some(LineRenderCache { line_index: i, shapes: Vec::new() }))
+            .collect();
+        renderer
+    }
+
+    #[test]
+    fn test_update_line_invalidates_only_its_row() {
+        let ctx = egui::Context::default();
+        let mut screen = Screen::new();
+        for i in 0..3 {
+            screen.push_line(Line::from_string(&format!("line {}", i), TerminalColor::WHITE));
+        }
+        let mut renderer = cached_renderer(3);
+
+        let op = screen.update_line(1, Line::from_string("rewritten", TerminalColor::WHITE));
+        assert_eq!(op.category(), crate::types::OperationCategory::Visual);
+        renderer.on_visual_change(&ctx, &op);
+        let valid: Vec<bool> = renderer.screen_cache.iter().map(Option::is_some).collect();
+        assert_eq!(valid, vec![true, false, true]);
+
+        // A second update in the same frame still leaves the other rows alone
+        let op = screen.update_line(2, Line::from_string("again", TerminalColor::WHITE));
+        renderer.on_visual_change(&ctx, &op);
+        let valid: Vec<bool> = renderer.screen_cache.iter().map(Option::is_some).collect();
+        assert_eq!(valid, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_operation_batch_coalesces() {
+        let ctx = egui::Context::default();
+        let mut screen = Screen::new();
+        let mut renderer = TerminalRenderer::new();
+
+        let burst: Vec<_> = (0..100)
+            .map(|i| screen.push_line(Line::from_string(&format!("line {}", i), TerminalColor::WHITE)))
+            .collect();
+        renderer.on_operation_batch(&ctx, &burst);
+        assert_eq!(renderer.metrics.structural_ops, 1);
+        assert_eq!(renderer.metrics.coalesced_ops, 99);
+
+        // More appends right after a re-layout ride on the existing cache
+        renderer.screen_cache = cached_renderer(100).screen_cache;
+        let more = vec![screen.push_line(Line::from_string("more", TerminalColor::WHITE))];
+        renderer.on_operation_batch(&ctx, &more);
+        assert_eq!(renderer.metrics.structural_ops, 1);
+        assert!(renderer.screen_cache.iter().all(Option::is_some));
+
+        // A clear always re-lays out
+        renderer.on_operation_batch(&ctx, &[screen.clear()]);
+        assert_eq!(renderer.metrics.structural_ops, 2);
+    }
+
+    #[test]
+    fn test_visible_rows() {
+        // 10px rows, viewport showing rows 100..110 of 50k
+        assert_eq!(visible_rows(1000.0, 1100.0, 10.0, 50_000), 92..118);
+        assert_eq!(visible_rows(0.0, 100.0, 10.0, 50_000), 0..18);
+        assert_eq!(visible_rows(0.0, 100.0, 10.0, 5), 0..5);
+        assert_eq!(visible_rows(0.0, 100.0, 0.0, 5), 0..5);
+    }
+
+    #[test]
+    fn test_column_at_x() {
+        let narrow = column_offsets("hello".chars());
+        assert_eq!(column_at_x(100.0, 100.0, 8.0, &narrow), 0);
+        assert_eq!(column_at_x(90.0, 100.0, 8.0, &narrow), 0);
+        assert_eq!(column_at_x(113.0, 100.0, 8.0, &narrow), 2);
+        assert_eq!(column_at_x(111.0, 100.0, 8.0, &narrow), 1);
+        assert_eq!(column_at_x(500.0, 100.0, 8.0, &narrow), 5);
+
+        // `日本` covers cells 0..4, so the boundary after 日 is at x = 16
+        let wide = column_offsets("日本x".chars());
+        assert_eq!(column_at_x(107.0, 100.0, 8.0, &wide), 0);
+        assert_eq!(column_at_x(109.0, 100.0, 8.0, &wide), 1);
+        assert_eq!(column_at_x(125.0, 100.0, 8.0, &wide), 2);
+        assert_eq!(column_at_x(137.0, 100.0, 8.0, &wide), 3);
+    }
+
+    #[test]
+    fn test_display_widths() {
+        assert_eq!(char_columns('a'), 1);
+        assert_eq!(char_columns('日'), 2);
+        assert_eq!(char_columns('😀'), 2);
+        assert_eq!(char_columns('\u{301}'), 0);
+        assert_eq!(column_offsets("ab".chars()), vec![0, 1, 2]);
+        assert_eq!(column_offsets("a日b".chars()), vec![0, 1, 3, 4]);
+        // A combining accent shares the cell of the letter it modifies
+        assert_eq!(column_offsets("e\u{301}x".chars()), vec![0, 1, 1, 2]);
+        assert_eq!(column_offsets("🎉 ok".chars()), vec![0, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_char_at_x() {
+        let offsets = column_offsets("a日e\u{301}".chars());
+        assert_eq!(char_at_x(90.0, 100.0, 8.0, &offsets), 0);
+        assert_eq!(char_at_x(104.0, 100.0, 8.0, &offsets), 0);
+        // Both halves of the wide character pick it
+        assert_eq!(char_at_x(109.0, 100.0, 8.0, &offsets), 1);
+        assert_eq!(char_at_x(123.0, 100.0, 8.0, &offsets), 1);
+        // The accent has no cell of its own, so its letter is picked
+        assert_eq!(char_at_x(126.0, 100.0, 8.0, &offsets), 2);
+        assert_eq!(char_at_x(500.0, 100.0, 8.0, &offsets), 4);
+    }
+
+    #[test]
+    fn test_word_and_line_selection() {
+        let lines = vec![
+            Line::from_string("cargo build --release", TerminalColor::WHITE),
+            Line::from_string("my_var=42;", TerminalColor::WHITE),
+        ];
+
+        assert_eq!(Selection::word(0, 8, &lines, "_").text(&lines), "build");
+        assert_eq!(Selection::word(0, 5, &lines, "_").text(&lines), " ");
+        assert_eq!(Selection::word(0, 17, &lines, "_").text(&lines), "release");
+        assert_eq!(Selection::word(1, 0, &lines, "_").text(&lines), "my_var");
+        assert_eq!(Selection::word(1, 8, &lines, "_").text(&lines), "42");
+        assert_eq!(Selection::word(1, 40, &lines, "_").text(&lines), "");
+        // Custom sets change what a double-click grabs
+        assert_eq!(Selection::word(0, 17, &lines, "_-").text(&lines), "--release");
+        assert_eq!(Selection::word(1, 0, &lines, "").text(&lines), "my");
+        assert_eq!(Selection::line(1, &lines).text(&lines), "my_var=42;");
+
+        let span = Selection { start: Cursor { row: 0, col: 6 }, end: Cursor { row: 1, col: 6 } };
+        assert_eq!(span.text(&lines), "build --release\nmy_var");
+    }
+
+    #[test]
+    fn test_find_links() {
+        let links = |text: &str| -> Vec<String> {
+            let chars: Vec<char> = text.chars().collect();
+            find_links(&chars).into_iter().map(|r| chars[r].iter().collect()).collect()
+        };
+
+        assert_eq!(links("see https://example.com/docs."), ["https://example.com/docs"]);
+        assert_eq!(links("(http://a.io/x), https://b.io?q=1!"), ["http://a.io/x", "https://b.io?q=1"]);
+        assert_eq!(links("https://en.wikipedia.org/wiki/Rust_(language)"), ["https://en.wikipedia.org/wiki/Rust_(language)"]);
+        assert_eq!(links("\"https://x.dev\" and ftp://y"), ["https://x.dev"]);
+        assert!(links("no links, just https:// here").is_empty());
+
+        let line = Line::from_string("go to https://x.dev now", TerminalColor::WHITE);
+        assert_eq!(link_at(&line, 10).as_deref(), Some("https://x.dev"));
+        assert_eq!(link_at(&line, 19), None);
+    }
+
+    #[test]
+    fn test_push_line_clears_cache() {
+        let ctx = egui::Context::default();
+        let mut renderer = cached_renderer(2);
+        let op = Screen::new().push_line(Line::from_string("new", TerminalColor::WHITE));
+        renderer.on_structural_change(&ctx);
+        assert_eq!(op.category(), crate::types::OperationCategory::Structural);
+        assert!(renderer.screen_cache.is_empty());
+    }
+
+    #[test]
+    fn test_cursor_blink_restarts_on_move() {
+        let mut renderer = TerminalRenderer::new();
+        let start = renderer.last_cursor_change;
+        assert_eq!(renderer.cursor_blink_phase(start), (true, CURSOR_BLINK_INTERVAL));
+        assert!(!renderer.cursor_blink_phase(start + CURSOR_BLINK_INTERVAL).0);
+        assert!(renderer.cursor_blink_phase(start + CURSOR_BLINK_INTERVAL * 2).0);
+
+        renderer.on_cursor_change(&egui::Context::default());
+        let moved = renderer.last_cursor_change;
+        assert!(renderer.cursor_blink_phase(moved).0);
+        assert_eq!(renderer.metrics.cursor_ops, 1);
+    }
+}
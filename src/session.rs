@@ -0,0 +1,104 @@
+use crate::pane::Pane;
+use eframe::egui;
+
+/// A tab: one or more panes side by side, one of which has focus. Each tab keeps its own
+/// panes, so switching tabs leaves scrollback and cwd where they were.
+pub struct Session {
+    // Left to right; there is always at least one
+    pub panes: Vec<Pane>,
+    pub focused: usize,
+}
+
+impl Session {
+    pub fn new(pane: Pane) -> Self {
+        Self { panes: vec![pane], focused: 0 }
+    }
+
+    pub fn focused_pane(&self) -> &Pane {
+        &self.panes[self.focused]
+    }
+
+    pub fn focused_pane_mut(&mut self) -> &mut Pane {
+        &mut self.panes[self.focused]
+    }
+
+    /// Adds `pane` to the right of the focused pane and focuses it.
+    pub fn insert_pane(&mut self, pane: Pane) {
+        self.panes.insert(self.focused + 1, pane);
+        self.focus(self.focused + 1);
+    }
+
+    /// Closes the focused pane and stops whatever it was running. Returns false, closing
+    /// nothing, when it is the last pane; the caller decides whether the whole tab goes.
+    pub fn close_focused(&mut self) -> bool {
+        if self.panes.len() == 1 {
+            return false;
+        }
+        let pane = self.panes.remove(self.focused);
+        pane.shell_state.lock().unwrap().kill_running();
+        // Dropping the pane closes its action channel, which ends its shell thread
+        drop(pane);
+        self.focus(self.focused.min(self.panes.len() - 1));
+        true
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focus((self.focused + 1) % self.panes.len());
+    }
+
+    pub fn focus_prev(&mut self) {
+        self.focus((self.focused + self.panes.len() - 1) % self.panes.len());
+    }
+
+    pub fn focus(&mut self, index: usize) {
+        if index != self.focused
+            && let Some(old) = self.panes.get_mut(self.focused)
+        {
+            // Ctrl+C copies the focused pane's selection, so a stale one elsewhere would win
+            old.renderer.selection = None;
+        }
+        self.focused = index;
+        // The window title follows the focused pane
+        self.panes[index].shell_state.lock().unwrap().title_updated = true;
+    }
+
+    /// The tab bar label: the last component of the focused pane's cwd.
+    pub fn title(&self) -> String {
+        let cwd = self.focused_pane().shell_state.lock().unwrap().current_dir.clone();
+        match std::path::Path::new(&cwd).file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => cwd,
+        }
+    }
+
+    pub fn kill_running(&self) {
+        for pane in &self.panes {
+            if let Ok(mut s) = pane.shell_state.lock() {
+                s.kill_running();
+            }
+        }
+    }
+
+    /// Lays the panes out in equal columns; clicking one focuses it.
+    pub fn draw(&mut self, ui: &mut egui::Ui) {
+        let focused = self.focused;
+        let mut clicked = None;
+        ui.columns(self.panes.len(), |columns| {
+            for (i, (ui, pane)) in columns.iter_mut().zip(&mut self.panes).enumerate() {
+                if i > 0 {
+                    let rect = ui.max_rect();
+                    let x = rect.left() - ui.spacing().item_spacing.x / 2.0;
+                    let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+                    ui.painter().vline(x, rect.y_range(), stroke);
+                }
+                if ui.rect_contains_pointer(ui.max_rect()) && ui.input(|i| i.pointer.any_pressed()) {
+                    clicked = Some(i);
+                }
+                ui.push_id(i, |ui| pane.draw(ui, i == focused));
+            }
+        });
+        if let Some(i) = clicked {
+            self.focus(i);
+        }
+    }
+}
@@ -1,54 +1,789 @@
-use crate::config::parse_config;
-use crate::types::{Action, Line, ShellEvent, ShellState, TerminalColor};
+use crate::config::{parse_config, parse_config_with_palette, ConfigParseError};
+use crate::types::{Action, CellAttr, CwdSource, HistorySearch, PasteMode, RunningCommand, Line, ShellEvent, ShellState, TerminalColor, FONT_SIZE_STEP, MAX_FONT_SIZE, MIN_FONT_SIZE, MIN_OPACITY, OPACITY_STEP};
+use crate::ansi::{AnsiParser, Output as AnsiOutput};
 use crate::backend::ProcessBackend;
-use crate::utils::{get_default_config_path, tokenize_command};
-use crossbeam_channel::{Receiver, Sender};
+use crate::utils::{
+    ansi_color, char_to_byte, delete_char_at, delete_char_before, delete_word_before, format_date, format_progress_bar, format_size, format_system_time, get_default_config_path, get_themes_dir,
+    glob_match, ls_color, parse_ls_colors, resolve, resolve_theme_path, seq_values, SeqError, SEQ_MAX_VALUES, truncate_chars, tokenize_command, tokenize_command_checked, DEFAULT_DATE_FORMAT,
+};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use std::collections::VecDeque;
 use std::env;
 // use std::io; // Removed unused import
 // use std::process::{Command, Stdio}; // Removed unused imports
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum LsSort {
+    #[default]
+    Name,
+    Time,
+    Size,
+}
+
+#[derive(Debug)]
+struct LsOptions {
+    show_all: bool,
+    long_format: bool,
+    human_readable: bool,
+    classify: bool,
+    reverse: bool,
+    sort: LsSort,
+    target_path: String,
+}
+
+impl LsOptions {
+    fn parse(args: &[String]) -> Self {
+        let mut opts = Self {
+            show_all: false,
+            long_format: false,
+            human_readable: false,
+            classify: false,
+            reverse: false,
+            sort: LsSort::Name,
+            target_path: ".".to_string(),
+        };
+
+        for arg in args {
+            if arg == "--all" {
+                opts.show_all = true;
+            } else if let Some(flags) = arg.strip_prefix('-') {
+                for flag in flags.chars() {
+                    match flag {
+                        'a' => opts.show_all = true,
+                        'l' => opts.long_format = true,
+                        'h' => opts.human_readable = true,
+                        'F' => opts.classify = true,
+                        'r' => opts.reverse = true,
+                        't' => opts.sort = LsSort::Time,
+                        'S' => opts.sort = LsSort::Size,
+                        _ => {}
+                    }
+                }
+            } else {
+                opts.target_path = arg.clone();
+            }
+        }
+
+        opts
+    }
+}
+
+/// Builtin commands as (name, usage, description), in the order `help` lists them.
+pub const BUILTINS: &[(&str, &str, &str)] = &[
+    ("cat", "cat <file>...", "Print file contents"),
+    ("cd", "cd [dir]", "Change the working directory"),
+    ("clear", "clear", "Clear the screen"),
+    ("config", "config load|validate [path] | config dump", "Reload, check or print the runtime config"),
+    ("cp", "cp <source> <dest>", "Copy a file"),
+    ("date", "date [+format]", "Print the local time, optionally with a strftime format"),
+    ("du", "du [-h] [-d <depth>] [path]...", "Show how much space directories take up"),
+    ("echo", "echo [text]...", "Print arguments"),
+    ("env", "env", "List environment variables"),
+    ("exit", "exit", "Quit the terminal"),
+    ("export", "export NAME[=value]...", "Set an environment variable"),
+    ("find", "find [dir] [-name <glob>] [-type f|d] [-maxdepth <n>]", "Search a directory tree"),
+    ("help", "help [name]", "List builtins or show usage for one"),
+    ("ls", "ls [-a] [-l] [-h] [-F] [-t|-S] [-r] [dir]", "List directory contents"),
+    ("macro", "macro stats [name]", "Show how often macros ran, what they emitted and their last error"),
+    ("metrics", "metrics", "Show this pane's rendering counters and every macro's stats"),
+    ("mkdir", "mkdir <dir>...", "Create directories"),
+    ("mv", "mv <source> <dest>", "Move or rename a file"),
+    ("progress", "progress [steps]", "Draw a demo progress bar that rewrites its own line"),
+    ("pwd", "pwd", "Print the working directory"),
+    ("reset", "reset", "Restore the default prompt, colors, font size and opacity"),
+    ("rm", "rm [-r] [-f] [--] <path>...", "Remove files or directories"),
+    ("seq", "seq [first [step]] last", "Print a range of numbers, one per line"),
+    ("sleep", "sleep <seconds>", "Hold back the next commands for a while (Ctrl+C cancels)"),
+    ("stat", "stat <path>...", "Show file metadata"),
+    ("test", "test <expr> | [ <expr> ]", "Check files, strings or integers and set the exit status"),
+    ("theme", "theme load <name> | theme list | theme preview", "Apply, list or preview color themes"),
+    ("touch", "touch <file>...", "Create a file or update its mtime"),
+    ("true", "true | false", "Set the exit status to success or failure"),
+    ("unset", "unset NAME...", "Remove an environment variable"),
+    ("watch", "watch [-n <seconds>] <command>...", "Re-run a command periodically, redrawing its output in place (Ctrl+C stops)"),
+];
+
+/// Builtins that work on the local filesystem, and so would miss the files a remote backend sees.
+const LOCAL_FILE_BUILTINS: &[&str] = &["cat", "cp", "du", "find", "ls", "mkdir", "mv", "rm", "stat", "touch"];
+
+const FIND_DEFAULT_MAX_DEPTH: usize = 32;
+
+const PROGRESS_DEFAULT_STEPS: usize = 20;
+const PROGRESS_BAR_WIDTH: usize = 30;
+const PROGRESS_STEP_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+// How often running commands are checked for completion while `notify_after_secs` is set
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const WATCH_DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+// How often a waiting `watch` checks whether it was interrupted
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FindType {
+    File,
+    Dir,
+}
+
+#[derive(Debug)]
+struct FindOptions {
+    root: String,
+    name: Option<String>,
+    file_type: Option<FindType>,
+    max_depth: usize,
+}
+
+impl FindOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = Self {
+            root: ".".to_string(),
+            name: None,
+            file_type: None,
+            max_depth: FIND_DEFAULT_MAX_DEPTH,
+        };
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-name" => {
+                    let pattern = iter.next().ok_or("missing argument to -name")?;
+                    opts.name = Some(pattern.clone());
+                }
+                "-type" => match iter.next().map(|s| s.as_str()) {
+                    Some("f") => opts.file_type = Some(FindType::File),
+                    Some("d") => opts.file_type = Some(FindType::Dir),
+                    Some(other) => return Err(format!("unknown argument to -type: {}", other)),
+                    None => return Err("missing argument to -type".to_string()),
+                },
+                "-maxdepth" => {
+                    let depth = iter.next().ok_or("missing argument to -maxdepth")?;
+                    opts.max_depth = depth
+                        .parse()
+                        .map_err(|_| format!("invalid argument to -maxdepth: {}", depth))?;
+                }
+                other if other.starts_with('-') => return Err(format!("unknown predicate: {}", other)),
+                other => opts.root = other.to_string(),
+            }
+        }
+
+        Ok(opts)
+    }
+
+    fn matches(&self, path: &std::path::Path, is_dir: bool) -> bool {
+        match self.file_type {
+            Some(FindType::File) if is_dir => return false,
+            Some(FindType::Dir) if !is_dir => return false,
+            _ => {}
+        }
+        match &self.name {
+            Some(pattern) => {
+                let file_name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| path.to_string_lossy().to_string());
+                glob_match(pattern, &file_name)
+            }
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct RmOptions {
+    recursive: bool,
+    force: bool,
+    paths: Vec<String>,
+}
+
+impl RmOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = Self::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--" => opts.paths.extend(iter.by_ref().cloned()),
+                other if other.starts_with("--") => return Err(format!("unrecognized option: {}", other)),
+                other if other.starts_with('-') && other.len() > 1 => {
+                    for flag in other[1..].chars() {
+                        match flag {
+                            'r' | 'R' => opts.recursive = true,
+                            'f' => opts.force = true,
+                            _ => return Err(format!("invalid option: -{}", flag)),
+                        }
+                    }
+                }
+                other => opts.paths.push(other.to_string()),
+            }
+        }
+        Ok(opts)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct DuOptions {
+    paths: Vec<String>,
+    human_readable: bool,
+    // Directories deeper than this still count toward their parents but aren't listed
+    max_depth: usize,
+}
+
+impl DuOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = Self { paths: Vec::new(), human_readable: false, max_depth: usize::MAX };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-h" => opts.human_readable = true,
+                "-d" => {
+                    let depth = iter.next().ok_or("missing argument to -d")?;
+                    opts.max_depth = depth.parse().map_err(|_| format!("invalid depth: {}", depth))?;
+                }
+                other if other.starts_with('-') && other.len() > 1 => return Err(format!("unknown option: {}", other)),
+                other => opts.paths.push(other.to_string()),
+            }
+        }
+        if opts.paths.is_empty() {
+            opts.paths.push(".".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// Visits `root` and everything under it down to `max_depth`, parents before children and
+// siblings in name order. Symlinks are visited but not followed. Directories that can't be
+// read go to `unreadable` and the walk carries on; only a missing root is an error.
+fn walk_tree(
+    root: &std::path::Path,
+    max_depth: usize,
+    mut visit: impl FnMut(&std::path::Path, &std::fs::Metadata, usize),
+    mut unreadable: impl FnMut(&std::path::Path, std::io::Error),
+) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(root)?;
+    // Explicit stack instead of recursion so deep trees can't blow the thread stack.
+    let mut stack = vec![(root.to_path_buf(), metadata, 0usize)];
+    while let Some((path, metadata, depth)) = stack.pop() {
+        visit(&path, &metadata, depth);
+        if !metadata.is_dir() || depth >= max_depth {
+            continue;
+        }
+        match std::fs::read_dir(&path) {
+            Ok(entries) => {
+                let mut children: Vec<_> = entries
+                    .filter_map(Result::ok)
+                    .filter_map(|e| Some((e.path(), e.metadata().ok()?, depth + 1)))
+                    .collect();
+                // Reverse-sorted so the stack pops entries in name order.
+                children.sort_by(|a, b| b.0.cmp(&a.0));
+                stack.extend(children);
+            }
+            Err(e) => unreadable(&path, e),
+        }
+    }
+    Ok(())
+}
+
+// How to show `path`, found by walking `real_root`, to a user who typed `shown` as the root:
+// `shown` itself, or `shown` followed by the rest of the path
+fn display_path(shown: &str, real_root: &std::path::Path, path: &std::path::Path) -> std::path::PathBuf {
+    match path.strip_prefix(real_root) {
+        Ok(rest) if rest.as_os_str().is_empty() => std::path::PathBuf::from(shown),
+        Ok(rest) => std::path::Path::new(shown).join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Sizes of `root` and each directory under it, listed like `du`: children before their
+// parent, with `root` last. Unreadable directories are reported as warnings.
+fn disk_usage(root: &std::path::Path, warnings: &mut Vec<String>) -> std::io::Result<Vec<(std::path::PathBuf, usize, u64)>> {
+    // Pre-order, so every directory's ancestors come before it
+    let mut dirs: Vec<(std::path::PathBuf, usize, u64)> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    walk_tree(
+        root,
+        usize::MAX,
+        |path, metadata, depth| {
+            // Leaving any directories this entry isn't under
+            open.truncate(depth);
+            if metadata.is_dir() {
+                open.push(dirs.len());
+                dirs.push((path.to_path_buf(), depth, 0));
+            } else {
+                for &i in &open {
+                    dirs[i].2 += metadata.len();
+                }
+                if depth == 0 {
+                    dirs.push((path.to_path_buf(), 0, metadata.len()));
+                }
+            }
+        },
+        |path, e| warnings.push(format!("du: {}: {}", path.display(), e)),
+    )?;
+
+    // Post-order: a directory is listed once the walk has moved past everything under it
+    let mut listed = Vec::with_capacity(dirs.len());
+    let mut pending: Vec<(std::path::PathBuf, usize, u64)> = Vec::new();
+    for dir in dirs {
+        while pending.last().is_some_and(|(_, depth, _)| *depth >= dir.1) {
+            listed.extend(pending.pop());
+        }
+        pending.push(dir);
+    }
+    listed.extend(pending.into_iter().rev());
+    Ok(listed)
+}
+
+fn is_valid_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// How a builtin that only reports a status finished, as a shell exit code would say it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CommandStatus {
+    Success,
+    Failure,
+    // Bad usage, such as a malformed `test` expression
+    Error,
+    // The command couldn't be started at all (not found, not executable)
+    NotRun,
+}
+
+impl CommandStatus {
+    fn code(self) -> i32 {
+        match self {
+            CommandStatus::Success => 0,
+            CommandStatus::Failure => 1,
+            CommandStatus::Error => 2,
+            CommandStatus::NotRun => 127,
+        }
+    }
+}
+
+// Prints `text` in the error color and marks the command as failed, so `--exec` and `--batch`
+// exit nonzero
+fn report_error(s: &mut ShellState, text: &str, output_tx: &Sender<ShellEvent>) {
+    let op = s.screen.push_line(Line::from_string(text, s.error_color));
+    let _ = output_tx.send(ShellEvent::Operation(op));
+    s.last_status = CommandStatus::Failure.code();
+}
+
+// Prints a builtin's usage after it was called wrongly, with the status for bad usage
+fn report_usage(s: &mut ShellState, usage: &str, color: TerminalColor, output_tx: &Sender<ShellEvent>) {
+    let op = s.screen.push_line(Line::from_string(usage, color));
+    let _ = output_tx.send(ShellEvent::Operation(op));
+    s.last_status = CommandStatus::Error.code();
+}
+
+// Evaluates a `test` expression: a single string, a unary file/string check, a binary
+// string or integer comparison, any of them negated with a leading `!`. File checks are
+// relative to `cwd`.
+fn evaluate_test(args: &[String], cwd: &str) -> Result<bool, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let integer = |s: &str| s.trim().parse::<i64>().map_err(|_| format!("{}: integer expression expected", s));
+    match args[..] {
+        [] => Ok(false),
+        ["!", ref rest @ ..] if !rest.is_empty() => {
+            let rest: Vec<String> = rest.iter().map(|s| s.to_string()).collect();
+            evaluate_test(&rest, cwd).map(|result| !result)
+        }
+        [s] => Ok(!s.is_empty()),
+        [op, operand] => match op {
+            "-e" => Ok(std::fs::metadata(resolve(cwd, operand)).is_ok()),
+            "-f" => Ok(std::fs::metadata(resolve(cwd, operand)).is_ok_and(|m| m.is_file())),
+            "-d" => Ok(std::fs::metadata(resolve(cwd, operand)).is_ok_and(|m| m.is_dir())),
+            "-z" => Ok(operand.is_empty()),
+            "-n" => Ok(!operand.is_empty()),
+            _ => Err(format!("{}: unary operator expected", op)),
+        },
+        [left, op, right] => match op {
+            "=" | "==" => Ok(left == right),
+            "!=" => Ok(left != right),
+            "-eq" => Ok(integer(left)? == integer(right)?),
+            "-ne" => Ok(integer(left)? != integer(right)?),
+            "-lt" => Ok(integer(left)? < integer(right)?),
+            "-le" => Ok(integer(left)? <= integer(right)?),
+            "-gt" => Ok(integer(left)? > integer(right)?),
+            "-ge" => Ok(integer(left)? >= integer(right)?),
+            _ => Err(format!("{}: binary operator expected", op)),
+        },
+        _ => Err("too many arguments".to_string()),
+    }
+}
+
+// Splits the leading `NAME=value` words off a command line, as in `FOO=bar cmd args`
+fn split_env_assignments(parts: &[String]) -> (Vec<(String, String)>, &[String]) {
+    let count = parts
+        .iter()
+        .take_while(|part| part.split_once('=').is_some_and(|(name, _)| is_valid_env_name(name)))
+        .count();
+    let assignments = parts[..count]
+        .iter()
+        .filter_map(|part| part.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    (assignments, &parts[count..])
+}
+
+#[cfg(unix)]
+fn is_executable(_path: &std::path::Path, metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path, metadata: &std::fs::Metadata) -> bool {
+    metadata.is_file()
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| matches!(ext.to_ascii_lowercase().as_str(), "exe" | "bat" | "cmd" | "com"))
+}
+
+#[cfg(unix)]
+fn stat_permissions(metadata: &std::fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode();
+    let mut rwx = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        rwx.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        rwx.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        rwx.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    format!("({:04o}/{})", mode & 0o7777, rwx)
+}
+
+#[cfg(windows)]
+fn stat_permissions(metadata: &std::fs::Metadata) -> String {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    let readonly = metadata.permissions().readonly();
+    let hidden = metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0;
+    format!("readonly={} hidden={}", readonly, hidden)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn stat_permissions(metadata: &std::fs::Metadata) -> String {
+    format!("readonly={}", metadata.permissions().readonly())
+}
+
+/// Enters the startup directory: `--cwd` first, then the config's `default_cwd`, then the
+/// directory the process was started in. A candidate that can't be entered is reported and
+/// the next one is tried. Returns the directory, where it came from, and any errors.
+pub fn resolve_initial_cwd(backend: &dyn ProcessBackend, cli: Option<&str>, config: Option<&str>) -> (String, CwdSource, Vec<String>) {
+    let mut errors = Vec::new();
+    let process_cwd = env::current_dir().map(|p| p.to_string_lossy().to_string()).unwrap_or_else(|_| ".".to_string());
+    for (dir, source) in [(cli, CwdSource::Cli), (config, CwdSource::Config)] {
+        let Some(dir) = dir else { continue };
+        match backend.change_directory(&process_cwd, dir) {
+            Ok(cwd) => return (cwd, source, errors),
+            Err(e) => errors.push(format!("Failed to start in {} from {}: {}", dir, source.describe(), e)),
+        }
+    }
+    (process_cwd, CwdSource::Process, errors)
+}
+
+/// Starts the shell thread. `on_action_done` runs after every action, so the UI can redraw
+/// changes that don't produce a `ShellEvent` (input line edits, mode switches).
 pub fn spawn_shell_thread(
     action_rx: Receiver<Action>,
     output_tx: Sender<ShellEvent>,
     thread_state: Arc<Mutex<ShellState>>,
     backend: Box<dyn ProcessBackend>,
+    on_action_done: impl Fn() + Send + 'static,
 ) {
+    let backend: Arc<dyn ProcessBackend> = Arc::from(backend);
     thread::spawn(move || {
+        // Commands submitted during `sleep`, run in order once it is over
+        let mut deferred: VecDeque<String> = VecDeque::new();
         loop {
-            let action = match action_rx.recv() {
+            let sleep_until = thread_state.lock().unwrap().sleep_until;
+            if sleep_until.is_none()
+                && let Some(cmd) = deferred.pop_front()
+            {
+                execute_command(&cmd, &thread_state, &output_tx, &backend);
+                on_action_done();
+                continue;
+            }
+
+            // While sleeping, wake up at the deadline even if nothing arrives. Commands that may
+            // need a completion notice are checked on every wakeup.
+            let watching = {
+                let s = thread_state.lock().unwrap();
+                s.notify_after_secs.is_some() && !s.running.is_empty()
+            };
+            let poll_at = watching.then(|| Instant::now() + JOB_POLL_INTERVAL);
+            let deadline = match (sleep_until, poll_at) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            let received = match deadline {
+                Some(deadline) => action_rx.recv_deadline(deadline),
+                None => action_rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let action = match received {
                 Ok(a) => a,
-                Err(_) => break, // Channel closed
+                Err(RecvTimeoutError::Timeout) => {
+                    if sleep_until.is_some_and(|deadline| Instant::now() >= deadline) {
+                        thread_state.lock().unwrap().sleep_until = None;
+                    }
+                    reap_finished(&thread_state, &output_tx);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break, // Channel closed
             };
 
             match action {
                 Action::AppendChar(ch) => {
                     let mut s = thread_state.lock().unwrap();
-                    s.input_buffer.push(ch);
-                    // For now, simple echo: we don't redraw the whole line, just push char to current line logic?
-                    // Actually, the current line logic is "push_line".
-                    // Let's just update the buffer. The renderer will need to show the prompt + buffer.
+                    // While searching, typing edits the query instead of the input line
+                    if let Some(search) = &mut s.history_search {
+                        search.query.push(ch);
+                        search.skip = 0;
+                    } else {
+                        let cursor = s.clamped_input_cursor();
+                        let byte_idx = char_to_byte(&s.input_buffer, cursor);
+                        s.input_buffer.insert(byte_idx, ch);
+                        s.input_cursor = cursor + 1;
+                    }
                 }
                 Action::Backspace => {
                     let mut s = thread_state.lock().unwrap();
-                    s.input_buffer.pop();
+                    let cursor = s.clamped_input_cursor();
+                    if let Some(search) = &mut s.history_search {
+                        search.query.pop();
+                        search.skip = 0;
+                    } else {
+                        s.input_cursor = delete_char_before(&mut s.input_buffer, cursor);
+                    }
+                }
+                Action::Delete => {
+                    let mut s = thread_state.lock().unwrap();
+                    // Nothing after the cursor to delete in a search query
+                    if s.history_search.is_none() {
+                        let cursor = s.clamped_input_cursor();
+                        s.input_cursor = delete_char_at(&mut s.input_buffer, cursor);
+                    }
+                }
+                Action::CursorLeft => {
+                    let mut s = thread_state.lock().unwrap();
+                    s.input_cursor = s.clamped_input_cursor().saturating_sub(1);
+                }
+                Action::CursorRight => {
+                    let mut s = thread_state.lock().unwrap();
+                    let len = s.input_buffer.chars().count();
+                    s.input_cursor = (s.clamped_input_cursor() + 1).min(len);
+                }
+                Action::SetInputCursor(col) => {
+                    let mut s = thread_state.lock().unwrap();
+                    s.input_cursor = col.min(s.input_buffer.chars().count());
+                }
+                Action::MoveCursor(d_row, d_col) => {
+                    let mut s = thread_state.lock().unwrap();
+                    let cursor = s.moved_cursor(d_row, d_col);
+                    let op = s.screen.set_cursor(cursor);
+                    let _ = output_tx.send(ShellEvent::Operation(op));
+                    drop(s);
+                    // Skip the prompt resync below, which would undo the move
+                    on_action_done();
+                    continue;
+                }
+                Action::ReverseSearch => {
+                    let mut s = thread_state.lock().unwrap();
+                    let ShellState { history, history_search, .. } = &mut *s;
+                    match history_search {
+                        None => *history_search = Some(HistorySearch::default()),
+                        Some(search) => {
+                            // Only step back if there is an older match to land on
+                            let older = HistorySearch { query: search.query.clone(), skip: search.skip + 1 };
+                            if older.find(history).is_some() {
+                                *search = older;
+                            }
+                        }
+                    }
+                }
+                Action::AcceptSearch => {
+                    let mut s = thread_state.lock().unwrap();
+                    if let Some(search) = s.history_search.take()
+                        && let Some(idx) = search.find(&s.history)
+                    {
+                        s.input_buffer = s.history[idx].clone();
+                        s.input_cursor = s.input_buffer.chars().count();
+                    }
+                }
+                Action::CancelSearch => {
+                    thread_state.lock().unwrap().history_search = None;
+                }
+                Action::FontSizeUp | Action::FontSizeDown => {
+                    let mut s = thread_state.lock().unwrap();
+                    let step = if action == Action::FontSizeUp { FONT_SIZE_STEP } else { -FONT_SIZE_STEP };
+                    s.font_size = (s.font_size + step).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+                }
+                Action::FontSizeReset => {
+                    let mut s = thread_state.lock().unwrap();
+                    s.font_size = s.configured_font_size;
+                }
+                Action::OpacityUp | Action::OpacityDown => {
+                    let mut s = thread_state.lock().unwrap();
+                    let step = if action == Action::OpacityUp { OPACITY_STEP } else { -OPACITY_STEP };
+                    // Round to the step so repeated nudges don't drift to 0.8999999
+                    let opacity = ((s.opacity + step) / OPACITY_STEP).round() * OPACITY_STEP;
+                    s.opacity = opacity.clamp(MIN_OPACITY, 1.0);
+                }
+                Action::ClearLine => {
+                    let mut s = thread_state.lock().unwrap();
+                    s.input_buffer.clear();
+                    s.input_cursor = 0;
+                }
+                Action::DeleteWord => {
+                    let mut s = thread_state.lock().unwrap();
+                    let cursor = s.clamped_input_cursor();
+                    let ShellState { input_buffer, word_chars, .. } = &mut *s;
+                    s.input_cursor = delete_word_before(input_buffer, cursor, word_chars);
+                }
+                Action::MoveToStart => {
+                    thread_state.lock().unwrap().input_cursor = 0;
+                }
+                Action::MoveToEnd => {
+                    let mut s = thread_state.lock().unwrap();
+                    s.input_cursor = s.input_buffer.chars().count();
                 }
                 Action::Submit => {
-                    let cmd_line = {
+                    let cmd_lines = {
                         let mut s = thread_state.lock().unwrap();
-                        let line = std::mem::take(&mut s.input_buffer);
-                        
+                        let mut line = std::mem::take(&mut s.input_buffer);
+                        s.input_cursor = 0;
+                        let truncated = truncate_chars(&mut line, s.max_input_length);
+
+                        // A new command starts on a clean screen; answers and continuation lines don't
+                        let starts_command = s.pending_confirmation.is_none() && s.continuation.is_none() && !line.trim().is_empty();
+                        if s.clear_on_command && starts_command {
+                            let op = s.screen.clear();
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+
                         // Echo the final submitted command
-                        let prompt = s.prompt.clone();
-                        let prompt_color = s.prompt_color;
+                        let prompt = s.active_prompt().to_string();
+                        let prompt_color = s.active_prompt_color();
                         let op = s.screen.push_line(Line::from_string(&format!("{}{}", prompt, line), prompt_color));
                         let _ = output_tx.send(ShellEvent::Operation(op));
-                        line
+                        if truncated {
+                            let message = format!("Input cut to its first {} characters (max_input_length)", s.max_input_length);
+                            let error_color = s.error_color;
+                            let op = s.screen.push_line(Line::from_string(&message, error_color));
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+
+                        match s.pending_confirmation.take() {
+                            // The line answers the question rather than being a command itself
+                            Some(pending) => {
+                                if matches!(line.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+                                    Some(pending)
+                                } else {
+                                    let text_color = s.active_text_color();
+                                    let op = s.screen.push_line(Line::from_string("Cancelled", text_color));
+                                    let _ = output_tx.send(ShellEvent::Operation(op));
+                                    None
+                                }
+                            }
+                            None => {
+                                // Lines of a command with an unclosed quote or trailing backslash
+                                // are collected until it is complete
+                                let mut line = match s.continuation.take() {
+                                    Some(previous) => format!("{}\n{}", previous, line),
+                                    None => line,
+                                };
+                                // Continuation lines each fit, but together they might not
+                                if truncate_chars(&mut line, s.max_input_length) {
+                                    let message = format!("Command cut to its first {} characters (max_input_length)", s.max_input_length);
+                                    let error_color = s.error_color;
+                                    let op = s.screen.push_line(Line::from_string(&message, error_color));
+                                    let _ = output_tx.send(ShellEvent::Operation(op));
+                                }
+                                if tokenize_command_checked(&line).is_err() {
+                                    s.continuation = Some(line);
+                                    None
+                                } else {
+                                    if !line.trim().is_empty() {
+                                        s.last_command = line.trim().to_string();
+                                        s.refresh_window_title();
+                                        if s.history.last() != Some(&line) {
+                                            s.history.push(line.clone());
+                                        }
+                                    }
+                                    match confirmation_question(&line) {
+                                        Some(question) if s.confirm_destructive => {
+                                            let text_color = s.active_text_color();
+                                            let op = s.screen.push_line(Line::from_string(&question, text_color));
+                                            let _ = output_tx.send(ShellEvent::Operation(op));
+                                            s.pending_confirmation = Some(vec![line]);
+                                            None
+                                        }
+                                        _ => Some(vec![line]),
+                                    }
+                                }
+                            }
+                        }
                     };
 
-                    execute_command(&cmd_line, &thread_state, &output_tx, &*backend);
+                    {
+                        // The next pasted line comes up for its own Enter, unless this one
+                        // asked a question that Enter must answer first
+                        let mut s = thread_state.lock().unwrap();
+                        if s.pending_confirmation.is_none()
+                            && let Some(next) = s.pasted_lines.pop_front()
+                        {
+                            s.input_cursor = next.chars().count();
+                            s.input_buffer = next;
+                        }
+                    }
+
+                    for cmd_line in cmd_lines.into_iter().flatten() {
+                        // A `sleep` among confirmed lines holds back the ones after it
+                        if thread_state.lock().unwrap().sleep_until.is_some() {
+                            deferred.push_back(cmd_line);
+                        } else {
+                            execute_command(&cmd_line, &thread_state, &output_tx, &backend);
+                        }
+                    }
+                }
+                Action::Paste(text) => {
+                    let mut s = thread_state.lock().unwrap();
+                    // Blank lines would only cost an extra Enter each
+                    let mut lines: VecDeque<String> =
+                        text.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect();
+                    match s.multiline_paste {
+                        PasteMode::Step => {
+                            // The first line joins whatever is already typed; the rest wait their turn
+                            if let Some(first) = lines.pop_front() {
+                                let cursor = s.clamped_input_cursor();
+                                let byte_idx = char_to_byte(&s.input_buffer, cursor);
+                                s.input_buffer.insert_str(byte_idx, &first);
+                                s.input_cursor = cursor + first.chars().count();
+                            }
+                            if !lines.is_empty() {
+                                let message = format!("{} more pasted line(s) follow, one per Enter (Ctrl+C drops them)", lines.len());
+                                s.pasted_lines.extend(lines);
+                                s.notify(&message, TerminalColor::GRAY, &output_tx);
+                            }
+                        }
+                        PasteMode::Confirm => {
+                            let commands = join_continued_lines(lines);
+                            let text_color = s.active_text_color();
+                            let mut shown = vec![format!("Pasted {} command(s):", commands.len())];
+                            shown.extend(commands.iter().flat_map(|c| c.lines()).map(|line| format!("  {}", line)));
+                            shown.push("Run them? [y/n]".to_string());
+                            for line in shown {
+                                let op = s.screen.push_line(Line::from_string(&line, text_color));
+                                let _ = output_tx.send(ShellEvent::Operation(op));
+                            }
+                            s.pending_confirmation = Some(commands);
+                        }
+                    }
                 }
                 Action::Clear => {
                     let mut s = thread_state.lock().unwrap();
@@ -58,23 +793,502 @@ pub fn spawn_shell_thread(
                 Action::ChangeMode(new_mode) => {
                     let mut s = thread_state.lock().unwrap();
                     s.mode = new_mode;
-                    s.window_title_full = format!("[{}] {}", s.mode.name(), s.window_title_base);
-                    s.title_updated = true;
+                    s.refresh_window_title();
                 }
                 Action::RunCommand(cmd) => {
-                    execute_command(&cmd, &thread_state, &output_tx, &*backend);
+                    if sleep_until.is_some() {
+                        deferred.push_back(cmd);
+                    } else {
+                        execute_command(&cmd, &thread_state, &output_tx, &backend);
+                    }
+                }
+                Action::ReloadConfig { auto } => {
+                    if let Some(path) = get_default_config_path() {
+                        load_config(&path, auto, &thread_state, &output_tx, backend.as_ref());
+                    }
+                }
+                Action::Interrupt => {
+                    deferred.clear();
+                    let mut s = thread_state.lock().unwrap();
+                    s.sleep_until = None;
+                    s.continuation = None;
+                    s.pasted_lines.clear();
+                    s.interrupts += 1;
+                    s.kill_running();
                 }
                 _ => {}
             }
+
+            // Keep the screen cursor on the prompt so the UI only repaints the cursor for edits
+            let cursor_op = thread_state.lock().unwrap().sync_prompt_cursor();
+            if let Some(op) = cursor_op {
+                let _ = output_tx.send(ShellEvent::Operation(op));
+            }
+            on_action_done();
         }
     });
 }
 
+/// Runs `command` every `interval` until interrupted, redrawing its output under a header row
+/// in place. Output that grows past the rows it owns is cut off if something else was printed below.
+fn spawn_watch(
+    interval: Duration,
+    command: Vec<String>,
+    thread_state: &Arc<Mutex<ShellState>>,
+    output_tx: &Sender<ShellEvent>,
+    backend: &Arc<dyn ProcessBackend>,
+    color: TerminalColor,
+) {
+    let (generation, clears, header_row) = {
+        let mut s = thread_state.lock().unwrap();
+        let header = format!("Every {}s: {}", interval.as_secs_f64(), command.join(" "));
+        let op = s.screen.push_line(Line::from_string(&header, color));
+        let _ = output_tx.send(ShellEvent::Operation(op));
+        (s.interrupts, s.screen.clears, s.screen.lines.len() - 1)
+    };
+
+    let state = Arc::clone(thread_state);
+    let tx = output_tx.clone();
+    let backend = Arc::clone(backend);
+    thread::spawn(move || {
+        let interrupted = || state.lock().unwrap().interrupts != generation;
+        // Output rows below the header written so far
+        let mut rows = 0;
+        loop {
+            let text = match backend.capture(&command[0], &command[1..], &state) {
+                Ok(bytes) => {
+                    let text: Vec<u8> = AnsiParser::default()
+                        .feed(&bytes)
+                        .into_iter()
+                        .filter_map(|out| match out {
+                            AnsiOutput::Text(text) => Some(text),
+                            AnsiOutput::Control(_) => None,
+                        })
+                        .flatten()
+                        .collect();
+                    String::from_utf8_lossy(&text).into_owned()
+                }
+                Err(e) => format!("watch: {}: {}", command[0], e),
+            };
+
+            {
+                let mut s = state.lock().unwrap();
+                if s.interrupts != generation || s.screen.clears != clears {
+                    break; // Interrupted, or the screen was cleared underneath us
+                }
+                let lines: Vec<&str> = text.lines().collect();
+                // Blank out rows left over from a longer previous run
+                for i in 0..lines.len().max(rows) {
+                    let row = header_row + 1 + i;
+                    let line = Line::from_string(lines.get(i).copied().unwrap_or(""), color);
+                    let op = if i < rows && row < s.screen.lines.len() {
+                        s.screen.update_line(row, line)
+                    } else if row == s.screen.lines.len() {
+                        rows += 1;
+                        s.screen.push_line(line)
+                    } else {
+                        break;
+                    };
+                    let _ = tx.send(ShellEvent::Operation(op));
+                }
+            }
+
+            let deadline = Instant::now() + interval;
+            while Instant::now() < deadline {
+                if interrupted() {
+                    return;
+                }
+                thread::sleep(WATCH_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+            }
+        }
+    });
+}
+
+/// Loads the config at `path` into the pane. `config load` also moves the pane to
+/// `default_cwd` through `backend`; an `auto` reload (the file was saved) leaves the cwd alone
+/// and reports success the way `config_reload_quiet` says.
+pub fn load_config(
+    path: &std::path::Path,
+    auto: bool,
+    thread_state: &Arc<Mutex<ShellState>>,
+    output_tx: &Sender<ShellEvent>,
+    backend: &dyn ProcessBackend,
+) {
+    let error_color = thread_state.lock().unwrap().error_color;
+    match parse_config(path) {
+        Ok((update, warnings)) => {
+            let mut actual_cwd = None;
+            let mut cwd_error = None;
+            // `--cwd` outranks default_cwd, on reloads as well as at startup
+            let (started_with_cli_cwd, current_dir) = {
+                let s = thread_state.lock().unwrap();
+                (s.cwd_source == CwdSource::Cli, s.current_dir.clone())
+            };
+            if let Some(new_cwd) = &update.default_cwd
+                && !started_with_cli_cwd
+                && !auto
+            {
+                match backend.change_directory(&current_dir, new_cwd) {
+                    Ok(cwd) => actual_cwd = Some(cwd),
+                    Err(e) => {
+                        cwd_error = Some(format!(
+                            "Failed to set default_cwd to {}: {}",
+                            new_cwd, e
+                        ));
+                    }
+                }
+            }
+
+            let theme = update.theme.clone();
+            let mut message = format!("Config loaded from: {}", path.display());
+            {
+                let mut s = thread_state.lock().unwrap();
+                if let Some(cwd_str) = actual_cwd {
+                    message.push_str(&format!(" (default_cwd: {})", cwd_str));
+                    s.current_dir = cwd_str;
+                    s.cwd_source = CwdSource::Config;
+                }
+                s.apply_config(update);
+                push_config_warnings(&mut s, &warnings, output_tx);
+            }
+
+            if let Some(e) = cwd_error {
+                let mut s = thread_state.lock().unwrap();
+                report_error(&mut s, &e, output_tx);
+            }
+            let mut s = thread_state.lock().unwrap();
+            if auto {
+                s.notify_reload(&message, TerminalColor::GOLD, output_tx);
+            } else {
+                s.notify(&message, TerminalColor::GOLD, output_tx);
+            }
+            drop(s);
+
+            if let Some(name) = theme {
+                load_theme(&name, auto, thread_state, output_tx);
+            }
+        }
+        Err(e) => {
+            let mut s = thread_state.lock().unwrap();
+            let message = config_error_lines(path, e.as_ref(), "Failed to load config").join("\n");
+            s.notify(&message, error_color, output_tx);
+            s.last_status = CommandStatus::Failure.code();
+        }
+    }
+}
+
+/// Applies the palette from a theme file, leaving every non-color setting untouched.
+/// `auto` is set when the theme comes with an automatic config reload, whose messages follow
+/// `config_reload_quiet`.
+fn load_theme(name: &str, auto: bool, thread_state: &Arc<Mutex<ShellState>>, output_tx: &Sender<ShellEvent>) {
+    let Some(path) = resolve_theme_path(name) else {
+        let mut s = thread_state.lock().unwrap();
+        report_error(&mut s, "Error: Could not determine themes directory", output_tx);
+        return;
+    };
+
+    let mut s = thread_state.lock().unwrap();
+    let path = resolve(&s.current_dir, path);
+    match parse_config_with_palette(&path, &s.palette) {
+        Ok((update, warnings)) => {
+            s.apply_config(update.colors_only());
+            push_config_warnings(&mut s, &warnings, output_tx);
+            let message = format!("Theme loaded from: {}", path.display());
+            if auto {
+                s.notify_reload(&message, TerminalColor::GOLD, output_tx);
+            } else {
+                s.notify(&message, TerminalColor::GOLD, output_tx);
+            }
+        }
+        Err(e) => {
+            let error_color = s.error_color;
+            s.notify(&format!("Failed to load theme at {}: {}", path.display(), e), error_color, output_tx);
+            s.last_status = CommandStatus::Failure.code();
+        }
+    }
+}
+
+// Width of the setting names down the left of `theme preview`
+const PREVIEW_LABEL_WIDTH: usize = 16;
+
+/// A sample of every themed element for `theme preview`, each after the name of the setting
+/// that colors it, ending with the 16 ANSI colors programs can pick from.
+fn theme_preview_lines(s: &ShellState) -> Vec<Line> {
+    let text_color = s.active_text_color();
+    let labeled = |label: &str, sample: &str, color: TerminalColor| {
+        let mut line = Line::from_string(&format!("{:<width$}", label, width = PREVIEW_LABEL_WIDTH), TerminalColor::GRAY);
+        line.cells.extend(Line::from_string(sample, color).cells);
+        line
+    };
+    let styled = |label: &str, sample: &str, attrs: CellAttr| {
+        let mut line = labeled(label, sample, text_color);
+        for cell in &mut line.cells[PREVIEW_LABEL_WIDTH..] {
+            cell.attrs = attrs;
+        }
+        line
+    };
+    let mut lines = vec![
+        labeled("prompt_color", &format!("{}ls -F", s.active_prompt()), s.active_prompt_color()),
+        labeled("text_color", "The quick brown fox jumps over the lazy dog", text_color),
+        labeled("directory_color", "src/  docs/  target/", s.directory_color),
+        labeled("symlink_color", "latest@  current@", s.symlink_color),
+        labeled("stderr_color", "warning: unused variable `x`", s.stderr_color),
+        labeled("error_color", "cd: missing: No such file or directory", s.error_color),
+        labeled("cursor_color", "\u{2588}", s.cursor_color),
+        styled("bold", "Bold text", CellAttr { bold: true, ..Default::default() }),
+        styled("underline", "Underlined text", CellAttr { underline: true, ..Default::default() }),
+    ];
+    for (label, indexes) in [("ansi 0-7", 0..8), ("ansi 8-15", 8..16)] {
+        let mut line = labeled(label, "", text_color);
+        for index in indexes {
+            line.cells.extend(Line::from_string("\u{2588}\u{2588}\u{2588} ", ansi_color(index)).cells);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Shows the settings a config or theme file had to skip or clamp.
+fn push_config_warnings(s: &mut ShellState, warnings: &[String], output_tx: &Sender<ShellEvent>) {
+    for warning in warnings {
+        let op = s.screen.push_line(Line::from_string(&format!("Warning: {}", warning), TerminalColor::GRAY));
+        let _ = output_tx.send(ShellEvent::Operation(op));
+    }
+}
+
+/// Describes a failed `parse_config`: syntax errors as `config.lua:LINE:COL: msg` plus the
+/// offending line, anything else as `<context> at <path>: <error>`.
+fn config_error_lines(path: &std::path::Path, e: &(dyn std::error::Error + 'static), context: &str) -> Vec<String> {
+    match e.downcast_ref::<ConfigParseError>() {
+        Some(parse_error) => parse_error.report(path),
+        None => vec![format!("{} at {}: {}", context, path.display(), e)],
+    }
+}
+
+/// Drops commands that have exited from `running`. One that ran for `notify_after_secs` or
+/// longer gets a completion notice: in the pane while the window has focus, otherwise on the
+/// desktop.
+fn reap_finished(thread_state: &Arc<Mutex<ShellState>>, output_tx: &Sender<ShellEvent>) {
+    let mut s = thread_state.lock().unwrap();
+    let mut finished = Vec::new();
+    let mut i = 0;
+    while i < s.running.len() {
+        if s.running[i].handle.is_running() {
+            i += 1;
+        } else {
+            finished.push(s.running.remove(i));
+        }
+    }
+    let Some(threshold) = s.notify_after_secs else { return };
+    let window_focused = s.window_focused;
+    // (command line, body, color) for each notice
+    let mut notices = Vec::new();
+    for mut command in finished {
+        let elapsed = command.started.elapsed();
+        if elapsed.as_secs_f32() < threshold {
+            continue;
+        }
+        let code = command.handle.wait().unwrap_or(-1);
+        let status = match code {
+            0 => "Finished".to_string(),
+            code => format!("Failed with exit code {}", code),
+        };
+        let body = format!("{} after {}s", status, elapsed.as_secs());
+        let color = if code == 0 { TerminalColor::GOLD } else { s.error_color };
+        notices.push((command.command_line, body, color));
+    }
+    // A desktop notification can block for seconds on D-Bus, and the UI locks the state every frame
+    drop(s);
+
+    for (command_line, body, color) in notices {
+        if window_focused || !show_desktop_notification(&command_line, &body) {
+            let mut s = thread_state.lock().unwrap();
+            s.notify(&format!("{}: {}", command_line, body), color, output_tx);
+        }
+        if !window_focused {
+            let _ = output_tx.send(ShellEvent::Bell);
+        }
+    }
+}
+
+// False when the desktop has no notification service to show it
+fn show_desktop_notification(summary: &str, body: &str) -> bool {
+    let shown = notify_rust::Notification::new()
+        .appname("axiomterm")
+        .summary(summary)
+        .body(body)
+        .show();
+    if let Err(e) = &shown {
+        log::debug!("Desktop notification failed, ringing the bell instead: {}", e);
+    }
+    shown.is_ok()
+}
+
+/// The active runtime settings, written in config.lua syntax.
+fn config_dump_lines(s: &ShellState) -> Vec<String> {
+    let mut lines = vec![
+        format!("prompt = {:?}", s.prompt),
+        format!("window_title_format = {:?}", s.window_title_format),
+        format!("font_size = {}", s.font_size),
+        format!("min_font_size = {}", s.min_font_size),
+        format!("high_contrast = {}", s.high_contrast),
+        format!("no_color = {}", !s.color_enabled),
+        format!("clear_on_command = {}", s.clear_on_command),
+        format!("window_background_opacity = {}", s.opacity),
+        format!(
+            "padding = {{ top = {}, right = {}, bottom = {}, left = {} }}",
+            s.padding.top, s.padding.right, s.padding.bottom, s.padding.left
+        ),
+    ];
+    let colors = [
+        ("prompt_color", s.prompt_color),
+        ("text_color", s.text_color),
+        ("directory_color", s.directory_color),
+        ("symlink_color", s.symlink_color),
+        ("stderr_color", s.stderr_color),
+        ("error_color", s.error_color),
+        ("cursor_color", s.cursor_color),
+        ("background_color", s.background_color),
+    ];
+    lines.extend(colors.iter().map(|(key, color)| format!("{} = \"{}\"", key, color.to_hex())));
+    if !s.palette.is_empty() {
+        let entries: Vec<String> = s.palette.iter().map(|(name, color)| format!("{} = \"{}\"", name, color.to_hex())).collect();
+        lines.push(format!("colors = {{ {} }}", entries.join(", ")));
+    }
+    if !s.ls_colors.is_empty() {
+        let entries: Vec<String> = s
+            .ls_colors
+            .iter()
+            .map(|(key, color)| {
+                // Keys like `tar.gz` or `7z` aren't Lua names
+                let is_name = key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !key.starts_with(|c: char| c.is_ascii_digit());
+                let key = if is_name { key.clone() } else { format!("[{:?}]", key) };
+                format!("{} = \"{}\"", key, color.to_hex())
+            })
+            .collect();
+        lines.push(format!("ls_colors = {{ {} }}", entries.join(", ")));
+    }
+    lines.push(format!("hidden_file_dim = {}", s.hidden_file_dim));
+    lines.push(match s.current_line_highlight {
+        Some(color) => format!("current_line_highlight = \"{}\"", color.to_hex()),
+        None => "current_line_highlight = false".to_string(),
+    });
+    lines.push(format!("clickable_links = {}", s.clickable_links));
+    lines.push(format!("copy_on_select = {}", s.copy_on_select));
+    lines.push(format!("word_chars = {:?}", s.word_chars));
+    lines.push(format!("max_input_length = {}", s.max_input_length));
+    lines.push(format!("confirm_destructive = {}", s.confirm_destructive));
+    lines.push(format!("notifications = {:?}", s.notifications.name()));
+    lines.push(format!("config_reload_quiet = {}", s.config_reload_quiet));
+    lines.push(format!("config_reload_debounce = {}", s.config_reload_debounce.as_millis()));
+    lines.push(format!("multiline_paste = {:?}", s.multiline_paste.name()));
+    if let Some(secs) = s.notify_after_secs {
+        lines.push(format!("notify_after_secs = {}", secs));
+    }
+    if let Some(max) = s.max_command_lines {
+        lines.push(format!("max_command_lines = {}", max));
+    }
+    lines.push(format!(
+        "-- started in the directory from {} (--cwd > default_cwd > process cwd); now in {}",
+        s.cwd_source.describe(),
+        s.current_dir
+    ));
+    lines.push(format!("scrollbar = {:?}", s.scrollbar.name()));
+    if let Some(width) = s.scrollbar_width {
+        lines.push(format!("scrollbar_width = {}", width));
+    }
+    if let Some(color) = s.scrollbar_color {
+        lines.push(format!("scrollbar_color = \"{}\"", color.to_hex()));
+    }
+    if let Some(image) = &s.background_image {
+        lines.push(format!("background_image = {:?}", image));
+        lines.push(format!("background_image_opacity = {}", s.background_image_opacity));
+    }
+
+    for def in &s.mode_definitions {
+        let mut line = format!("-- mode {}: {} binding(s)", def.mode.name(), def.bindings.len());
+        if let Some(prompt) = &def.appearance.prompt {
+            line.push_str(&format!(", prompt = {:?}", prompt));
+        }
+        if let Some(color) = def.appearance.prompt_color {
+            line.push_str(&format!(", prompt_color = \"{}\"", color.to_hex()));
+        }
+        if let Some(color) = def.appearance.text_color {
+            line.push_str(&format!(", text_color = \"{}\"", color.to_hex()));
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// The y/n question to ask before running `cmd_line` when `confirm_destructive` is on, for
+// commands that throw away something that can't be brought back
+fn confirmation_question(cmd_line: &str) -> Option<String> {
+    let parts = tokenize_command(cmd_line.trim());
+    let (command, args) = parts.split_first()?;
+    match command.as_str() {
+        "clear" => Some("Clear the screen and its scrollback? [y/n]".to_string()),
+        "rm" => {
+            // Bad options are reported by `rm` itself, without asking first
+            let opts = RmOptions::parse(args).ok().filter(|opts| opts.recursive)?;
+            Some(format!("rm: remove {} recursively? [y/n]", opts.paths.join(" ")))
+        }
+        _ => None,
+    }
+}
+
+// Joins lines that end inside a quote or after a backslash with the lines completing them, as
+// submitting them one at a time would
+fn join_continued_lines(lines: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut pending: Option<String> = None;
+    for line in lines {
+        let line = match pending.take() {
+            Some(previous) => format!("{}\n{}", previous, line),
+            None => line,
+        };
+        if tokenize_command_checked(&line).is_err() {
+            pending = Some(line);
+        } else {
+            commands.push(line);
+        }
+    }
+    commands.extend(pending);
+    commands
+}
+
+/// Runs `commands` in order without a window, for `--batch` and `--exec`. Each one finishes, external
+/// processes included, before the next starts. Returns the exit status of the last command;
+/// builtins that print an error count as failure (127 for a command that could not be started).
+pub fn run_batch(commands: &[String], thread_state: &Arc<Mutex<ShellState>>, backend: Box<dyn ProcessBackend>) -> i32 {
+    let backend: Arc<dyn ProcessBackend> = Arc::from(backend);
+    let (output_tx, output_rx) = crossbeam_channel::unbounded();
+    for cmd in commands {
+        execute_command(cmd, thread_state, &output_tx, &backend);
+        let (running, sleep_until) = {
+            let mut s = thread_state.lock().unwrap();
+            (std::mem::take(&mut s.running), s.sleep_until.take())
+        };
+        if let Some(deadline) = sleep_until {
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+        for mut command in running {
+            let code = command.handle.wait().unwrap_or(-1);
+            thread_state.lock().unwrap().last_status = code;
+        }
+    }
+    // Output readers hold a sender until their process's pipes close, so this waits for the
+    // last of the output to land on the screen
+    drop(output_tx);
+    for _ in output_rx {}
+    thread_state.lock().unwrap().last_status
+}
+
 fn execute_command(
     cmd_line: &str,
     thread_state: &Arc<Mutex<ShellState>>,
     output_tx: &Sender<ShellEvent>,
-    backend: &dyn ProcessBackend,
+    backend: &Arc<dyn ProcessBackend>,
 ) {
             let cmd_line = cmd_line.trim();
             if cmd_line.is_empty() {
@@ -85,27 +1299,58 @@ fn execute_command(
             if parts.is_empty() {
                 return;
             }
+            // Builtins that succeed without saying so leave it at success
+            thread_state.lock().unwrap().last_status = CommandStatus::Success.code();
+
+            // Leading assignments are for this command only, not the shell
+            let (assignments, parts) = split_env_assignments(&parts);
+            if parts.is_empty() {
+                // With no command to scope them to, they set the shell's environment like `export`
+                let mut s = thread_state.lock().unwrap();
+                for (name, value) in assignments {
+                    s.env_overrides.insert(name, Some(value));
+                }
+                return;
+            }
 
             let command = &parts[0];
             let args = &parts[1..];
 
-            let (text_color, dir_color) = {
+            // Builtins resolve relative paths against this pane's directory, never the process cwd
+            let (text_color, dir_color, symlink_color, cwd) = {
                 let s = thread_state.lock().unwrap();
-                (s.text_color, s.directory_color)
+                (s.active_text_color(), s.directory_color, s.symlink_color, s.current_dir.clone())
             };
 
+            if !backend.is_local() && LOCAL_FILE_BUILTINS.contains(&command.as_str()) {
+                let mut s = thread_state.lock().unwrap();
+                let message = format!(
+                    "{}: only works on local files, so it is off with the remote backend (run the remote one by path, e.g. /bin/{})",
+                    command, command
+                );
+                report_error(&mut s, &message, output_tx);
+                return;
+            }
+
             match command.as_str() {
-                "exit" => std::process::exit(0),
+                "exit" => {
+                    // Don't leave children orphaned behind us
+                    thread_state.lock().unwrap().kill_running();
+                    std::process::exit(0);
+                }
                 "cd" => {
                     let new_dir = args.get(0).map_or("/", |x| x.as_str());
-                    let root = std::path::Path::new(new_dir);
-                    if let Err(e) = env::set_current_dir(&root) {
-                        let mut s = thread_state.lock().unwrap();
-                        let op = s.screen.push_line(Line::from_string(&format!("Error: {}", e), TerminalColor::RED));
-                        let _ = output_tx.send(ShellEvent::Operation(op));
-                    } else if let Ok(cwd) = env::current_dir() {
-                        let new_cwd_str = cwd.to_string_lossy().to_string();
-                        thread_state.lock().unwrap().current_dir = new_cwd_str;
+                    // The backend checks the new directory: locally, or on the remote host over ssh
+                    match backend.change_directory(&cwd, new_dir) {
+                        Ok(new_cwd_str) => {
+                            let mut s = thread_state.lock().unwrap();
+                            s.current_dir = new_cwd_str;
+                            s.refresh_window_title();
+                        }
+                        Err(e) => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("Error: {}", e), output_tx);
+                        }
                     }
                 }
                 "pwd" => {
@@ -120,6 +1365,15 @@ fn execute_command(
                     let op = s.screen.clear();
                     let _ = output_tx.send(ShellEvent::Operation(op));
                 }
+                // Unlike `clear`, keeps the output and redraws it in the default look
+                "reset" => {
+                    let mut s = thread_state.lock().unwrap();
+                    s.reset_appearance();
+                    let text_color = s.text_color;
+                    let line = Line::from_string("Appearance reset to defaults (config load brings the config back)", text_color);
+                    let op = s.screen.push_line(line);
+                    let _ = output_tx.send(ShellEvent::Operation(op));
+                }
                 "echo" => {
                     let output = args.join(" ");
                     let mut s = thread_state.lock().unwrap();
@@ -127,255 +1381,1632 @@ fn execute_command(
                     let op = s.screen.push_line(Line::from_string(&output, text_color));
                     let _ = output_tx.send(ShellEvent::Operation(op));
                 }
+                "date" => {
+                    let format = match args.first() {
+                        Some(arg) => arg.strip_prefix('+').unwrap_or(arg),
+                        None => DEFAULT_DATE_FORMAT,
+                    };
+                    let mut s = thread_state.lock().unwrap();
+                    match format_date(&chrono::Local::now(), format) {
+                        Some(text) => {
+                            let op = s.screen.push_line(Line::from_string(&text, text_color));
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+                        None => report_error(&mut s, &format!("date: invalid format '{}'", format), output_tx),
+                    }
+                }
+                "true" => thread_state.lock().unwrap().last_status = CommandStatus::Success.code(),
+                "false" => thread_state.lock().unwrap().last_status = CommandStatus::Failure.code(),
+                "test" | "[" => {
+                    let expression = if command == "[" {
+                        match args.split_last() {
+                            Some((last, rest)) if last == "]" => Ok(rest),
+                            _ => Err("missing ']'".to_string()),
+                        }
+                    } else {
+                        Ok(args)
+                    };
+                    let status = match expression.and_then(|args| evaluate_test(args, &cwd)) {
+                        Ok(true) => CommandStatus::Success,
+                        Ok(false) => CommandStatus::Failure,
+                        Err(e) => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("{}: {}", command, e), output_tx);
+                            CommandStatus::Error
+                        }
+                    };
+                    thread_state.lock().unwrap().last_status = status.code();
+                }
+                "macro" => {
+                    let mut s = thread_state.lock().unwrap();
+                    let lines = match (args.first().map(String::as_str), args.get(1)) {
+                        (Some("stats"), name) => {
+                            let metrics = s.macro_metrics.lock().unwrap();
+                            let mut invocations = match name {
+                                Some(name) => metrics.get(name).into_iter().collect(),
+                                None => metrics.snapshot(),
+                            };
+                            drop(metrics);
+                            invocations.sort_by(|a, b| a.macro_name.cmp(&b.macro_name));
+                            if invocations.is_empty() {
+                                let message = match name {
+                                    Some(name) => format!("No invocations recorded for macro '{}'", name),
+                                    None => "No macro invocations recorded yet".to_string(),
+                                };
+                                vec![Line::from_string(&message, TerminalColor::GRAY)]
+                            } else {
+                                invocations
+                                    .iter()
+                                    .flat_map(|invocation| invocation.report_lines())
+                                    .map(|line| Line::from_string(&line, text_color))
+                                    .collect()
+                            }
+                        }
+                        _ => {
+                            report_usage(&mut s, "Usage: macro stats [name]", text_color, output_tx);
+                            return;
+                        }
+                    };
+                    for line in lines {
+                        let op = s.screen.push_line(line);
+                        let _ = output_tx.send(ShellEvent::Operation(op));
+                    }
+                }
+                "metrics" => {
+                    let mut s = thread_state.lock().unwrap();
+                    let mut invocations = s.macro_metrics.lock().unwrap().snapshot();
+                    invocations.sort_by(|a, b| a.macro_name.cmp(&b.macro_name));
+                    let mut lines = s.render_metrics.report_lines();
+                    if invocations.is_empty() {
+                        lines.push("macros: none run yet".to_string());
+                    } else {
+                        lines.push("macros:".to_string());
+                        let reports = invocations.iter().flat_map(|invocation| invocation.report_lines());
+                        lines.extend(reports.map(|line| format!("  {}", line)));
+                    }
+                    for line in lines {
+                        let op = s.screen.push_line(Line::from_string(&line, text_color));
+                        let _ = output_tx.send(ShellEvent::Operation(op));
+                    }
+                }
+                "seq" => {
+                    let mut s = thread_state.lock().unwrap();
+                    match seq_values(args) {
+                        Ok(values) => {
+                            for value in values {
+                                let op = s.screen.push_line(Line::from_string(&value, text_color));
+                                let _ = output_tx.send(ShellEvent::Operation(op));
+                            }
+                        }
+                        Err(SeqError::Usage) => {
+                            report_usage(&mut s, "Usage: seq [first [step]] last", text_color, output_tx);
+                        }
+                        Err(SeqError::TooMany(count)) => {
+                            let message = format!("seq: {} numbers is more than the limit of {}", count, SEQ_MAX_VALUES);
+                            report_error(&mut s, &message, output_tx);
+                        }
+                    }
+                }
                 "mkdir" => {
                     for path in args {
-                        if let Err(e) = std::fs::create_dir_all(path) {
+                        if let Err(e) = std::fs::create_dir_all(resolve(&cwd, path)) {
                             let mut s = thread_state.lock().unwrap();
-                            let op = s.screen.push_line(Line::from_string(&format!("mkdir: {}: {}", path, e), TerminalColor::RED));
-                            let _ = output_tx.send(ShellEvent::Operation(op));
+                            report_error(&mut s, &format!("mkdir: {}: {}", path, e), output_tx);
                         }
                     }
                 }
                 "touch" => {
                     for path in args {
-                        match std::fs::OpenOptions::new().create(true).write(true).open(path) {
+                        let target = resolve(&cwd, path);
+                        match std::fs::OpenOptions::new().create(true).write(true).open(&target) {
                             Ok(_) => {
-                                if let Err(e) = filetime::set_file_mtime(path, filetime::FileTime::from_system_time(SystemTime::now())) {
+                                if let Err(e) = filetime::set_file_mtime(&target, filetime::FileTime::from_system_time(SystemTime::now())) {
                                     let mut s = thread_state.lock().unwrap();
-                                    let op = s.screen.push_line(Line::from_string(&format!("touch (mtime): {}: {}", path, e), TerminalColor::RED));
+                                    report_error(&mut s, &format!("touch (mtime): {}: {}", path, e), output_tx);
+                                }
+                            }
+                            Err(e) => {
+                                let mut s = thread_state.lock().unwrap();
+                                report_error(&mut s, &format!("touch: {}: {}", path, e), output_tx);
+                            }
+                        }
+                    }
+                }
+                "cat" => {
+                    for path in args {
+                        match std::fs::read_to_string(resolve(&cwd, path)) {
+                            Ok(content) => {
+                                let mut s = thread_state.lock().unwrap();
+                                for line in content.lines() {
+                                    let op = s.screen.push_line(Line::from_string(line, text_color));
+                                    let _ = output_tx.send(ShellEvent::Operation(op));
+                                }
+                            }
+                            Err(e) => {
+                                let mut s = thread_state.lock().unwrap();
+                                report_error(&mut s, &format!("cat: {}: {}", path, e), output_tx);
+                            }
+                        }
+                    }
+                }
+                "rm" => {
+                    let RmOptions { recursive, force, paths } = match RmOptions::parse(args) {
+                        Ok(opts) => opts,
+                        Err(e) => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("rm: {}", e), output_tx);
+                            report_usage(&mut s, "Usage: rm [-r] [-f] [--] <path>...", text_color, output_tx);
+                            return;
+                        }
+                    };
+
+                    for path in &paths {
+                        let target = resolve(&cwd, path);
+                        let is_root = std::fs::canonicalize(&target)
+                            .map(|p| p.parent().is_none())
+                            .unwrap_or(false);
+                        if recursive && is_root {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("rm: refusing to remove root directory '{}'", path), output_tx);
+                            continue;
+                        }
+
+                        let result = match std::fs::symlink_metadata(&target) {
+                            Ok(metadata) if metadata.is_dir() => {
+                                if recursive {
+                                    std::fs::remove_dir_all(&target)
+                                } else {
+                                    Err(std::io::Error::other("is a directory (use -r)"))
+                                }
+                            }
+                            Ok(_) => std::fs::remove_file(&target),
+                            Err(e) => Err(e),
+                        };
+
+                        if let Err(e) = result {
+                            if force {
+                                continue;
+                            }
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("rm: {}: {}", path, e), output_tx);
+                        }
+                    }
+                }
+                "stat" => {
+                    if args.is_empty() {
+                        let mut s = thread_state.lock().unwrap();
+                        report_usage(&mut s, "Usage: stat <path>...", text_color, output_tx);
+                    }
+                    for path in args {
+                        match std::fs::symlink_metadata(resolve(&cwd, path)) {
+                            Ok(metadata) => {
+                                let file_type = if metadata.file_type().is_symlink() {
+                                    "symbolic link"
+                                } else if metadata.is_dir() {
+                                    "directory"
+                                } else if metadata.is_file() {
+                                    "regular file"
+                                } else {
+                                    "other"
+                                };
+                                let format_time = |t: std::io::Result<SystemTime>| {
+                                    t.map(format_system_time).unwrap_or_else(|_| "-".to_string())
+                                };
+
+                                let lines = vec![
+                                    format!("  File: {}", path),
+                                    format!("  Size: {} ({})", metadata.len(), format_size(metadata.len())),
+                                    format!("  Type: {}", file_type),
+                                    format!("Access: {}", stat_permissions(&metadata)),
+                                    format!("Modify: {}", format_time(metadata.modified())),
+                                    format!(" Birth: {}", format_time(metadata.created())),
+                                ];
+
+                                let mut s = thread_state.lock().unwrap();
+                                for line in lines {
+                                    let op = s.screen.push_line(Line::from_string(&line, text_color));
+                                    let _ = output_tx.send(ShellEvent::Operation(op));
+                                }
+                            }
+                            Err(e) => {
+                                let mut s = thread_state.lock().unwrap();
+                                report_error(&mut s, &format!("stat: {}: {}", path, e), output_tx);
+                            }
+                        }
+                    }
+                }
+                "find" => {
+                    let opts = match FindOptions::parse(args) {
+                        Ok(opts) => opts,
+                        Err(e) => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("find: {}", e), output_tx);
+                            return;
+                        }
+                    };
+
+                    let root = resolve(&cwd, &opts.root);
+                    let walked = walk_tree(
+                        &root,
+                        opts.max_depth,
+                        |path, metadata, _| {
+                            let is_dir = metadata.is_dir();
+                            if opts.matches(path, is_dir) {
+                                let mut s = thread_state.lock().unwrap();
+                                let color = if is_dir { dir_color } else { text_color };
+                                let shown = display_path(&opts.root, &root, path);
+                                let op = s.screen.push_line(Line::from_string(&shown.to_string_lossy(), color));
+                                let _ = output_tx.send(ShellEvent::Operation(op));
+                            }
+                        },
+                        |path, e| {
+                            let mut s = thread_state.lock().unwrap();
+                            let shown = display_path(&opts.root, &root, path);
+                            let op = s.screen.push_line(Line::from_string(&format!("find: {}: {}", shown.display(), e), TerminalColor::GRAY));
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        },
+                    );
+                    if let Err(e) = walked {
+                        let mut s = thread_state.lock().unwrap();
+                        report_error(&mut s, &format!("find: {}: {}", opts.root, e), output_tx);
+                    }
+                }
+                "du" => {
+                    let opts = match DuOptions::parse(args) {
+                        Ok(opts) => opts,
+                        Err(e) => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("du: {}", e), output_tx);
+                            return;
+                        }
+                    };
+                    let size = |bytes: u64| if opts.human_readable { format_size(bytes) } else { bytes.to_string() };
+
+                    let mut grand_total = 0;
+                    for root in &opts.paths {
+                        let mut warnings = Vec::new();
+                        let real_root = resolve(&cwd, root);
+                        let usage = disk_usage(&real_root, &mut warnings);
+                        let mut s = thread_state.lock().unwrap();
+                        for warning in warnings {
+                            let op = s.screen.push_line(Line::from_string(&warning, TerminalColor::GRAY));
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+                        match usage {
+                            Ok(dirs) => {
+                                for (path, depth, bytes) in &dirs {
+                                    if *depth <= opts.max_depth {
+                                        let text = format!("{:>12}  {}", size(*bytes), display_path(root, &real_root, path).display());
+                                        let op = s.screen.push_line(Line::from_string(&text, text_color));
+                                        let _ = output_tx.send(ShellEvent::Operation(op));
+                                    }
+                                }
+                                grand_total += dirs.last().map_or(0, |(_, _, bytes)| *bytes);
+                            }
+                            Err(e) => {
+                                report_error(&mut s, &format!("du: {}: {}", root, e), output_tx);
+                            }
+                        }
+                    }
+                    let mut s = thread_state.lock().unwrap();
+                    let op = s.screen.push_line(Line::from_string(&format!("{:>12}  total", size(grand_total)), TerminalColor::GOLD));
+                    let _ = output_tx.send(ShellEvent::Operation(op));
+                }
+                "env" => {
+                    let mut s = thread_state.lock().unwrap();
+                    let mut vars = s.environment();
+                    vars.extend(assignments);
+                    for (key, value) in vars {
+                        let op = s.screen.push_line(Line::from_string(&format!("{}={}", key, value), text_color));
+                        let _ = output_tx.send(ShellEvent::Operation(op));
+                    }
+                }
+                "export" => {
+                    if args.is_empty() {
+                        let mut s = thread_state.lock().unwrap();
+                        report_usage(&mut s, "Usage: export NAME[=value]...", text_color, output_tx);
+                    }
+                    for arg in args {
+                        let (name, value) = match arg.split_once('=') {
+                            Some((name, value)) => (name, Some(value)),
+                            None => (arg.as_str(), None),
+                        };
+                        if !is_valid_env_name(name) {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("export: '{}': not a valid identifier", arg), output_tx);
+                            continue;
+                        }
+                        let mut s = thread_state.lock().unwrap();
+                        match value {
+                            // Kept in the shell state and passed to each spawned command,
+                            // rather than mutating this process's environment
+                            Some(value) => {
+                                s.env_overrides.insert(name.to_string(), Some(value.to_string()));
+                            }
+                            None => {
+                                // Every variable in this shell is already exported
+                                if !s.environment().contains_key(name) {
+                                    let op = s.screen.push_line(Line::from_string(&format!("export: {}: not set", name), TerminalColor::GRAY));
                                     let _ = output_tx.send(ShellEvent::Operation(op));
                                 }
                             }
-                            Err(e) => {
-                                let mut s = thread_state.lock().unwrap();
-                                let op = s.screen.push_line(Line::from_string(&format!("touch: {}: {}", path, e), TerminalColor::RED));
-                                let _ = output_tx.send(ShellEvent::Operation(op));
-                            }
                         }
                     }
                 }
-                "cat" => {
-                    for path in args {
-                        match std::fs::read_to_string(path) {
-                            Ok(content) => {
-                                let mut s = thread_state.lock().unwrap();
-                                for line in content.lines() {
-                                    let op = s.screen.push_line(Line::from_string(line, text_color));
-                                    let _ = output_tx.send(ShellEvent::Operation(op));
+                "unset" => {
+                    for name in args {
+                        if !is_valid_env_name(name) {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("unset: '{}': not a valid identifier", name), output_tx);
+                            continue;
+                        }
+                        thread_state.lock().unwrap().env_overrides.insert(name.to_string(), None);
+                    }
+                }
+                "help" => {
+                    let mut s = thread_state.lock().unwrap();
+                    let mut lines = Vec::new();
+                    match args.first() {
+                        None => {
+                            let width = BUILTINS.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+                            for (name, _, description) in BUILTINS {
+                                lines.push(format!("{:<width$}  {}", name, description, width = width));
+                            }
+                            if !s.macro_names.is_empty() {
+                                lines.push(String::new());
+                                lines.push("Lua macros:".to_string());
+                                for name in &s.macro_names {
+                                    lines.push(format!("  {}", name));
                                 }
                             }
-                            Err(e) => {
-                                let mut s = thread_state.lock().unwrap();
-                                let op = s.screen.push_line(Line::from_string(&format!("cat: {}: {}", path, e), TerminalColor::RED));
-                                let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+                        Some(name) => {
+                            if let Some((_, usage, description)) = BUILTINS.iter().find(|(n, _, _)| n == name) {
+                                lines.push(format!("{}: builtin", name));
+                                lines.push(format!("  {}", description));
+                                lines.push(format!("  Usage: {}", usage));
+                            } else if s.macro_names.iter().any(|m| m == name) {
+                                lines.push(format!("{}: Lua macro (axiom.macros.{})", name, name));
+                            } else {
+                                lines.push(format!("{}: not a builtin; will be run as an external command", name));
                             }
                         }
                     }
-                }
-                "rm" => {
-                    for path in args {
-                        if let Err(e) = std::fs::remove_file(path).or_else(|_| std::fs::remove_dir(path)) {
-                            let mut s = thread_state.lock().unwrap();
-                            let op = s.screen.push_line(Line::from_string(&format!("rm: {}: {}", path, e), TerminalColor::RED));
-                            let _ = output_tx.send(ShellEvent::Operation(op));
-                        }
+                    for line in lines {
+                        let op = s.screen.push_line(Line::from_string(&line, text_color));
+                        let _ = output_tx.send(ShellEvent::Operation(op));
                     }
                 }
                 "mv" => {
                     if args.len() == 2 {
-                        if let Err(e) = std::fs::rename(&args[0], &args[1]) {
+                        if let Err(e) = std::fs::rename(resolve(&cwd, &args[0]), resolve(&cwd, &args[1])) {
                             let mut s = thread_state.lock().unwrap();
-                            let op = s.screen.push_line(Line::from_string(&format!("mv: {}", e), TerminalColor::RED));
-                            let _ = output_tx.send(ShellEvent::Operation(op));
+                            report_error(&mut s, &format!("mv: {}", e), output_tx);
                         }
                     } else {
                         let mut s = thread_state.lock().unwrap();
-                        let op = s.screen.push_line(Line::from_string("Usage: mv <source> <dest>", text_color));
-                        let _ = output_tx.send(ShellEvent::Operation(op));
+                        report_usage(&mut s, "Usage: mv <source> <dest>", text_color, output_tx);
                     }
                 }
                 "cp" => {
                     if args.len() == 2 {
-                        if let Err(e) = std::fs::copy(&args[0], &args[1]) {
+                        if let Err(e) = std::fs::copy(resolve(&cwd, &args[0]), resolve(&cwd, &args[1])) {
                             let mut s = thread_state.lock().unwrap();
-                            let op = s.screen.push_line(Line::from_string(&format!("cp: {}", e), TerminalColor::RED));
-                            let _ = output_tx.send(ShellEvent::Operation(op));
+                            report_error(&mut s, &format!("cp: {}", e), output_tx);
                         }
                     } else {
                         let mut s = thread_state.lock().unwrap();
-                        let op = s.screen.push_line(Line::from_string("Usage: cp <source> <dest>", text_color));
-                        let _ = output_tx.send(ShellEvent::Operation(op));
+                        report_usage(&mut s, "Usage: cp <source> <dest>", text_color, output_tx);
                     }
                 }
                 "ls" => {
-                    let mut show_all = false;
-                    let mut long_format = false;
-                    let mut target_path = ".";
-
-                    for arg in args {
-                        if arg == "-a" || arg == "--all" {
-                            show_all = true;
-                        } else if arg == "-l" {
-                            long_format = true;
-                        } else if !arg.starts_with('-') {
-                            target_path = arg;
-                        }
-                    }
+                    let opts = LsOptions::parse(args);
+                    // Extensions from `LS_COLORS`, overridden by the config's `ls_colors`
+                    let (ls_colors, hidden_file_dim) = {
+                        let s = thread_state.lock().unwrap();
+                        let env_value = match s.env_overrides.get("LS_COLORS") {
+                            Some(value) => value.clone(),
+                            None => env::var("LS_COLORS").ok(),
+                        };
+                        let mut colors = env_value.map(|v| parse_ls_colors(&v)).unwrap_or_default();
+                        colors.extend(s.ls_colors.iter().map(|(k, c)| (k.clone(), *c)));
+                        (colors, s.hidden_file_dim)
+                    };
 
-                    match std::fs::read_dir(target_path) {
+                    match std::fs::read_dir(resolve(&cwd, &opts.target_path)) {
                         Ok(entries) => {
-                            let mut entry_list: Vec<_> = entries.filter_map(Result::ok).collect();
-                            entry_list.sort_by_key(|e| e.file_name());
+                            let mut entry_list: Vec<_> = entries
+                                .filter_map(Result::ok)
+                                .map(|e| {
+                                    let metadata = std::fs::symlink_metadata(e.path()).ok();
+                                    (e, metadata)
+                                })
+                                .collect();
+
+                            match opts.sort {
+                                LsSort::Name => entry_list.sort_by_key(|(e, _)| e.file_name()),
+                                LsSort::Time => entry_list.sort_by_key(|(_, m)| {
+                                    std::cmp::Reverse(m.as_ref().and_then(|m| m.modified().ok()))
+                                }),
+                                LsSort::Size => entry_list.sort_by_key(|(_, m)| {
+                                    std::cmp::Reverse(m.as_ref().map_or(0, |m| m.len()))
+                                }),
+                            }
+                            if opts.reverse {
+                                entry_list.reverse();
+                            }
 
-                            for entry in entry_list {
+                            for (entry, metadata) in entry_list {
                                 let file_name = entry.file_name().to_string_lossy().to_string();
-                                if !show_all && file_name.starts_with('.') {
+                                let hidden = file_name.starts_with('.');
+                                if !opts.show_all && hidden {
                                     continue;
                                 }
+                                // Dotfiles keep their kind's color, only dimmer
+                                let dim = if hidden { hidden_file_dim } else { 1.0 };
 
                                 let mut line_color = text_color;
-                                if let Ok(metadata) = entry.metadata() {
+                                if let Some(metadata) = metadata {
+                                    let is_symlink = metadata.file_type().is_symlink();
                                     let is_dir = metadata.is_dir();
-                                    if is_dir {
+                                    if is_symlink {
+                                        line_color = symlink_color;
+                                    } else if is_dir {
                                         line_color = dir_color;
+                                    } else if let Some(color) = ls_color(&file_name, &ls_colors) {
+                                        line_color = color;
+                                    }
+                                    line_color = line_color.dim(dim);
+
+                                    let mut display_name = file_name;
+                                    if opts.classify {
+                                        if is_symlink {
+                                            display_name.push('@');
+                                        } else if is_dir {
+                                            display_name.push('/');
+                                        } else if is_executable(&entry.path(), &metadata) {
+                                            display_name.push('*');
+                                        }
                                     }
 
                                     let mut s = thread_state.lock().unwrap();
-                                    let op = if long_format {
-                                        let type_indicator = if is_dir { "<DIR>" } else { "     " };
-                                        let size = metadata.len();
+                                    let op = if opts.long_format {
+                                        let type_indicator = if is_symlink {
+                                            "<LNK>"
+                                        } else if is_dir {
+                                            "<DIR>"
+                                        } else {
+                                            "     "
+                                        };
+                                        let size = if opts.human_readable {
+                                            format_size(metadata.len())
+                                        } else {
+                                            metadata.len().to_string()
+                                        };
+                                        if is_symlink
+                                            && let Ok(target) = std::fs::read_link(entry.path())
+                                        {
+                                            display_name = format!("{} -> {}", display_name, target.display());
+                                        }
                                         s.screen.push_line(Line::from_string(
-                                            &format!("{} {:>12} {}", type_indicator, size, file_name),
+                                            &format!("{} {:>12} {}", type_indicator, size, display_name),
                                             line_color,
                                         ))
                                     } else {
-                                        s.screen.push_line(Line::from_string(&file_name, line_color))
+                                        s.screen.push_line(Line::from_string(&display_name, line_color))
                                     };
                                     let _ = output_tx.send(ShellEvent::Operation(op));
                                 } else {
                                     let mut s = thread_state.lock().unwrap();
-                                    let op = s.screen.push_line(Line::from_string(&file_name, text_color));
+                                    let op = s.screen.push_line(Line::from_string(&file_name, text_color.dim(dim)));
                                     let _ = output_tx.send(ShellEvent::Operation(op));
                                 }
                             }
                         }
                         Err(e) => {
                             let mut s = thread_state.lock().unwrap();
-                            let op = s.screen.push_line(Line::from_string(&format!("ls: {}: {}", target_path, e), TerminalColor::RED));
-                            let _ = output_tx.send(ShellEvent::Operation(op));
+                            report_error(&mut s, &format!("ls: {}: {}", opts.target_path, e), output_tx);
                         }
                     }
                 }
+                "theme" => match (args.first().map(|s| s.as_str()), args.get(1)) {
+                    (Some("load"), Some(name)) => load_theme(name, false, thread_state, output_tx),
+                    (Some("preview"), None) => {
+                        let mut s = thread_state.lock().unwrap();
+                        for line in theme_preview_lines(&s) {
+                            let op = s.screen.push_line(line);
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+                    }
+                    (Some("list"), None) => {
+                        let mut names: Vec<String> = get_themes_dir()
+                            .and_then(|dir| std::fs::read_dir(dir).ok())
+                            .map(|entries| {
+                                entries
+                                    .filter_map(Result::ok)
+                                    .filter_map(|e| {
+                                        let path = e.path();
+                                        let is_lua = path.extension().is_some_and(|ext| ext == "lua");
+                                        is_lua.then(|| path.file_stem()?.to_str().map(str::to_string)).flatten()
+                                    })
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        names.sort();
+
+                        let mut s = thread_state.lock().unwrap();
+                        if names.is_empty() {
+                            let dir = get_themes_dir().map_or_else(|| "themes directory".to_string(), |d| d.display().to_string());
+                            let op = s.screen.push_line(Line::from_string(&format!("No themes found in {}", dir), TerminalColor::GRAY));
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+                        for name in names {
+                            let op = s.screen.push_line(Line::from_string(&name, text_color));
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
+                    }
+                    _ => {
+                        let mut s = thread_state.lock().unwrap();
+                        report_usage(&mut s, "Usage: theme load <name> | theme list | theme preview", text_color, output_tx);
+                    }
+                },
                 "config" => {
                     if args.first().map(|s| s.as_str()) == Some("load") {
-                        let path = if let Some(path_arg) = args.get(1) {
-                            std::path::PathBuf::from(path_arg)
-                        } else {
-                            match get_default_config_path() {
-                                Some(p) => p,
-                                None => {
-                                    let mut s = thread_state.lock().unwrap();
-                                    let op = s.screen.push_line(Line::from_string("Error: Could not determine default config path", TerminalColor::RED));
-                                    let _ = output_tx.send(ShellEvent::Operation(op));
-                                    return;
-                                }
-                            }
+                        let Some(path) = args.get(1).map(|path| resolve(&cwd, path)).or_else(get_default_config_path) else {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, "Error: Could not determine default config path", output_tx);
+                            return;
                         };
 
-                        match parse_config(&path) {
-                            Ok(update) => {
-                                let mut actual_cwd = None;
-                                let mut cwd_error = None;
-                                if let Some(new_cwd) = &update.default_cwd {
-                                    let root = std::path::Path::new(new_cwd);
-                                    if let Err(e) = env::set_current_dir(&root) {
-                                        cwd_error = Some(format!(
-                                            "Failed to set default_cwd to {}: {}",
-                                            new_cwd, e
-                                        ));
-                                    } else {
-                                        match env::current_dir() {
-                                            Ok(cwd) => {
-                                                actual_cwd = Some(cwd.to_string_lossy().to_string());
-                                            }
-                                            Err(e) => {
-                                                cwd_error = Some(format!(
-                                                    "Failed to read current dir '{}': {}",
-                                                    new_cwd, e
-                                                ));
-                                            }
-                                        }
-                                    }
-                                }
-
-                                {
-                                    let mut s = thread_state.lock().unwrap();
-                                    if let Some(p) = update.prompt {
-                                        s.prompt = p;
-                                    }
-                                    if let Some(pc) = update.prompt_color {
-                                        s.prompt_color = pc;
-                                    }
-                                    if let Some(tc) = update.text_color {
-                                        s.text_color = tc;
-                                    }
-                                    if let Some(wt) = update.window_title {
-                                        s.window_title_base = wt;
-                                    }
-                                    if let Some(sh) = update.shortcuts {
-                                        s.shortcuts = sh;
-                                    }
-                                    if let Some(op) = update.opacity {
-                                        s.opacity = op;
-                                    }
-                                    if let Some(fs) = update.font_size {
-                                        s.font_size = fs;
-                                    }
-                                    if let Some(dc) = update.directory_color {
-                                        s.directory_color = dc;
-                                    }
-                                    if let Some(md) = update.mode_definitions {
-                                        s.mode_definitions = md;
-                                    }
-                                    if let Some(cwd_str) = actual_cwd {
-                                        s.current_dir = cwd_str;
-                                    }
-
-                                    s.window_title_full =
-                                        format!("[{}] {}", s.mode.name(), s.window_title_base);
-                                    s.title_updated = true;
-                                }
+                        load_config(&path, false, thread_state, output_tx, backend.as_ref());
+                    } else if args.first().map(|s| s.as_str()) == Some("validate") {
+                        // Parses without applying anything
+                        let Some(path) = args.get(1).map(|path| resolve(&cwd, path)).or_else(get_default_config_path) else {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, "Error: Could not determine default config path", output_tx);
+                            return;
+                        };
 
-                                if let Some(e) = cwd_error {
-                                    let mut s = thread_state.lock().unwrap();
-                                    let op = s.screen.push_line(Line::from_string(&e, TerminalColor::RED));
-                                    let _ = output_tx.send(ShellEvent::Operation(op));
-                                }
-                                let mut s = thread_state.lock().unwrap();
-                                let op = s.screen.push_line(Line::from_string(
-                                    &format!("Config loaded from: {}", path.display()),
-                                    TerminalColor::GOLD,
-                                ));
+                        let mut s = thread_state.lock().unwrap();
+                        match parse_config(&path) {
+                            Ok((_, warnings)) => {
+                                push_config_warnings(&mut s, &warnings, output_tx);
+                                let summary = match warnings.len() {
+                                    0 => format!("Config OK: {}", path.display()),
+                                    n => format!("Config parsed with {} warning(s): {}", n, path.display()),
+                                };
+                                let op = s.screen.push_line(Line::from_string(&summary, TerminalColor::GOLD));
                                 let _ = output_tx.send(ShellEvent::Operation(op));
                             }
                             Err(e) => {
-                                let mut s = thread_state.lock().unwrap();
-                                let op = s.screen.push_line(Line::from_string(&format!("Failed to load config at {}: {}", path.display(), e), TerminalColor::RED));
-                                let _ = output_tx.send(ShellEvent::Operation(op));
+                                for message in config_error_lines(&path, e.as_ref(), "Invalid config") {
+                                    report_error(&mut s, &message, output_tx);
+                                }
                             }
                         }
+                    } else if args.first().map(|s| s.as_str()) == Some("dump") {
+                        let mut s = thread_state.lock().unwrap();
+                        for line in config_dump_lines(&s) {
+                            let op = s.screen.push_line(Line::from_string(&line, text_color));
+                            let _ = output_tx.send(ShellEvent::Operation(op));
+                        }
                     } else {
                         let mut s = thread_state.lock().unwrap();
-                        let op = s.screen.push_line(Line::from_string("Usage: config load [path]", text_color));
-                        let _ = output_tx.send(ShellEvent::Operation(op));
+                        report_usage(&mut s, "Usage: config load|validate [path] | config dump", text_color, output_tx);
                     }
                 }
-                command_name => {
-                    if let Err(e) = backend.spawn(command_name, args, output_tx.clone(), Arc::clone(thread_state)) {
+                "sleep" => {
+                    match args.first().and_then(|a| a.parse::<f64>().ok()).filter(|secs| secs.is_finite() && *secs >= 0.0) {
+                        Some(secs) if args.len() == 1 => {
+                            thread_state.lock().unwrap().sleep_until = Some(Instant::now() + Duration::from_secs_f64(secs));
+                        }
+                        _ => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_usage(&mut s, "Usage: sleep <seconds>", text_color, output_tx);
+                        }
+                    }
+                }
+                "watch" => {
+                    let (interval, command) = match args.first().map(String::as_str) {
+                        Some("-n") => match args.get(1).and_then(|a| a.parse::<f64>().ok()).filter(|secs| secs.is_finite() && *secs > 0.0) {
+                            Some(secs) => (Duration::from_secs_f64(secs), &args[2..]),
+                            None => (Duration::ZERO, &[][..]),
+                        },
+                        _ => (WATCH_DEFAULT_INTERVAL, args),
+                    };
+                    if command.is_empty() {
+                        let mut s = thread_state.lock().unwrap();
+                        report_usage(&mut s, "Usage: watch [-n <seconds>] <command>...", text_color, output_tx);
+                        return;
+                    }
+                    spawn_watch(interval, command.to_vec(), thread_state, output_tx, backend, text_color);
+                }
+                "progress" => {
+                    let steps = match args.first().map(|a| a.parse::<usize>()) {
+                        None => PROGRESS_DEFAULT_STEPS,
+                        Some(Ok(n)) if n > 0 => n,
+                        Some(_) => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_usage(&mut s, "Usage: progress [steps]", text_color, output_tx);
+                            return;
+                        }
+                    };
+
+                    let (generation, clears, row) = {
                         let mut s = thread_state.lock().unwrap();
-                        let op = s.screen.push_line(Line::from_string(&format!("Failed to spawn {}: {}", command_name, e), TerminalColor::RED));
+                        let op = s.screen.push_line(Line::from_string(&format_progress_bar(0, steps, PROGRESS_BAR_WIDTH), text_color));
                         let _ = output_tx.send(ShellEvent::Operation(op));
+                        (s.interrupts, s.screen.clears, s.screen.lines.len() - 1)
+                    };
+
+                    // Each step rewrites the same row, so the renderer only re-lays out that line
+                    let state = Arc::clone(thread_state);
+                    let tx = output_tx.clone();
+                    thread::spawn(move || {
+                        for done in 1..=steps {
+                            thread::sleep(PROGRESS_STEP_INTERVAL);
+                            let mut s = state.lock().unwrap();
+                            if s.interrupts != generation || s.screen.clears != clears {
+                                break; // Interrupted, or the screen was cleared underneath us
+                            }
+                            let op = s.screen.update_line(row, Line::from_string(&format_progress_bar(done, steps, PROGRESS_BAR_WIDTH), text_color));
+                            let _ = tx.send(ShellEvent::Operation(op));
+                        }
+                    });
+                }
+                command_name => {
+                    match backend.spawn(command_name, args, &assignments, output_tx.clone(), Arc::clone(thread_state)) {
+                        Ok(handle) => {
+                            reap_finished(thread_state, output_tx);
+                            thread_state.lock().unwrap().running.push(RunningCommand {
+                                handle,
+                                command_line: cmd_line.to_string(),
+                                started: Instant::now(),
+                            });
+                        }
+                        Err(e) => {
+                            let mut s = thread_state.lock().unwrap();
+                            report_error(&mut s, &format!("Failed to spawn {}: {}", command_name, e), output_tx);
+                            s.last_status = CommandStatus::NotRun.code();
+                        }
                     }
                 }
             }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockCommand};
+    use crate::types::{NotificationStyle, DEFAULT_MAX_INPUT_LENGTH};
+
+    fn run(cmd_line: &str, backend: &Arc<MockBackend>) -> (Arc<Mutex<ShellState>>, Vec<String>) {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let backend: Arc<dyn ProcessBackend> = backend.clone();
+        execute_command(cmd_line, &state, &tx, &backend);
+        let lines = state.lock().unwrap().screen.lines.iter()
+            .map(|line| line.cells.iter().map(|c| c.ch).collect())
+            .collect();
+        (state, lines)
+    }
+
+    // Starts a shell thread over `state`. The returned closure sends an action and waits until
+    // the thread has handled it.
+    fn shell(state: &Arc<Mutex<ShellState>>) -> impl Fn(Action) {
+        let (action_tx, action_rx) = crossbeam_channel::unbounded();
+        let (output_tx, _output_rx) = crossbeam_channel::unbounded();
+        let (done_tx, done_rx) = crossbeam_channel::unbounded();
+        spawn_shell_thread(action_rx, output_tx, state.clone(), Box::new(MockBackend::default()), move || {
+            let _ = done_tx.send(());
+        });
+        move |action| {
+            action_tx.send(action).unwrap();
+            done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_external_command_output() {
+        let backend = Arc::new(MockBackend::default().with_command("git", MockCommand {
+            stdout: vec!["On branch main".to_string()],
+            stderr: vec!["warning: stale".to_string()],
+            exit_code: 0,
+        }));
+
+        let (state, lines) = run(r#"git commit -m "two words""#, &backend);
+        assert_eq!(lines, vec!["On branch main", "warning: stale"]);
+        assert_eq!(state.lock().unwrap().screen.lines[1].cells[0].fg, TerminalColor::RED);
+        assert_eq!(*backend.calls.lock().unwrap(), vec![vec!["git", "commit", "-m", "two words"]]);
+    }
+
+    #[test]
+    fn test_unknown_command_reports_spawn_failure() {
+        let backend = Arc::new(MockBackend::default());
+        let (state, lines) = run("nope --flag", &backend);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("Failed to spawn nope"));
+        assert_eq!(state.lock().unwrap().screen.lines[0].cells[0].fg, TerminalColor::RED);
+        assert_eq!(state.lock().unwrap().last_status, 127);
+    }
+
+    #[test]
+    fn test_builtins_bypass_backend() {
+        let backend = Arc::new(MockBackend::default());
+        let (_, lines) = run("echo hello world", &backend);
+        assert_eq!(lines, vec!["hello world"]);
+        assert!(backend.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_theme_preview() {
+        let backend = Arc::new(MockBackend::default());
+        let (state, lines) = run("theme preview", &backend);
+        assert_eq!(lines.len(), 11);
+        assert!(lines[2].starts_with("directory_color") && lines[2].ends_with("src/  docs/  target/"));
+
+        let s = state.lock().unwrap();
+        let rows = &s.screen.lines;
+        let sample = |row: usize| &rows[row].cells[PREVIEW_LABEL_WIDTH..];
+        assert!(sample(2).iter().all(|c| c.fg == s.directory_color));
+        assert!(sample(5).iter().all(|c| c.fg == s.error_color));
+        assert!(sample(7).iter().all(|c| c.attrs.bold && !c.attrs.underline));
+        assert!(sample(8).iter().all(|c| c.attrs.underline && !c.attrs.bold));
+        // Each ANSI swatch is three blocks and a space
+        assert_eq!(sample(10)[0].fg, ansi_color(8));
+        assert_eq!(sample(10)[28].fg, ansi_color(15));
+    }
+
+    #[test]
+    fn test_macro_stats() {
+        let backend = Arc::new(MockBackend::default());
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let dyn_backend: Arc<dyn ProcessBackend> = backend;
+        let stats = |cmd: &str| {
+            state.lock().unwrap().screen = crate::types::Screen::new();
+            execute_command(cmd, &state, &tx, &dyn_backend);
+            state.lock().unwrap().screen.lines.iter().map(|l| l.cells.iter().map(|c| c.ch).collect()).collect::<Vec<String>>()
+        };
+
+        assert_eq!(stats("macro stats"), vec!["No macro invocations recorded yet"]);
+        {
+            let s = state.lock().unwrap();
+            let mut metrics = s.macro_metrics.lock().unwrap();
+            metrics.record_success("zeta", 2);
+            metrics.record_success("alpha", 3);
+            metrics.record_success("alpha", 1);
+            metrics.record_error("zeta", crate::lua_bridge::MacroError::NotFound("zeta".to_string()));
+        }
+        assert_eq!(
+            stats("macro stats"),
+            vec![
+                "alpha: 2 invocation(s), 4 action(s) emitted, max 3 per call",
+                "zeta: 2 invocation(s), 2 action(s) emitted, max 2 per call",
+                "  last error: Macro 'zeta' is not defined",
+            ]
+        );
+        assert_eq!(stats("macro stats alpha"), vec!["alpha: 2 invocation(s), 4 action(s) emitted, max 3 per call"]);
+        assert_eq!(stats("macro stats missing"), vec!["No invocations recorded for macro 'missing'"]);
+        assert_eq!(stats("macro"), vec!["Usage: macro stats [name]"]);
+
+        state.lock().unwrap().render_metrics = crate::types::RenderMetrics {
+            structural_ops: 3,
+            visual_ops: 5,
+            cursor_ops: 2,
+            dirty_line_count: usize::MAX,
+            coalesced_ops: 40,
+        };
+        assert_eq!(
+            stats("metrics"),
+            vec![
+                "render: 3 structural, 5 visual, 2 cursor op(s), 40 coalesced into a re-layout",
+                "  dirty lines awaiting a draw: all",
+                "macros:",
+                "  alpha: 2 invocation(s), 4 action(s) emitted, max 3 per call",
+                "  zeta: 2 invocation(s), 2 action(s) emitted, max 2 per call",
+                "    last error: Macro 'zeta' is not defined",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batch_waits_and_returns_the_last_status() {
+        let run = |commands: &[&str]| {
+            let backend = MockBackend::default()
+                .with_command("build", MockCommand { exit_code: 3, ..Default::default() })
+                .with_command("deploy", MockCommand::default());
+            let state = Arc::new(Mutex::new(ShellState::for_test()));
+            let commands: Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+            let code = run_batch(&commands, &state, Box::new(backend));
+            assert!(state.lock().unwrap().running.is_empty());
+            code
+        };
+        assert_eq!(run(&["deploy", "build"]), 3);
+        assert_eq!(run(&["build", "deploy"]), 0);
+        assert_eq!(run(&["build", "echo done"]), 0);
+        assert_eq!(run(&["echo done", "false"]), 1);
+        assert_eq!(run(&[]), 0);
+        // Failures that aren't an exit status of their own
+        assert_eq!(run(&["nosuchcmd_xyz"]), 127);
+        assert_eq!(run(&["ls /no/such/dir"]), 1);
+        assert_eq!(run(&["cd /no/such/dir"]), 1);
+        assert_eq!(run(&["date +%Q"]), 1);
+        // Bad usage is 2, whichever builtin it is
+        for usage in ["mv onlyone", "seq", "sleep soon", "watch", "progress 0", "macro nope", "rm -x file"] {
+            assert_eq!(run(&[usage]), 2, "{}", usage);
+        }
+        assert_eq!(run(&["ls /no/such/dir", "echo done"]), 0);
+    }
+
+    #[test]
+    fn test_builtins_act_in_their_own_pane_directory() {
+        let root = std::env::temp_dir().join("test_shell_pane_directories");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("left/sub")).unwrap();
+        std::fs::create_dir_all(root.join("right")).unwrap();
+        let process_cwd = env::current_dir().unwrap();
+        let backend: Arc<dyn ProcessBackend> = Arc::new(MockBackend::default());
+        let pane = |dir: &str| {
+            let mut state = ShellState::for_test();
+            state.current_dir = root.join(dir).to_string_lossy().to_string();
+            Arc::new(Mutex::new(state))
+        };
+        let (left, right) = (pane("left"), pane("right"));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let lines = |state: &Arc<Mutex<ShellState>>| -> Vec<String> {
+            state.lock().unwrap().screen.lines.iter().map(|line| line.cells.iter().map(|c| c.ch).collect()).collect()
+        };
+
+        execute_command("touch mine", &left, &tx, &backend);
+        execute_command("mkdir made", &right, &tx, &backend);
+        execute_command("cd sub", &left, &tx, &backend);
+        execute_command("touch deeper", &left, &tx, &backend);
+        execute_command("cp deeper copied", &left, &tx, &backend);
+        execute_command("find . -name copied", &left, &tx, &backend);
+        execute_command("test -d made", &right, &tx, &backend);
+
+        assert!(root.join("left/mine").is_file());
+        assert!(root.join("right/made").is_dir());
+        assert!(root.join("left/sub/copied").is_file());
+        assert_eq!(left.lock().unwrap().current_dir, root.join("left/sub").canonicalize().unwrap().to_string_lossy());
+        // Paths are shown the way they were typed
+        assert_eq!(lines(&left), vec![std::path::Path::new(".").join("copied").to_string_lossy()]);
+        assert_eq!(right.lock().unwrap().last_status, 0);
+        assert_eq!(env::current_dir().unwrap(), process_cwd);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_remote_backend_refuses_local_file_builtins() {
+        let dir = std::env::temp_dir().join("test_shell_remote_refuses_rm");
+        std::fs::create_dir_all(&dir).unwrap();
+        let backend: Arc<dyn ProcessBackend> = Arc::new(crate::backend::RemoteBackend::new(Default::default()));
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        execute_command(&format!("rm -r {}", dir.display()), &state, &tx, &backend);
+
+        assert!(dir.is_dir());
+        let s = state.lock().unwrap();
+        let line: String = s.screen.lines[0].cells.iter().map(|c| c.ch).collect();
+        assert!(line.starts_with("rm: only works on local files"));
+        assert_eq!(s.last_status, 1);
+        drop(s);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_du_totals_and_depth() {
+        let root = std::env::temp_dir().join("axiomterm_test_du");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("a/deep")).unwrap();
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("top.txt"), [0u8; 10]).unwrap();
+        std::fs::write(root.join("a/one"), [0u8; 100]).unwrap();
+        std::fs::write(root.join("a/deep/two"), [0u8; 1000]).unwrap();
+        std::fs::write(root.join("b/three"), [0u8; 5]).unwrap();
+
+        let backend = Arc::new(MockBackend::default());
+        let r = root.display();
+        let lines: Vec<String> = run(&format!("du {}", r), &backend).1.iter().map(|l| l.trim().to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!("1000  {}", root.join("a/deep").display()),
+                format!("1100  {}", root.join("a").display()),
+                format!("5  {}", root.join("b").display()),
+                format!("1115  {}", r),
+                "1115  total".to_string(),
+            ]
+        );
+
+        // Deeper directories still count toward the listed ones; the total covers every path
+        let lines: Vec<String> = run(&format!("du -d 0 {} {}", root.join("a").display(), root.join("top.txt").display()), &backend)
+            .1
+            .iter()
+            .map(|l| l.trim().to_string())
+            .collect();
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!(
+            lines,
+            vec![
+                format!("1100  {}", root.join("a").display()),
+                format!("10  {}", root.join("top.txt").display()),
+                "1110  total".to_string(),
+            ]
+        );
+
+        assert_eq!(run("du -d", &backend).1, vec!["du: missing argument to -d"]);
+        assert_eq!(DuOptions::parse(&[]).unwrap().paths, vec!["."]);
+    }
+
+    #[test]
+    fn test_rm_rejects_bad_options_and_root() {
+        let root = std::env::temp_dir().join("test_shell_rm_options");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("dir/sub")).unwrap();
+        std::fs::write(root.join("file"), "").unwrap();
+        let backend = Arc::new(MockBackend::default());
+
+        // The `r` in `--force` is not `-r`
+        let (state, lines) = run(&format!("rm --force {}", root.join("dir").display()), &backend);
+        assert_eq!(lines, vec!["rm: unrecognized option: --force", "Usage: rm [-r] [-f] [--] <path>..."]);
+        assert_eq!(state.lock().unwrap().last_status, 2);
+        assert!(root.join("dir/sub").is_dir());
+
+        let (state, lines) = run(&format!("rm -x {}", root.join("file").display()), &backend);
+        assert_eq!(lines[0], "rm: invalid option: -x");
+        assert_eq!(state.lock().unwrap().last_status, 2);
+        assert!(root.join("file").is_file());
+
+        let (state, lines) = run("rm -rf /", &backend);
+        assert_eq!(lines, vec!["rm: refusing to remove root directory '/'"]);
+        assert_eq!(state.lock().unwrap().last_status, 1);
+
+        let (state, _) = run(&format!("rm -f -- {}", root.join("file").display()), &backend);
+        assert_eq!(state.lock().unwrap().last_status, 0);
+        assert!(!root.join("file").exists());
+        assert_eq!(RmOptions::parse(&["--".to_string(), "-r".to_string()]).unwrap().paths, vec!["-r"]);
+        assert!(confirmation_question("rm --force dir").is_none());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_seq_ranges_and_usage() {
+        let backend = Arc::new(MockBackend::default());
+        assert_eq!(run("seq 3", &backend).1, vec!["1", "2", "3"]);
+        assert_eq!(run("seq 0 2 10", &backend).1, vec!["0", "2", "4", "6", "8", "10"]);
+        assert_eq!(run("seq 5 -2 1", &backend).1, vec!["5", "3", "1"]);
+        assert_eq!(run("seq 0 0.1 0.3", &backend).1, vec!["0.0", "0.1", "0.2", "0.3"]);
+        assert!(run("seq 3 1", &backend).1.is_empty());
+        // Fractional steps that land a rounding error short of `last` still reach it, however
+        // large the numbers are
+        assert_eq!(run("seq 1 0.3 2.2", &backend).1, vec!["1.0", "1.3", "1.6", "1.9", "2.2"]);
+        assert_eq!(run("seq 1000000 0.1 1000000.3", &backend).1.last().map(String::as_str), Some("1000000.3"));
+
+        // Ranges past the limit are refused up front instead of filling the screen
+        for huge in ["seq 1000000000", "seq 1e18"] {
+            let (state, lines) = run(huge, &backend);
+            assert_eq!(lines.len(), 1, "{}", huge);
+            assert!(lines[0].ends_with(&format!("numbers is more than the limit of {}", SEQ_MAX_VALUES)), "{}", huge);
+            assert_eq!(state.lock().unwrap().last_status, 1);
+        }
+        assert_eq!(seq_values(&[SEQ_MAX_VALUES.to_string()]).map(|values| values.len() as u64), Ok(SEQ_MAX_VALUES));
+
+        for bad in ["seq", "seq x", "seq 1 0 5", "seq 1 2 3 4"] {
+            let (state, lines) = run(bad, &backend);
+            assert_eq!(lines, vec!["Usage: seq [first [step]] last"], "{}", bad);
+            assert_eq!(state.lock().unwrap().last_status, 2);
+        }
+    }
+
+    #[test]
+    fn test_test_builtin_status() {
+        let backend = Arc::new(MockBackend::default());
+        let status = |cmd: &str| run(cmd, &backend).0.lock().unwrap().last_status;
+        let dir = std::env::temp_dir();
+        let dir = dir.display();
+
+        assert_eq!(status("true"), 0);
+        assert_eq!(status("false"), 1);
+        assert_eq!(status(&format!("test -d {}", dir)), 0);
+        assert_eq!(status(&format!("[ -f {} ]", dir)), 1);
+        assert_eq!(status(&format!("[ ! -f {} ]", dir)), 0);
+        assert_eq!(status("test -e /no/such/path"), 1);
+        assert_eq!(status("test -z ''"), 0);
+        assert_eq!(status("test -n ''"), 1);
+        assert_eq!(status("test abc = abc"), 0);
+        assert_eq!(status("test abc != abc"), 1);
+        assert_eq!(status("[ 10 -gt 9 ]"), 0);
+        assert_eq!(status("[ 3 -le 2 ]"), 1);
+        assert_eq!(status("test"), 1);
+
+        for (cmd, message) in [
+            ("[ -f x", "[: missing ']'"),
+            ("test a -lt 2", "test: a: integer expression expected"),
+            ("test -q x", "test: -q: unary operator expected"),
+            ("test a b c d", "test: too many arguments"),
+        ] {
+            let (state, lines) = run(cmd, &backend);
+            assert_eq!(lines, vec![message]);
+            assert_eq!(state.lock().unwrap().last_status, 2);
+            assert_eq!(state.lock().unwrap().screen.lines[0].cells[0].fg, TerminalColor::RED);
+        }
+    }
+
+    #[test]
+    fn test_env_assignments_apply_to_one_command() {
+        let backend = Arc::new(MockBackend::default().with_command("make", MockCommand::default()));
+        let (state, _) = run("CC=clang CFLAGS='-O2 -g' make all", &backend);
+        assert_eq!(*backend.calls.lock().unwrap(), vec![vec!["CC=clang", "CFLAGS=-O2 -g", "make", "all"]]);
+        assert!(state.lock().unwrap().env_overrides.is_empty());
+
+        // Only leading words count; later ones are ordinary arguments
+        backend.calls.lock().unwrap().clear();
+        run("make CC=gcc", &backend);
+        assert_eq!(*backend.calls.lock().unwrap(), vec![vec!["make", "CC=gcc"]]);
+
+        let (_, lines) = run("AXIOMTERM_ONE_OFF=1 env", &backend);
+        assert!(lines.contains(&"AXIOMTERM_ONE_OFF=1".to_string()));
+
+        let (state, lines) = run("AXIOMTERM_ONE_OFF=1", &backend);
+        assert!(lines.is_empty());
+        assert_eq!(state.lock().unwrap().env_overrides.get("AXIOMTERM_ONE_OFF"), Some(&Some("1".to_string())));
+    }
+
+    #[test]
+    fn test_export_is_scoped_to_shell_state() {
+        let backend = Arc::new(MockBackend::default());
+        let (state, _) = run("export AXIOMTERM_TEST_VAR=1", &backend);
+        let s = state.lock().unwrap();
+        assert_eq!(s.environment().get("AXIOMTERM_TEST_VAR").map(String::as_str), Some("1"));
+        assert!(std::env::var_os("AXIOMTERM_TEST_VAR").is_none());
+    }
+
+    #[test]
+    fn test_config_validate_does_not_apply() {
+        let path = std::env::temp_dir().join("test_shell_config_validate.lua");
+        std::fs::write(&path, "font_size = 30\ncursor_color = \"blue\"\n").unwrap();
+        let (state, lines) = run(&format!("config validate {}", path.display()), &Arc::new(MockBackend::default()));
+        std::fs::write(&path, "font_size = = 30\n").unwrap();
+        let (_, error_lines) = run(&format!("config validate {}", path.display()), &Arc::new(MockBackend::default()));
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(state.lock().unwrap().font_size, ShellState::for_test().font_size);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("cursor_color"));
+        assert!(lines[1].starts_with("Config parsed with 1 warning(s)"));
+        assert!(error_lines[0].starts_with("test_shell_config_validate.lua:1:"));
+    }
+
+    #[test]
+    fn test_config_reload_on_save() {
+        let path = std::env::temp_dir().join("test_shell_config_reload_on_save.lua");
+        let backend: Arc<dyn ProcessBackend> = Arc::new(MockBackend::default());
+        let cwd = env::current_dir().unwrap();
+        // (auto, config_reload_quiet) -> whether the message is a toast
+        for (auto, quiet, toast) in [(false, true, false), (true, false, false), (true, true, true)] {
+            std::fs::write(&path, format!(
+                "notifications = \"inline\"\nconfig_reload_quiet = {}\ndefault_cwd = {:?}\n",
+                quiet,
+                std::env::temp_dir().display(),
+            )).unwrap();
+            let state = Arc::new(Mutex::new(ShellState::for_test()));
+            let (tx, rx) = crossbeam_channel::unbounded();
+            if auto {
+                load_config(&path, true, &state, &tx, backend.as_ref());
+            } else {
+                execute_command(&format!("config load {}", path.display()), &state, &tx, &backend);
+            }
+            let toasts: Vec<_> = rx.try_iter().filter_map(|event| match event {
+                ShellEvent::Notification(notice) => Some(notice.text),
+                _ => None,
+            }).collect();
+            let s = state.lock().unwrap();
+            let loaded_lines = s.screen.lines.iter()
+                .filter(|line| line.cells.iter().map(|c| c.ch).collect::<String>().starts_with("Config loaded from: "))
+                .count();
+            assert_eq!(loaded_lines, usize::from(!toast));
+            assert_eq!(toasts.len(), usize::from(toast));
+            // Only `config load` moves the pane to default_cwd, and never the process
+            let expected = if auto {
+                ShellState::for_test().current_dir
+            } else {
+                std::env::temp_dir().canonicalize().unwrap().to_string_lossy().to_string()
+            };
+            assert_eq!(s.current_dir, expected);
+            assert_eq!(env::current_dir().unwrap(), cwd);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_ls_dims_hidden_files() {
+        let dir = std::env::temp_dir().join("test_shell_ls_hidden_dim");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join(".config")).unwrap();
+        std::fs::write(dir.join(".profile"), "").unwrap();
+        std::fs::write(dir.join("notes"), "").unwrap();
+
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().hidden_file_dim = 0.5;
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let backend: Arc<dyn ProcessBackend> = Arc::new(MockBackend::default());
+        execute_command(&format!("ls -a {}", dir.display()), &state, &tx, &backend);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let s = state.lock().unwrap();
+        let colors: Vec<(String, TerminalColor)> = s.screen.lines.iter()
+            .map(|line| (line.cells.iter().map(|c| c.ch).collect(), line.cells[0].fg))
+            .collect();
+        assert_eq!(colors, vec![
+            (".config".to_string(), s.directory_color.dim(0.5)),
+            (".profile".to_string(), s.text_color.dim(0.5)),
+            ("notes".to_string(), s.text_color),
+        ]);
+        assert_eq!(TerminalColor::from_rgb(200, 100, 0).dim(0.5), TerminalColor::from_rgb(100, 50, 0));
+        assert_eq!(TerminalColor::GOLD.dim(1.0), TerminalColor::GOLD);
+    }
+
+    #[test]
+    fn test_reset_keeps_output() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        {
+            let mut s = state.lock().unwrap();
+            s.screen.push_line(Line::from_string("earlier output", TerminalColor::WHITE));
+            s.prompt = "$ ".to_string();
+            s.font_size = 40.0;
+        }
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let backend: Arc<dyn ProcessBackend> = Arc::new(MockBackend::default());
+        execute_command("reset", &state, &tx, &backend);
+        let s = state.lock().unwrap();
+        assert_eq!(s.prompt, "> ");
+        assert_eq!(s.font_size, 14.0);
+        assert_eq!(s.screen.lines.len(), 2);
+        assert!(s.screen.lines[1].cells.iter().map(|c| c.ch).collect::<String>().starts_with("Appearance reset"));
+    }
+
+    #[test]
+    fn test_config_dump_shows_active_settings() {
+        let (_, lines) = run("config dump", &Arc::new(MockBackend::default()));
+        let s = ShellState::for_test();
+        assert!(lines.contains(&format!("prompt = {:?}", s.prompt)));
+        assert!(lines.contains(&format!("error_color = \"{}\"", TerminalColor::RED.to_hex())));
+        assert!(lines.iter().any(|l| l.starts_with("font_size = ")));
+        assert!(lines.iter().any(|l| l.starts_with("-- started in the directory from process cwd (--cwd > default_cwd > process cwd)")));
+    }
+
+    #[test]
+    fn test_initial_cwd_falls_back_in_precedence_order() {
+        let backend = MockBackend::default();
+        let cwd = env::current_dir().unwrap().to_string_lossy().to_string();
+
+        let (dir, source, errors) = resolve_initial_cwd(&backend, None, None);
+        assert_eq!((dir.as_str(), source, errors.len()), (cwd.as_str(), CwdSource::Process, 0));
+
+        // Neither candidate exists, so both are reported and the process cwd is kept
+        let (dir, source, errors) = resolve_initial_cwd(&backend, Some("/no/such/cli/dir"), Some("/no/such/config/dir"));
+        assert_eq!((dir.as_str(), source), (cwd.as_str(), CwdSource::Process));
+        assert!(errors[0].starts_with("Failed to start in /no/such/cli/dir from --cwd"));
+        assert!(errors[1].starts_with("Failed to start in /no/such/config/dir from default_cwd"));
+    }
+
+    #[test]
+    fn test_input_edits_move_screen_cursor() {
+        use crate::types::{Cursor, ScreenOperation};
+
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let (action_tx, action_rx) = crossbeam_channel::unbounded();
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        spawn_shell_thread(action_rx, output_tx, state.clone(), Box::new(MockBackend::default()), || {});
+
+        let timeout = std::time::Duration::from_secs(5);
+        action_tx.send(Action::AppendChar('a')).unwrap();
+        action_tx.send(Action::AppendChar('b')).unwrap();
+        action_tx.send(Action::CursorLeft).unwrap();
+        action_tx.send(Action::SetInputCursor(0)).unwrap();
+        action_tx.send(Action::SetInputCursor(9)).unwrap();
+        for col in [1, 2, 1, 0, 2] {
+            let event = output_rx.recv_timeout(timeout).unwrap();
+            assert!(matches!(event, ShellEvent::Operation(ScreenOperation::SetCursor(Cursor { row: 0, col: c })) if c == col));
+        }
+
+        // Submitting echoes the line, then puts the cursor at the start of the next prompt row
+        action_tx.send(Action::Submit).unwrap();
+        let events: Vec<_> = (0..3).map(|_| output_rx.recv_timeout(timeout).unwrap()).collect();
+        assert!(matches!(events[0], ShellEvent::Operation(ScreenOperation::PushLine(_))));
+        assert!(matches!(events.last(), Some(ShellEvent::Operation(ScreenOperation::SetCursor(c))) if *c == state.lock().unwrap().prompt_cursor()));
+        assert_eq!(state.lock().unwrap().screen.cursor.col, 0);
+    }
+
+    #[test]
+    fn test_move_cursor_is_clamped_to_the_screen() {
+        use crate::types::{Cursor, ScreenOperation};
+
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        {
+            let mut s = state.lock().unwrap();
+            for text in ["first line", "ab"] {
+                s.screen.push_line(Line::from_string(text, TerminalColor::WHITE));
+            }
+            s.input_buffer = "xyz".to_string();
+            s.input_cursor = 3;
+            s.screen.cursor = s.prompt_cursor();
+        }
+        let (action_tx, action_rx) = crossbeam_channel::unbounded();
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        spawn_shell_thread(action_rx, output_tx, state.clone(), Box::new(MockBackend::default()), || {});
+
+        let moves = [
+            ((-1, 0), Cursor { row: 1, col: 1 }),   // "ab" is narrower than the prompt column
+            ((-1, 5), Cursor { row: 0, col: 6 }),
+            ((-3, 0), Cursor { row: 0, col: 6 }),   // Can't go above the first line
+            ((0, -20), Cursor { row: 0, col: 0 }),  // ...or left of the first column
+            ((9, 99), Cursor { row: 2, col: 3 }),   // The prompt row ends just past the input
+        ];
+        let timeout = std::time::Duration::from_secs(5);
+        for ((d_row, d_col), expected) in moves {
+            action_tx.send(Action::MoveCursor(d_row, d_col)).unwrap();
+            let event = output_rx.recv_timeout(timeout).unwrap();
+            assert!(matches!(event, ShellEvent::Operation(ScreenOperation::SetCursor(c)) if c == expected), "{:?}", (d_row, d_col));
+        }
+        assert_eq!(Action::from_str("MoveCursor(-1, 2)"), Some(Action::MoveCursor(-1, 2)));
+    }
+
+    #[test]
+    fn test_typing_during_search_edits_query() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().input_buffer = "draft".to_string();
+        let send = shell(&state);
+
+        let actions = [Action::ReverseSearch, Action::AppendChar('g'), Action::AppendChar('x'), Action::Backspace, Action::AppendChar('i')];
+        for action in actions {
+            send(action);
+        }
+
+        let s = state.lock().unwrap();
+        assert_eq!(s.history_search.as_ref().map(|search| search.query.as_str()), Some("gi"));
+        assert_eq!(s.input_buffer, "draft");
+    }
+
+    #[test]
+    fn test_font_size_zoom_and_reset() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        {
+            let mut s = state.lock().unwrap();
+            s.font_size = MAX_FONT_SIZE - 1.0;
+            s.configured_font_size = 14.0;
+        }
+        let send = shell(&state);
+        let run = |action: Action| {
+            send(action);
+            state.lock().unwrap().font_size
+        };
+
+        assert_eq!(run(Action::FontSizeUp), MAX_FONT_SIZE);
+        assert_eq!(run(Action::FontSizeUp), MAX_FONT_SIZE);
+        assert_eq!(run(Action::FontSizeDown), MAX_FONT_SIZE - FONT_SIZE_STEP);
+        assert_eq!(run(Action::FontSizeReset), 14.0);
+    }
+
+    #[test]
+    fn test_finished_commands_are_reaped() {
+        let backend = Arc::new(MockBackend::default().with_command("make", MockCommand::default()));
+        let (state, _) = run("make", &backend);
+        {
+            let mut s = state.lock().unwrap();
+            assert_eq!(s.running.len(), 1);
+            assert_eq!(s.running[0].command_line, "make");
+            // Far above how long the mock ran, so no notification is attempted
+            s.notify_after_secs = Some(1000.0);
+            s.window_focused = false;
+        }
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        reap_finished(&state, &output_tx);
+        assert!(state.lock().unwrap().running.is_empty());
+        assert!(output_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_focused_completion_follows_notification_style() {
+        let backend = Arc::new(MockBackend::default().with_command("make", MockCommand::default()));
+        for style in NotificationStyle::ALL {
+            let (state, _) = run("make", &backend);
+            let lines_before = {
+                let mut s = state.lock().unwrap();
+                s.notify_after_secs = Some(0.0);
+                s.window_focused = true;
+                s.notifications = style;
+                s.screen.lines.len()
+            };
+            let (output_tx, output_rx) = crossbeam_channel::unbounded();
+            reap_finished(&state, &output_tx);
+            let events: Vec<_> = output_rx.try_iter().collect();
+            let s = state.lock().unwrap();
+            match style {
+                NotificationStyle::Toast => {
+                    assert!(matches!(&events[..], [ShellEvent::Notification(n)] if n.text.starts_with("make: Finished")));
+                    assert_eq!(s.screen.lines.len(), lines_before);
+                }
+                NotificationStyle::Inline => {
+                    assert!(matches!(&events[..], [ShellEvent::Operation(_)]));
+                    let text: String = s.screen.lines.last().unwrap().cells.iter().map(|c| c.ch).collect();
+                    assert!(text.starts_with("make: Finished"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_opacity_steps_are_clamped() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().opacity = 0.2;
+        let send = shell(&state);
+        let run = |action: Action| {
+            send(action);
+            state.lock().unwrap().opacity
+        };
+
+        assert_eq!(run(Action::OpacityDown), 0.15);
+        assert_eq!(run(Action::OpacityDown), MIN_OPACITY);
+        assert_eq!(run(Action::OpacityDown), MIN_OPACITY);
+        for _ in 0..20 {
+            run(Action::OpacityUp);
+        }
+        assert_eq!(run(Action::OpacityUp), 1.0);
+        assert_eq!(run(Action::OpacityDown), 0.95);
+    }
+
+    #[test]
+    fn test_confirm_destructive_asks_before_running() {
+        let dir = std::env::temp_dir().join("test_shell_confirm_destructive");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().confirm_destructive = true;
+        let send = shell(&state);
+        let submit = |line: &str| {
+            state.lock().unwrap().input_buffer = line.to_string();
+            send(Action::Submit);
+            state.lock().unwrap().screen.lines.last().map(|l| l.cells.iter().map(|c| c.ch).collect::<String>())
+        };
+        let rm = format!("rm -r {}", dir.display());
+
+        assert_eq!(submit(&rm), Some(format!("rm: remove {} recursively? [y/n]", dir.display())));
+        assert_eq!(submit("n"), Some("Cancelled".to_string()));
+        assert!(dir.exists());
+
+        submit(&rm);
+        submit("y");
+        assert!(!dir.exists());
+        // Answers aren't commands, so they stay out of the history
+        assert_eq!(state.lock().unwrap().history, vec![rm]);
+
+        assert_eq!(submit("clear"), Some("Clear the screen and its scrollback? [y/n]".to_string()));
+        assert_eq!(submit("yes"), None);
+        // Only recursive rm asks
+        assert!(confirmation_question("rm -f file").is_none());
+        assert!(confirmation_question("rm -fR dir").is_some());
+    }
+
+    #[test]
+    fn test_clear_on_command() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().clear_on_command = true;
+        state.lock().unwrap().confirm_destructive = true;
+        let send = shell(&state);
+        let submit = |line: &str| {
+            for action in [Action::ClearLine, Action::Paste(line.to_string()), Action::Submit] {
+                send(action);
+            }
+            let s = state.lock().unwrap();
+            s.screen.lines.iter().map(|l| l.cells.iter().map(|c| c.ch).collect::<String>()).collect::<Vec<_>>()
+        };
+
+        submit("echo one");
+        let lines = submit("echo two");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("echo two"));
+        assert_eq!(lines[1], "two");
+
+        // The answer to a question stays under it
+        submit("rm -r build");
+        let lines = submit("n");
+        assert!(lines[0].ends_with("rm -r build"));
+        assert_eq!(lines.last().unwrap(), "Cancelled");
+    }
+
+    #[test]
+    fn test_multiline_paste_never_runs_without_enter() {
+        for mode in PasteMode::ALL {
+            let state = Arc::new(Mutex::new(ShellState::for_test()));
+            state.lock().unwrap().multiline_paste = mode;
+            let run = shell(&state);
+            let send = |action: Action| {
+                run(action);
+                let s = state.lock().unwrap();
+                let last = s.screen.lines.last().map(|l| l.cells.iter().map(|c| c.ch).collect::<String>());
+                (s.input_buffer.clone(), last)
+            };
+
+            state.lock().unwrap().input_buffer = "x; ".to_string();
+            state.lock().unwrap().input_cursor = 3;
+            let (input, _) = send(Action::Paste("echo one\r\n\necho 'two\nlines'\necho three\n".to_string()));
+            match mode {
+                PasteMode::Step => {
+                    assert_eq!(input, "x; echo one");
+                    assert_eq!(state.lock().unwrap().pasted_lines, ["echo 'two", "lines'", "echo three"]);
+                    send(Action::ClearLine);
+                    send(Action::AppendChar('#'));
+                    assert_eq!(send(Action::Submit).0, "echo 'two");
+                    assert_eq!(send(Action::Submit).0, "lines'");
+                    assert_eq!(send(Action::Submit), ("echo three".to_string(), Some("two\nlines".to_string())));
+                    send(Action::Interrupt);
+                    assert!(state.lock().unwrap().pasted_lines.is_empty());
+                }
+                PasteMode::Confirm => {
+                    assert_eq!(input, "x; ");
+                    assert_eq!(
+                        state.lock().unwrap().pending_confirmation,
+                        Some(vec!["echo one".to_string(), "echo 'two\nlines'".to_string(), "echo three".to_string()])
+                    );
+                    send(Action::ClearLine);
+                    send(Action::AppendChar('y'));
+                    assert_eq!(send(Action::Submit), (String::new(), Some("three".to_string())));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_huge_submitted_line_is_cut() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let send = shell(&state);
+        state.lock().unwrap().input_buffer = format!("echo {}", "x".repeat(1_000_000));
+        send(Action::Submit);
+
+        let s = state.lock().unwrap();
+        let max = DEFAULT_MAX_INPUT_LENGTH;
+        let lines: Vec<usize> = s.screen.lines.iter().map(|l| l.cells.len()).collect();
+        // The echoed prompt line, the warning, then echo's output
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0] <= s.active_prompt().chars().count() + max);
+        assert_eq!(lines[2], max - "echo ".len());
+        assert_eq!(s.history[0].len(), max);
+    }
+
+    #[test]
+    fn test_unterminated_line_continues_on_next_submit() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        state.lock().unwrap().prompt = "$ ".to_string();
+        let send = shell(&state);
+        let submit = |line: &str| {
+            state.lock().unwrap().input_buffer = line.to_string();
+            send(Action::Submit);
+            state.lock().unwrap().screen.lines.iter().map(|l| l.cells.iter().map(|c| c.ch).collect()).collect::<Vec<String>>()
+        };
+
+        submit("echo 'one");
+        assert_eq!(state.lock().unwrap().active_prompt(), crate::types::CONTINUATION_PROMPT);
+        submit("two");
+        let lines = submit("three'");
+        assert_eq!(lines.last().unwrap(), "one\ntwo\nthree");
+        assert_eq!(state.lock().unwrap().active_prompt(), "$ ");
+        assert_eq!(state.lock().unwrap().history, vec!["echo 'one\ntwo\nthree'"]);
+
+        submit("echo split \\");
+        assert_eq!(submit("line").last().unwrap(), "split line");
+
+        // Ctrl+C abandons the unfinished command
+        submit("echo \"never");
+        send(Action::Interrupt);
+        assert!(state.lock().unwrap().continuation.is_none());
+        assert_eq!(submit("echo next").last().unwrap(), "next");
+    }
+
+    #[test]
+    fn test_sleep_defers_later_commands() {
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let send = shell(&state);
+        let text = |state: &Arc<Mutex<ShellState>>| -> Vec<String> {
+            state.lock().unwrap().screen.lines.iter().map(|l| l.cells.iter().map(|c| c.ch).collect()).collect()
+        };
+
+        let start = Instant::now();
+        for cmd in ["sleep 0.2", "echo after"] {
+            send(Action::RunCommand(cmd.to_string()));
+        }
+        assert!(text(&state).is_empty());
+        while text(&state).is_empty() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(text(&state), vec!["after"]);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // Interrupt cancels the sleep along with everything queued behind it
+        for action in [Action::RunCommand("sleep 30".to_string()), Action::RunCommand("echo dropped".to_string()), Action::Interrupt, Action::RunCommand("echo next".to_string())] {
+            send(action);
+        }
+        assert_eq!(text(&state), vec!["after", "next"]);
+    }
+
+    #[test]
+    fn test_watch_redraws_in_place_until_interrupted() {
+        use crate::types::ScreenOperation;
+
+        let backend = MockBackend::default().with_command("date", MockCommand { stdout: vec!["tick".to_string()], ..Default::default() });
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let (action_tx, action_rx) = crossbeam_channel::unbounded();
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        spawn_shell_thread(action_rx, output_tx, state.clone(), Box::new(backend), || {});
+
+        action_tx.send(Action::RunCommand("watch -n 0.01 date".to_string())).unwrap();
+        let timeout = Duration::from_secs(5);
+        loop {
+            if let ShellEvent::Operation(ScreenOperation::UpdateLine(1, line)) = output_rx.recv_timeout(timeout).unwrap() {
+                assert_eq!(line.cells.iter().map(|c| c.ch).collect::<String>(), "tick");
+                break;
+            }
+        }
+
+        action_tx.send(Action::Interrupt).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let s = state.lock().unwrap();
+        assert_eq!(s.interrupts, 1);
+        assert_eq!(s.screen.lines.len(), 2);
+        assert!(s.screen.lines[0].cells.iter().map(|c| c.ch).collect::<String>().starts_with("Every 0.01s: date"));
+    }
+
+    #[test]
+    fn test_watch_stops_when_the_screen_is_cleared() {
+        use crate::types::ScreenOperation;
+
+        let backend = MockBackend::default().with_command("date", MockCommand { stdout: vec!["tick".to_string()], ..Default::default() });
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let (action_tx, action_rx) = crossbeam_channel::unbounded();
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        spawn_shell_thread(action_rx, output_tx, state.clone(), Box::new(backend), || {});
+
+        action_tx.send(Action::RunCommand("watch -n 0.01 date".to_string())).unwrap();
+        let timeout = Duration::from_secs(5);
+        while !matches!(output_rx.recv_timeout(timeout).unwrap(), ShellEvent::Operation(ScreenOperation::UpdateLine(1, _))) {}
+
+        // Cleared, then refilled past the rows watch was drawing in
+        {
+            let mut s = state.lock().unwrap();
+            s.screen.clear();
+            for i in 0..5 {
+                s.screen.push_line(Line::from_string(&format!("later {}", i), TerminalColor::WHITE));
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+        let s = state.lock().unwrap();
+        let lines: Vec<String> = s.screen.lines.iter().map(|line| line.cells.iter().map(|c| c.ch).collect()).collect();
+        assert_eq!(lines, (0..5).map(|i| format!("later {}", i)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_progress_stops_when_cleared_or_interrupted() {
+        let backend: Arc<dyn ProcessBackend> = Arc::new(MockBackend::default());
+        let text = |s: &ShellState| -> Vec<String> { s.screen.lines.iter().map(|line| line.cells.iter().map(|c| c.ch).collect()).collect() };
+
+        // Cleared and refilled before the first step lands
+        let state = Arc::new(Mutex::new(ShellState::for_test()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        execute_command("progress 3", &state, &tx, &backend);
+        {
+            let mut s = state.lock().unwrap();
+            s.screen.clear();
+            s.screen.push_line(Line::from_string("later", TerminalColor::WHITE));
+        }
+        // Interrupted before the first step lands
+        let interrupted = Arc::new(Mutex::new(ShellState::for_test()));
+        execute_command("progress 3", &interrupted, &tx, &backend);
+        let start = text(&interrupted.lock().unwrap());
+        interrupted.lock().unwrap().interrupts += 1;
+
+        thread::sleep(PROGRESS_STEP_INTERVAL * 5);
+        assert_eq!(text(&state.lock().unwrap()), vec!["later"]);
+        assert_eq!(text(&interrupted.lock().unwrap()), start);
+    }
+}
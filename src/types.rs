@@ -1,4 +1,34 @@
 
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+pub const DEFAULT_WINDOW_TITLE_FORMAT: &str = "[{mode}] {title}";
+/// Prompt shown while a command with an unclosed quote waits for its next line.
+pub const CONTINUATION_PROMPT: &str = "> ";
+pub const DEFAULT_PADDING: f32 = 6.0;
+pub const MIN_FONT_SIZE: f32 = 6.0;
+pub const MAX_FONT_SIZE: f32 = 96.0;
+/// Points added or removed by one `FontSizeUp` / `FontSizeDown`.
+pub const FONT_SIZE_STEP: f32 = 1.0;
+/// Window opacity added or removed by one `OpacityUp` / `OpacityDown`, and the lowest it goes
+/// so the window can't be nudged out of sight.
+pub const OPACITY_STEP: f32 = 0.05;
+pub const MIN_OPACITY: f32 = 0.1;
+/// How long a key must be held before it starts repeating, and how often it repeats after that.
+pub const DEFAULT_KEY_REPEAT_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_KEY_REPEAT_RATE: f32 = 30.0;
+/// How long the config file must go unchanged after a save before it is reloaded.
+pub const DEFAULT_CONFIG_RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Characters besides alphanumerics that count as part of a word.
+pub const DEFAULT_WORD_CHARS: &str = "_";
+/// Longest command line Enter will submit, in chars; anything past it is cut off with a
+/// warning, so a huge accidental paste can't swamp the shell and the screen.
+pub const DEFAULT_MAX_INPUT_LENGTH: usize = 64 * 1024;
+pub const MIN_MAX_INPUT_LENGTH: usize = 256;
+pub const MAX_MAX_INPUT_LENGTH: usize = 16 * 1024 * 1024;
+/// Selecting copies right away on Linux, where that's what the primary selection has taught users to expect.
+pub const DEFAULT_COPY_ON_SELECT: bool = cfg!(target_os = "linux");
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TerminalColor {
     pub r: u8,
@@ -19,6 +49,19 @@ impl TerminalColor {
     pub const WHITE: Self = Self::from_rgb(255, 255, 255);
     pub const GOLD: Self = Self::from_rgb(255, 215, 0);
     pub const GRAY: Self = Self::from_rgb(128, 128, 128);
+    pub const CYAN: Self = Self::from_rgb(0, 200, 200);
+
+    /// The color scaled toward black: `1.0` leaves it as is, `0.0` makes it black.
+    pub fn dim(self, factor: f32) -> Self {
+        let factor = factor.clamp(0.0, 1.0);
+        let scale = |c: u8| (c as f32 * factor).round() as u8;
+        Self::from_rgb(scale(self.r), scale(self.g), scale(self.b))
+    }
+
+    /// Formats the color the way config files write it, e.g. `#FFD700`.
+    pub fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -94,14 +137,12 @@ pub enum ScreenOperation {
     Clear,
     #[allow(dead_code)]
     SetCursor(Cursor),
-    #[allow(dead_code)]
     UpdateLine(usize, Line), // Visual update: row index, new content
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OperationCategory {
     Structural, // Affects layout (scroll, resize, clear)
-    #[allow(dead_code)]
     Visual,     // Affects content only (no layout shift)
     Cursor,     // Affects cursor layer only
 }
@@ -138,11 +179,44 @@ impl ScreenOperation {
     }
 }
 
+/// How a pane's renderer has handled screen operations so far. The renderer keeps the live
+/// counters; the pane copies them into its [`ShellState`] for the `metrics` builtin.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderMetrics {
+    pub structural_ops: usize,
+    pub visual_ops: usize,
+    pub cursor_ops: usize,
+    // Rows invalidated since the last draw; `usize::MAX` when the whole screen was
+    pub dirty_line_count: usize,
+    // Operations folded into another re-layout instead of triggering their own
+    pub coalesced_ops: usize,
+}
+
+impl RenderMetrics {
+    /// Human-readable summary for `metrics`.
+    pub fn report_lines(&self) -> Vec<String> {
+        let dirty = match self.dirty_line_count {
+            usize::MAX => "all".to_string(),
+            count => count.to_string(),
+        };
+        vec![
+            format!(
+                "render: {} structural, {} visual, {} cursor op(s), {} coalesced into a re-layout",
+                self.structural_ops, self.visual_ops, self.cursor_ops, self.coalesced_ops
+            ),
+            format!("  dirty lines awaiting a draw: {}", dirty),
+        ]
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Screen {
     pub lines: Vec<Line>,
     pub cursor: Cursor,
     pub meta: ScreenMeta,
+    // Bumped by every `clear`, so code that remembers a row index can tell the rows were
+    // thrown away, even once new output has refilled them
+    pub clears: u64,
 }
 
 impl Screen {
@@ -161,17 +235,16 @@ impl Screen {
         self.lines.clear();
         self.cursor = Cursor::default();
         self.meta.dirty = true;
+        self.clears += 1;
         ScreenOperation::Clear
     }
 
-    #[allow(dead_code)]
     pub fn set_cursor(&mut self, cursor: Cursor) -> ScreenOperation {
         self.cursor = cursor;
         self.meta.dirty = true;
         ScreenOperation::SetCursor(cursor)
     }
 
-    #[allow(dead_code)]
     pub fn update_line(&mut self, row: usize, line: Line) -> ScreenOperation {
         if row < self.lines.len() {
             self.lines[row] = line.clone();
@@ -204,10 +277,42 @@ pub enum Action {
     Delete,
     Submit,          // Typically Enter
     Clear,           // Clear screen
-    #[allow(dead_code)]
-    MoveCursor(i32, i32), // Delta move
+    ClearLine,       // Clear the input line (Ctrl+U)
+    DeleteWord,      // Delete the word before the cursor (Ctrl+W)
+    MoveToStart,     // Cursor to start of input (Ctrl+A)
+    MoveToEnd,       // Cursor to end of input (Ctrl+E)
+    CursorLeft,      // Input cursor one char left
+    CursorRight,     // Input cursor one char right
+    SetInputCursor(usize), // Input cursor to a char column (mouse click)
+    Paste(String),   // Clipboard text with more than one line (see `multiline_paste`)
+    ReverseSearch,   // Start reverse history search, or step to an older match (Ctrl+R)
+    AcceptSearch,    // Copy the current search match into the input line
+    CancelSearch,    // Leave history search without changing the input line
+    ScrollTop,       // Scrollback to the first line (gg)
+    ScrollBottom,    // Scrollback to the latest line (G)
+    ScrollPageUp,    // Scrollback up one viewport (Ctrl+U in Normal mode)
+    ScrollPageDown,  // Scrollback down one viewport (Ctrl+D in Normal mode)
+    FontSizeUp,      // Zoom in one step (Ctrl+=)
+    FontSizeDown,    // Zoom out one step (Ctrl+-)
+    FontSizeReset,   // Back to the configured font size (Ctrl+0)
+    OpacityUp,       // Less transparent by one step (Ctrl+Shift+Up)
+    OpacityDown,     // More transparent by one step (Ctrl+Shift+Down)
+    SplitPane,       // Open a new pane beside the focused one (Ctrl+Shift+D)
+    ClosePane,       // Close the focused pane, unless it is the last (Ctrl+Shift+W)
+    FocusNextPane,   // Focus the pane to the right, wrapping around (Alt+Right)
+    FocusPrevPane,   // Focus the pane to the left, wrapping around (Alt+Left)
+    NewTab,          // Open a tab after the active one (Ctrl+Shift+T)
+    CloseTab,        // Close the active tab, unless it is the last (Ctrl+F4)
+    NextTab,         // Switch to the next tab, wrapping around (Ctrl+Tab)
+    PrevTab,         // Switch to the previous tab, wrapping around (Ctrl+Shift+Tab)
+    SelectTab(usize), // Switch to the nth tab, counting from 1 (Ctrl+1..9)
+    CommandPalette,  // Search builtins and macros and run one (Ctrl+P)
+    Interrupt,       // Stop `sleep`/`watch` and kill running commands (Ctrl+C)
+    MoveCursor(i32, i32), // Screen cursor by (rows, columns), e.g. over the scrollback
     ChangeMode(TerminalMode),
     RunCommand(String),
+    // Load the default config file; `auto` when it was saved rather than asked for
+    ReloadConfig { auto: bool },
     NoOp,
 }
 
@@ -218,6 +323,34 @@ impl Action {
             "Delete" => Some(Self::Delete),
             "Submit" | "Enter" => Some(Self::Submit),
             "Clear" => Some(Self::Clear),
+            "ClearLine" => Some(Self::ClearLine),
+            "DeleteWord" => Some(Self::DeleteWord),
+            "MoveToStart" => Some(Self::MoveToStart),
+            "MoveToEnd" => Some(Self::MoveToEnd),
+            "CursorLeft" => Some(Self::CursorLeft),
+            "CursorRight" => Some(Self::CursorRight),
+            "ReverseSearch" => Some(Self::ReverseSearch),
+            "AcceptSearch" => Some(Self::AcceptSearch),
+            "CancelSearch" => Some(Self::CancelSearch),
+            "ScrollTop" => Some(Self::ScrollTop),
+            "ScrollBottom" => Some(Self::ScrollBottom),
+            "ScrollPageUp" => Some(Self::ScrollPageUp),
+            "ScrollPageDown" => Some(Self::ScrollPageDown),
+            "FontSizeUp" => Some(Self::FontSizeUp),
+            "FontSizeDown" => Some(Self::FontSizeDown),
+            "FontSizeReset" => Some(Self::FontSizeReset),
+            "OpacityUp" => Some(Self::OpacityUp),
+            "OpacityDown" => Some(Self::OpacityDown),
+            "SplitPane" => Some(Self::SplitPane),
+            "ClosePane" => Some(Self::ClosePane),
+            "FocusNextPane" => Some(Self::FocusNextPane),
+            "FocusPrevPane" => Some(Self::FocusPrevPane),
+            "NewTab" => Some(Self::NewTab),
+            "CloseTab" => Some(Self::CloseTab),
+            "NextTab" => Some(Self::NextTab),
+            "PrevTab" => Some(Self::PrevTab),
+            "CommandPalette" => Some(Self::CommandPalette),
+            "Interrupt" => Some(Self::Interrupt),
             "NoOp" => Some(Self::NoOp),
             _ if s.starts_with("ChangeMode(") && s.ends_with(')') => {
                 let mode_str = &s[11..s.len()-1];
@@ -227,6 +360,13 @@ impl Action {
                 let cmd = &s[11..s.len()-1];
                 Some(Self::RunCommand(cmd.to_string()))
             },
+            _ if s.starts_with("MoveCursor(") && s.ends_with(')') => {
+                let (rows, cols) = s[11..s.len()-1].split_once(',')?;
+                Some(Self::MoveCursor(rows.trim().parse().ok()?, cols.trim().parse().ok()?))
+            },
+            _ if s.starts_with("SelectTab(") && s.ends_with(')') => {
+                s[10..s.len()-1].trim().parse().ok().map(Self::SelectTab)
+            },
             _ if s.starts_with("InsertChar(") && s.ends_with(')') => {
                 let char_str = &s[11..s.len()-1];
                 char_str.chars().next().map(Self::AppendChar)
@@ -235,12 +375,35 @@ impl Action {
             _ => None,
         }
     }
+
+    /// Builds an action from its name and optional argument kept apart, as in the macro
+    /// table form `{ "RunCommand", "git status" }`, so the argument needs no escaping.
+    pub fn from_parts(name: &str, arg: Option<&str>) -> Option<Self> {
+        let Some(arg) = arg else {
+            return Self::from_str(name);
+        };
+        match name {
+            "ChangeMode" => TerminalMode::from_str(arg).map(Self::ChangeMode),
+            "RunCommand" => Some(Self::RunCommand(arg.to_string())),
+            "SelectTab" => arg.trim().parse().ok().map(Self::SelectTab),
+            "AppendChar" | "InsertChar" => {
+                let mut chars = arg.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Some(Self::AppendChar(ch)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum InputEvent {
     Key { code: String, ctrl: bool, alt: bool, shift: bool },
     Text(String),
+    // Multi-key chord such as `g g`; only valid as a binding, never produced by input polling
+    Sequence(Vec<InputEvent>),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -287,15 +450,165 @@ pub struct KeyBinding {
 pub struct ModeDefinition {
     pub mode: TerminalMode,
     pub bindings: Vec<KeyBinding>,
+    pub appearance: ModeAppearance,
+}
+
+/// Per-mode overrides for the prompt line; `None` falls back to the global setting.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModeAppearance {
+    pub prompt: Option<String>,
+    pub prompt_color: Option<TerminalColor>,
+    pub text_color: Option<TerminalColor>,
+}
+
+/// Where the startup directory came from. Earlier variants take precedence.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CwdSource {
+    /// `--cwd` on the command line.
+    Cli,
+    /// `default_cwd` in the config.
+    Config,
+    /// The directory the process was started in.
+    #[default]
+    Process,
+}
+
+impl CwdSource {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Cli => "--cwd",
+            Self::Config => "default_cwd",
+            Self::Process => "process cwd",
+        }
+    }
+}
+
+/// How the output's scrollbar is shown (the `scrollbar` setting).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScrollbarMode {
+    /// Thin bar floating over the text, widening on hover (egui's default).
+    #[default]
+    Overlay,
+    /// Bar in its own column, shown when the output overflows.
+    Solid,
+    /// Like `Solid`, but shown even when everything fits.
+    Always,
+    Hidden,
+}
+
+impl ScrollbarMode {
+    pub const ALL: [Self; 4] = [Self::Overlay, Self::Solid, Self::Always, Self::Hidden];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Overlay => "overlay",
+            Self::Solid => "solid",
+            Self::Always => "always",
+            Self::Hidden => "hidden",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(s))
+    }
+}
+
+/// Space between the window edges and the terminal content, in points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Padding {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Padding {
+    pub fn uniform(value: f32) -> Self {
+        Self { top: value, right: value, bottom: value, left: value }
+    }
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Self::uniform(DEFAULT_PADDING)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum ShellEvent {
     // Every mutation of the Screen state generates a ScreenOperation.
     Operation(ScreenOperation),
-    // Background notifications or control signals.
-    #[allow(dead_code)]
-    Notification(String),
+    // A message about the terminal itself, for the pane to show as a toast
+    Notification(Notice),
+    // Ask for the user's attention, e.g. when a desktop notification couldn't be shown
+    Bell,
+}
+
+/// A message about the terminal itself (a config reload, a finished job) rather than
+/// program output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notice {
+    pub text: String,
+    pub color: TerminalColor,
+}
+
+/// How notices are shown (the `notifications` setting).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NotificationStyle {
+    /// Over the top-right corner of the pane, fading after a few seconds.
+    #[default]
+    Toast,
+    /// As a line in the output.
+    Inline,
+}
+
+impl NotificationStyle {
+    pub const ALL: [Self; 2] = [Self::Toast, Self::Inline];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Toast => "toast",
+            Self::Inline => "inline",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.name().eq_ignore_ascii_case(s))
+    }
+}
+
+/// What pasting several lines at once does (the `multiline_paste` setting). Neither runs
+/// anything without an Enter, so a pasted command can't smuggle in its own line break.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PasteMode {
+    /// Lines are put on the input line one at a time; each Enter submits one and brings up the next.
+    #[default]
+    Step,
+    /// All the lines are listed first, and one `y` runs them in order.
+    Confirm,
+}
+
+impl PasteMode {
+    pub const ALL: [Self; 2] = [Self::Step, Self::Confirm];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Step => "step",
+            Self::Confirm => "confirm",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(s))
+    }
+}
+
+/// A child process the shell started, with what it was and when, so its completion can be
+/// reported.
+pub struct RunningCommand {
+    pub handle: Box<dyn crate::backend::ProcessHandle>,
+    pub command_line: String,
+    pub started: std::time::Instant,
 }
 
 
@@ -306,17 +619,81 @@ pub struct Shortcut {
     pub cmd: String,
 }
 
+/// Commands the config asks to run once at launch (`on_startup`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StartupCommands {
+    List(Vec<String>),
+    // An rc file with one command per line
+    File(std::path::PathBuf),
+}
+
+impl StartupCommands {
+    /// The commands in order. Blank lines and `#` comments in an rc file are skipped.
+    pub fn commands(&self) -> std::io::Result<Vec<String>> {
+        match self {
+            StartupCommands::List(commands) => Ok(commands.clone()),
+            StartupCommands::File(path) => Ok(std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect()),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct ConfigUpdate {
+    pub theme: Option<String>,
+    pub cursor_color: Option<TerminalColor>,
+    pub background_color: Option<TerminalColor>,
+    pub background_image: Option<String>,
+    pub background_image_opacity: Option<f32>,
+    pub padding: Option<Padding>,
+    // `Some(None)` explicitly turns the highlight off
+    pub current_line_highlight: Option<Option<TerminalColor>>,
+    pub clickable_links: Option<bool>,
+    pub copy_on_select: Option<bool>,
+    pub word_chars: Option<String>,
+    pub max_input_length: Option<usize>,
+    // `Some(None)` explicitly turns the limit off
+    pub max_command_lines: Option<Option<usize>>,
+    pub confirm_destructive: Option<bool>,
+    pub notify_after_secs: Option<f32>,
+    pub notifications: Option<NotificationStyle>,
+    pub config_reload_quiet: Option<bool>,
+    // Milliseconds
+    pub config_reload_debounce: Option<f32>,
+    pub multiline_paste: Option<PasteMode>,
     pub prompt: Option<String>,
     pub prompt_color: Option<TerminalColor>,
     pub text_color: Option<TerminalColor>,
     pub window_title: Option<String>,
+    pub window_title_format: Option<String>,
     pub shortcuts: Option<Vec<Shortcut>>,
     pub opacity: Option<f32>,
     pub font_size: Option<f32>,
+    pub min_font_size: Option<f32>,
+    pub high_contrast: Option<bool>,
+    pub no_color: Option<bool>,
+    pub clear_on_command: Option<bool>,
+    // Held-key repeat: delay in milliseconds, rate in repeats per second
+    pub key_repeat_delay: Option<f32>,
+    pub key_repeat_rate: Option<f32>,
     pub default_cwd: Option<String>,
+    // Only read at launch, so reloading the config doesn't run these again
+    pub on_startup: Option<StartupCommands>,
     pub directory_color: Option<TerminalColor>,
+    pub symlink_color: Option<TerminalColor>,
+    pub hidden_file_dim: Option<f32>,
+    pub ls_colors: Option<BTreeMap<String, TerminalColor>>,
+    pub scrollbar: Option<ScrollbarMode>,
+    pub scrollbar_width: Option<f32>,
+    pub scrollbar_color: Option<TerminalColor>,
+    pub stderr_color: Option<TerminalColor>,
+    pub error_color: Option<TerminalColor>,
+    // Named colors from `colors = { ... }`, usable wherever a color is expected
+    pub palette: Option<BTreeMap<String, TerminalColor>>,
     pub mode_definitions: Option<Vec<ModeDefinition>>,
 }
 
@@ -326,14 +703,521 @@ pub struct ShellState {
     pub text_color: TerminalColor,
     pub window_title_base: String,
     pub window_title_full: String,
+    pub window_title_format: String,
     pub title_updated: bool,
     pub mode: TerminalMode,
     pub shortcuts: Vec<Shortcut>,
     pub opacity: f32,
     pub font_size: f32,
+    // `font_size` as last set by the config, restored by `FontSizeReset`
+    pub configured_font_size: f32,
+    // Floor for the displayed font size, whatever `font_size` says
+    pub min_font_size: f32,
+    // White-on-black bold text and an opaque cursor, overriding the configured colors
+    pub high_contrast: bool,
+    // Colors in the output; every line is drawn in the text color when off
+    pub color_enabled: bool,
+    // Set by `NO_COLOR` or `--no-color`, which the config can't turn colors back on over
+    pub color_forced_off: bool,
+    // Start every command on a cleared screen
+    pub clear_on_command: bool,
+    pub key_repeat_delay: Duration,
+    // Repeats per second once a held key starts repeating
+    pub key_repeat_rate: f32,
     pub current_dir: String,
+    // Which source picked the startup directory; `--cwd` also stops `config load` from moving it
+    pub cwd_source: CwdSource,
     pub directory_color: TerminalColor,
+    pub symlink_color: TerminalColor,
+    // How bright `ls` draws dotfiles, as a factor on their usual color
+    pub hidden_file_dim: f32,
+    // `ls` colors by lowercase extension or category name (see `utils::LS_CATEGORIES`)
+    pub ls_colors: BTreeMap<String, TerminalColor>,
+    pub scrollbar: ScrollbarMode,
+    // Bar width and handle color; `None` keeps egui's look
+    pub scrollbar_width: Option<f32>,
+    pub scrollbar_color: Option<TerminalColor>,
+    // Child process stderr lines
+    pub stderr_color: TerminalColor,
+    // Error messages from builtins and the shell itself
+    pub error_color: TerminalColor,
+    pub cursor_color: TerminalColor,
+    pub background_color: TerminalColor,
+    pub background_image: Option<String>,
+    pub background_image_opacity: f32,
+    pub padding: Padding,
+    // Faint tint behind the cursor row; `None` disables it
+    pub current_line_highlight: Option<TerminalColor>,
+    // Underline http(s) URLs in the output and open them on click
+    pub clickable_links: bool,
+    // Copy a word/line selection to the clipboard as soon as it is made
+    pub copy_on_select: bool,
+    // Characters besides alphanumerics that Ctrl+W and double-click treat as part of a word
+    pub word_chars: String,
+    // Submitted lines are cut to this many chars
+    pub max_input_length: usize,
+    // A command that prints more lines than this is killed
+    pub max_command_lines: Option<usize>,
+    // Ask for y/n before `clear` and recursive `rm`
+    pub confirm_destructive: bool,
+    // Commands that run at least this long notify on completion while the window is unfocused
+    pub notify_after_secs: Option<f32>,
+    // Kept up to date by the UI, so the shell thread knows whether anyone is watching
+    pub window_focused: bool,
+    pub notifications: NotificationStyle,
+    // Messages from reloads on save are always toasts, whatever `notifications` says
+    pub config_reload_quiet: bool,
+    // Quiet time after the last change to the config file before it is reloaded
+    pub config_reload_debounce: Duration,
+    pub multiline_paste: PasteMode,
+    // Pasted lines still to come up on the input line, one per Enter
+    pub pasted_lines: VecDeque<String>,
+    // Command lines waiting for the y/n answer the next submitted line gives
+    pub pending_confirmation: Option<Vec<String>>,
+    // Lines submitted so far of a command with an unclosed quote or trailing backslash
+    pub continuation: Option<String>,
     pub screen: Screen,
     pub input_buffer: String,
+    // Cursor position within `input_buffer`, in chars (not bytes)
+    pub input_cursor: usize,
     pub mode_definitions: Vec<ModeDefinition>,
+    pub macro_names: Vec<String>,
+    // Recorded by the Lua engine as macros run
+    pub macro_metrics: std::sync::Arc<std::sync::Mutex<crate::lua_bridge::MacroMetrics>>,
+    // The pane's renderer counters as of its last batch of output
+    pub render_metrics: RenderMetrics,
+    pub last_command: String,
+    // Submitted command lines, oldest first
+    pub history: Vec<String>,
+    pub history_search: Option<HistorySearch>,
+    // Variables changed by `export`/`unset` on top of the inherited environment; `None` = unset
+    pub env_overrides: BTreeMap<String, Option<String>>,
+    // Named colors defined by the config, so themes loaded later can refer to them
+    pub palette: BTreeMap<String, TerminalColor>,
+    // Child processes started by the shell that haven't been seen to exit yet
+    pub running: Vec<RunningCommand>,
+    // Set by the `sleep` builtin; commands submitted before then wait for it
+    pub sleep_until: Option<std::time::Instant>,
+    // Bumped by every Interrupt, so background loops like `watch` can tell they were stopped
+    pub interrupts: u64,
+    // Exit code of the last builtin that reports one (`test`, `true`, `false`)
+    pub last_status: i32,
+}
+
+/// State of an incremental reverse history search (Ctrl+R).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistorySearch {
+    pub query: String,
+    // Number of newer matches stepped past with repeated Ctrl+R
+    pub skip: usize,
+}
+
+impl HistorySearch {
+    /// Index into `history` of the current match, searching newest to oldest.
+    pub fn find(&self, history: &[String]) -> Option<usize> {
+        history
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, entry)| entry.contains(&self.query))
+            .nth(self.skip)
+            .map(|(i, _)| i)
+    }
+}
+
+impl ConfigUpdate {
+    /// Keeps only the palette fields, which is all a theme file may change.
+    pub fn colors_only(self) -> Self {
+        Self {
+            prompt_color: self.prompt_color,
+            text_color: self.text_color,
+            directory_color: self.directory_color,
+            symlink_color: self.symlink_color,
+            ls_colors: self.ls_colors,
+            scrollbar_color: self.scrollbar_color,
+            stderr_color: self.stderr_color,
+            error_color: self.error_color,
+            cursor_color: self.cursor_color,
+            background_color: self.background_color,
+            current_line_highlight: self.current_line_highlight,
+            palette: self.palette,
+            ..Self::default()
+        }
+    }
+}
+
+impl ShellState {
+    /// A plain Insert-mode state with no bindings, for tests that drive the shell directly.
+    #[cfg(test)]
+    pub fn for_test() -> Self {
+        Self {
+            prompt: "> ".to_string(),
+            prompt_color: TerminalColor::GREEN,
+            text_color: TerminalColor::LIGHT_GRAY,
+            window_title_base: "Test".to_string(),
+            window_title_full: "Test".to_string(),
+            window_title_format: DEFAULT_WINDOW_TITLE_FORMAT.to_string(),
+            title_updated: false,
+            mode: TerminalMode::Insert,
+            shortcuts: Vec::new(),
+            opacity: 1.0,
+            font_size: 14.0,
+            configured_font_size: 14.0,
+            min_font_size: MIN_FONT_SIZE,
+            high_contrast: false,
+            color_enabled: true,
+            color_forced_off: false,
+            clear_on_command: false,
+            key_repeat_delay: DEFAULT_KEY_REPEAT_DELAY,
+            key_repeat_rate: DEFAULT_KEY_REPEAT_RATE,
+            current_dir: ".".to_string(),
+            cwd_source: CwdSource::default(),
+            directory_color: TerminalColor::BLUE,
+            symlink_color: TerminalColor::CYAN,
+            hidden_file_dim: 1.0,
+            ls_colors: BTreeMap::new(),
+            scrollbar: Default::default(),
+            scrollbar_width: None,
+            scrollbar_color: None,
+            stderr_color: TerminalColor::RED,
+            error_color: TerminalColor::RED,
+            cursor_color: TerminalColor::WHITE,
+            background_color: TerminalColor::BLACK,
+            background_image: None,
+            background_image_opacity: 1.0,
+            padding: Padding::default(),
+            current_line_highlight: None,
+            clickable_links: true,
+            copy_on_select: DEFAULT_COPY_ON_SELECT,
+            word_chars: DEFAULT_WORD_CHARS.to_string(),
+            max_input_length: DEFAULT_MAX_INPUT_LENGTH,
+            max_command_lines: None,
+            confirm_destructive: false,
+            notify_after_secs: None,
+            window_focused: true,
+            notifications: NotificationStyle::default(),
+            config_reload_quiet: false,
+            config_reload_debounce: DEFAULT_CONFIG_RELOAD_DEBOUNCE,
+            multiline_paste: PasteMode::default(),
+            pasted_lines: VecDeque::new(),
+            pending_confirmation: None,
+            continuation: None,
+            screen: Screen::new(),
+            input_buffer: String::new(),
+            input_cursor: 0,
+            mode_definitions: Vec::new(),
+            macro_names: Vec::new(),
+            macro_metrics: Default::default(),
+            render_metrics: Default::default(),
+            last_command: String::new(),
+            history: Vec::new(),
+            history_search: None,
+            env_overrides: BTreeMap::new(),
+            palette: BTreeMap::new(),
+            running: Vec::new(),
+            sleep_until: None,
+            interrupts: 0,
+            last_status: 0,
+        }
+    }
+
+    /// Applies every field set in `update`. `default_cwd` and `theme` need I/O and are
+    /// resolved by the shell before/after calling this.
+    pub fn apply_config(&mut self, update: ConfigUpdate) {
+        if let Some(p) = update.prompt {
+            self.prompt = p;
+        }
+        if let Some(pc) = update.prompt_color {
+            self.prompt_color = pc;
+        }
+        if let Some(tc) = update.text_color {
+            self.text_color = tc;
+        }
+        if let Some(wt) = update.window_title {
+            self.window_title_base = wt;
+        }
+        if let Some(wtf) = update.window_title_format {
+            self.window_title_format = wtf;
+        }
+        if let Some(sh) = update.shortcuts {
+            self.shortcuts = sh;
+        }
+        if let Some(op) = update.opacity {
+            self.opacity = op;
+        }
+        if let Some(fs) = update.font_size {
+            self.font_size = fs;
+            self.configured_font_size = fs;
+        }
+        if let Some(min) = update.min_font_size {
+            self.min_font_size = min;
+        }
+        if let Some(hc) = update.high_contrast {
+            self.high_contrast = hc;
+        }
+        if let Some(no_color) = update.no_color {
+            self.color_enabled = !no_color && !self.color_forced_off;
+        }
+        if let Some(clear) = update.clear_on_command {
+            self.clear_on_command = clear;
+        }
+        if let Some(delay) = update.key_repeat_delay {
+            self.key_repeat_delay = Duration::from_secs_f32(delay / 1000.0);
+        }
+        if let Some(rate) = update.key_repeat_rate {
+            self.key_repeat_rate = rate;
+        }
+        if let Some(dc) = update.directory_color {
+            self.directory_color = dc;
+        }
+        if let Some(sc) = update.symlink_color {
+            self.symlink_color = sc;
+        }
+        if let Some(dim) = update.hidden_file_dim {
+            self.hidden_file_dim = dim;
+        }
+        if let Some(colors) = update.ls_colors {
+            self.ls_colors = colors;
+        }
+        if let Some(mode) = update.scrollbar {
+            self.scrollbar = mode;
+        }
+        if let Some(width) = update.scrollbar_width {
+            self.scrollbar_width = Some(width);
+        }
+        if let Some(color) = update.scrollbar_color {
+            self.scrollbar_color = Some(color);
+        }
+        if let Some(ec) = update.stderr_color {
+            self.stderr_color = ec;
+        }
+        if let Some(ec) = update.error_color {
+            self.error_color = ec;
+        }
+        if let Some(palette) = update.palette {
+            self.palette = palette;
+        }
+        if let Some(cc) = update.cursor_color {
+            self.cursor_color = cc;
+        }
+        if let Some(bg) = update.background_color {
+            self.background_color = bg;
+        }
+        if let Some(img) = update.background_image {
+            // An empty path clears the image
+            self.background_image = Some(img).filter(|p| !p.is_empty());
+        }
+        if let Some(io) = update.background_image_opacity {
+            self.background_image_opacity = io;
+        }
+        if let Some(pad) = update.padding {
+            self.padding = pad;
+        }
+        if let Some(hl) = update.current_line_highlight {
+            self.current_line_highlight = hl;
+        }
+        if let Some(links) = update.clickable_links {
+            self.clickable_links = links;
+        }
+        if let Some(copy) = update.copy_on_select {
+            self.copy_on_select = copy;
+        }
+        if let Some(chars) = update.word_chars {
+            self.word_chars = chars;
+        }
+        if let Some(len) = update.max_input_length {
+            self.max_input_length = len;
+        }
+        if let Some(lines) = update.max_command_lines {
+            self.max_command_lines = lines;
+        }
+        if let Some(confirm) = update.confirm_destructive {
+            self.confirm_destructive = confirm;
+        }
+        if let Some(style) = update.notifications {
+            self.notifications = style;
+        }
+        if let Some(quiet) = update.config_reload_quiet {
+            self.config_reload_quiet = quiet;
+        }
+        if let Some(debounce) = update.config_reload_debounce {
+            self.config_reload_debounce = Duration::from_secs_f32(debounce / 1000.0);
+        }
+        if let Some(mode) = update.multiline_paste {
+            self.multiline_paste = mode;
+        }
+        if let Some(secs) = update.notify_after_secs {
+            // 0 turns it back off
+            self.notify_after_secs = Some(secs).filter(|&secs| secs > 0.0);
+        }
+        if let Some(md) = update.mode_definitions {
+            self.mode_definitions = md;
+        }
+        self.refresh_window_title();
+    }
+
+    /// The environment spawned commands see: the inherited one with `env_overrides` applied.
+    pub fn environment(&self) -> BTreeMap<String, String> {
+        let mut vars: BTreeMap<String, String> = std::env::vars_os()
+            .map(|(k, v)| (k.to_string_lossy().to_string(), v.to_string_lossy().to_string()))
+            .collect();
+        for (name, value) in &self.env_overrides {
+            match value {
+                Some(value) => vars.insert(name.clone(), value.clone()),
+                None => vars.remove(name),
+            };
+        }
+        vars
+    }
+
+    /// Reports `text` the way the `notifications` setting asks: as one toast for the pane,
+    /// or as output lines.
+    pub fn notify(&mut self, text: &str, color: TerminalColor, output_tx: &crossbeam_channel::Sender<ShellEvent>) {
+        match self.notifications {
+            NotificationStyle::Toast => {
+                let _ = output_tx.send(ShellEvent::Notification(Notice { text: text.to_string(), color }));
+            }
+            NotificationStyle::Inline => {
+                for line in text.lines() {
+                    let op = self.screen.push_line(Line::from_string(line, color));
+                    let _ = output_tx.send(ShellEvent::Operation(op));
+                }
+            }
+        }
+    }
+
+    /// Reports a config or theme reload that followed a save. With `config_reload_quiet` it is
+    /// a toast even when notices are inline, so saving the config over and over leaves no lines behind.
+    pub fn notify_reload(&mut self, text: &str, color: TerminalColor, output_tx: &crossbeam_channel::Sender<ShellEvent>) {
+        if self.config_reload_quiet {
+            let _ = output_tx.send(ShellEvent::Notification(Notice { text: text.to_string(), color }));
+        } else {
+            self.notify(text, color, output_tx);
+        }
+    }
+
+    /// Turns colors off for good, as `NO_COLOR` and `--no-color` ask; `no_color = false` in the
+    /// config doesn't bring them back.
+    pub fn force_no_color(&mut self) {
+        self.color_forced_off = true;
+        self.color_enabled = false;
+    }
+
+    /// Puts the prompt, colors, font size, opacity and background back to the built-in
+    /// defaults (the ones a new pane starts with before its config loads), for the `reset`
+    /// builtin. Behavior settings and the accessibility ones (`high_contrast`, `no_color`) stay.
+    pub fn reset_appearance(&mut self) {
+        self.prompt = "> ".to_string();
+        self.prompt_color = TerminalColor::GREEN;
+        self.text_color = TerminalColor::LIGHT_GRAY;
+        self.opacity = 1.0;
+        self.font_size = 14.0;
+        self.configured_font_size = 14.0;
+        self.min_font_size = MIN_FONT_SIZE;
+        self.directory_color = TerminalColor::BLUE;
+        self.symlink_color = TerminalColor::CYAN;
+        self.hidden_file_dim = 1.0;
+        self.ls_colors.clear();
+        self.scrollbar_width = None;
+        self.scrollbar_color = None;
+        self.stderr_color = TerminalColor::RED;
+        self.error_color = TerminalColor::RED;
+        self.cursor_color = TerminalColor::WHITE;
+        self.background_color = TerminalColor::BLACK;
+        self.background_image = None;
+        self.background_image_opacity = 1.0;
+        self.padding = Padding::default();
+        self.current_line_highlight = None;
+        self.palette.clear();
+        for definition in &mut self.mode_definitions {
+            definition.appearance = ModeAppearance::default();
+        }
+    }
+
+    /// Kills every child process still running. Used on `exit` and when the window closes.
+    pub fn kill_running(&mut self) {
+        for mut command in self.running.drain(..) {
+            if command.handle.is_running() {
+                let _ = command.handle.kill();
+            }
+        }
+    }
+
+    /// Input cursor clamped to the current buffer length, in chars.
+    pub fn clamped_input_cursor(&self) -> usize {
+        self.input_cursor.min(self.input_buffer.chars().count())
+    }
+
+    /// Where the prompt cursor belongs: the row just after the output, at the input cursor's
+    /// char offset into the input line.
+    pub fn prompt_cursor(&self) -> Cursor {
+        Cursor { row: self.screen.lines.len(), col: self.clamped_input_cursor() }
+    }
+
+    /// `screen.cursor` moved by `d_row` rows and `d_col` columns, kept on the screen: rows run
+    /// from the first output line to the prompt row, columns stop at the end of the row's text.
+    pub fn moved_cursor(&self, d_row: i32, d_col: i32) -> Cursor {
+        let cursor = self.screen.cursor;
+        let last_row = self.screen.lines.len();
+        let row = (cursor.row as i64 + d_row as i64).clamp(0, last_row as i64) as usize;
+        let width = match self.screen.lines.get(row) {
+            Some(line) => line.cells.len().saturating_sub(1),
+            // The prompt row, where the cursor may also sit just past the typed text
+            None => self.input_buffer.chars().count(),
+        };
+        let col = (cursor.col as i64 + d_col as i64).clamp(0, width as i64) as usize;
+        Cursor { row, col }
+    }
+
+    /// Moves `screen.cursor` to [`Self::prompt_cursor`], returning the `SetCursor` op if it moved.
+    pub fn sync_prompt_cursor(&mut self) -> Option<ScreenOperation> {
+        let cursor = self.prompt_cursor();
+        (self.screen.cursor != cursor).then(|| self.screen.set_cursor(cursor))
+    }
+
+    fn active_appearance(&self) -> Option<&ModeAppearance> {
+        self.mode_definitions
+            .iter()
+            .find(|d| d.mode == self.mode)
+            .map(|d| &d.appearance)
+    }
+
+    /// Prompt string for the active mode, falling back to the global prompt.
+    pub fn active_prompt(&self) -> &str {
+        if self.continuation.is_some() {
+            return CONTINUATION_PROMPT;
+        }
+        self.active_appearance()
+            .and_then(|a| a.prompt.as_deref())
+            .unwrap_or(&self.prompt)
+    }
+
+    pub fn active_prompt_color(&self) -> TerminalColor {
+        self.active_appearance()
+            .and_then(|a| a.prompt_color)
+            .unwrap_or(self.prompt_color)
+    }
+
+    /// The font size text is actually drawn at: `font_size`, but never below `min_font_size`.
+    pub fn display_font_size(&self) -> f32 {
+        self.font_size.max(self.min_font_size)
+    }
+
+    pub fn active_text_color(&self) -> TerminalColor {
+        self.active_appearance()
+            .and_then(|a| a.text_color)
+            .unwrap_or(self.text_color)
+    }
+
+    /// Rebuilds `window_title_full` from `window_title_format` and flags it for the UI thread.
+    pub fn refresh_window_title(&mut self) {
+        self.window_title_full = crate::utils::format_window_title(
+            &self.window_title_format,
+            self.mode.name(),
+            &self.window_title_base,
+            &self.current_dir,
+            &self.last_command,
+        );
+        self.title_updated = true;
+    }
 }
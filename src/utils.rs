@@ -1,6 +1,8 @@
 use crate::types::TerminalColor;
+use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_default_config_path() -> Option<PathBuf> {
     // Try environment variables first for explicit control
@@ -21,17 +23,161 @@ pub fn get_default_config_path() -> Option<PathBuf> {
     })
 }
 
+/// Directory searched by `theme load <name>`, next to the default config.lua.
+pub fn get_themes_dir() -> Option<PathBuf> {
+    get_default_config_path().and_then(|p| p.parent().map(|dir| dir.join("themes")))
+}
+
+/// Resolves a theme name to a file: names with a path separator or `.lua` suffix are used
+/// as-is, anything else is looked up as `<themes dir>/<name>.lua`.
+pub fn resolve_theme_path(name: &str) -> Option<PathBuf> {
+    if name.ends_with(".lua") || name.contains('/') || name.contains('\\') {
+        Some(PathBuf::from(name))
+    } else {
+        get_themes_dir().map(|dir| dir.join(format!("{}.lua", name)))
+    }
+}
+
+/// Command-line options for the terminal itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CliArgs {
+    /// `--cwd <dir>`: the directory to start in, ahead of the config's `default_cwd`.
+    pub cwd: Option<String>,
+    /// `--batch`: run the commands piped to stdin without a window, then exit.
+    pub batch: bool,
+    /// `--exec <command>`: run one command without a window, then exit.
+    pub exec: Option<String>,
+    /// `--no-color`: draw all output in the text color, as `NO_COLOR` does.
+    pub no_color: bool,
+}
+
+impl CliArgs {
+    /// Parses the arguments after the program name. Accepts `--cwd <dir>`, `--cwd=<dir>`,
+    /// `--batch`, `--exec <command>`, `--exec=<command>` and `--no-color`.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--cwd" {
+                cli.cwd = Some(args.next().ok_or("--cwd needs a directory")?);
+            } else if let Some(dir) = arg.strip_prefix("--cwd=") {
+                cli.cwd = Some(dir.to_string());
+            } else if arg == "--no-color" {
+                cli.no_color = true;
+            } else if arg == "--batch" {
+                cli.batch = true;
+            } else if arg == "--exec" {
+                cli.exec = Some(args.next().ok_or("--exec needs a command")?);
+            } else if let Some(command) = arg.strip_prefix("--exec=") {
+                cli.exec = Some(command.to_string());
+            } else {
+                return Err(format!("unknown argument '{}'", arg));
+            }
+        }
+        if cli.batch && cli.exec.is_some() {
+            return Err("--batch and --exec can't be used together".to_string());
+        }
+        Ok(cli)
+    }
+
+    /// Whether colors are off for this run: `--no-color`, or `NO_COLOR` set to anything
+    /// non-empty (see no-color.org).
+    pub fn colors_disabled(&self) -> bool {
+        self.no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+    }
+}
+
+/// Quotes `s` for a POSIX shell, leaving plain words untouched.
+pub fn shell_quote(s: &str) -> String {
+    let plain = !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// Expands a leading `~/` (or a lone `~`) to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = if path == "~" { Some("") } else { path.strip_prefix("~/") };
+    match (rest, dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Resolves `path` against `base`, a pane's working directory. Absolute paths are kept.
+pub fn resolve(base: &str, path: impl AsRef<Path>) -> PathBuf {
+    Path::new(base).join(path)
+}
+
+/// Replaces `$NAME` and `${NAME}` with the value `lookup` gives the variable. Unknown
+/// variables, and a `$` that doesn't start a name, are left as written.
+pub fn expand_vars(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let (name, len) = match after.strip_prefix('{').and_then(|inner| inner.split_once('}')) {
+            Some((name, _)) => (name, name.len() + 2),
+            None => {
+                let len = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..len], len)
+            }
+        };
+        match lookup(name).filter(|_| !name.is_empty()) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[pos..pos + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Why a command line can't be run as it stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenizeError {
+    /// A quote is still open or the line ends in a backslash, so more input is expected.
+    Unterminated,
+}
+
+/// Splits a command line into words. An unterminated quote or trailing backslash is
+/// forgiven and the partial word kept; see [`tokenize_command_checked`] to detect them.
+/// A `#` that starts a word comments out the rest of the line, as in sh; one inside quotes
+/// or a word (`#tag`, `a#b`) is kept.
 pub fn tokenize_command(input: &str) -> Vec<String> {
+    tokenize(input).0
+}
+
+/// Like [`tokenize_command`], but fails with `Unterminated` when the line needs more input.
+pub fn tokenize_command_checked(input: &str) -> Result<Vec<String>, TokenizeError> {
+    match tokenize(input) {
+        (tokens, false) => Ok(tokens),
+        (_, true) => Err(TokenizeError::Unterminated),
+    }
+}
+
+// The words of `input`, and whether it ended inside a quote or escape
+fn tokenize(input: &str) -> (Vec<String>, bool) {
     let mut tokens = Vec::new();
     let mut current_token = String::new();
     let mut in_single_quote = false;
     let mut in_double_quote = false;
     let mut escape = false;
     let mut token_started = false;
+    let mut in_comment = false;
 
     for c in input.chars() {
-        if escape {
-            current_token.push(c);
+        if in_comment {
+            // A comment runs to the end of its line; continuation lines carry on after it
+            in_comment = c != '\n';
+        } else if escape {
+            // Backslash-newline joins continuation lines
+            if c != '\n' {
+                current_token.push(c);
+            }
             escape = false;
             token_started = true;
         } else if in_single_quote {
@@ -64,6 +210,7 @@ pub fn tokenize_command(input: &str) -> Vec<String> {
                     escape = true;
                     token_started = true;
                 }
+                '#' if !token_started => in_comment = true,
                 c if c.is_whitespace() => {
                     if token_started {
                         tokens.push(current_token);
@@ -83,7 +230,241 @@ pub fn tokenize_command(input: &str) -> Vec<String> {
         tokens.push(current_token);
     }
 
-    tokens
+    (tokens, in_single_quote || in_double_quote || escape)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+/// Renders `[#####     ]  50%` for `done` out of `total` steps.
+pub fn format_progress_bar(done: usize, total: usize, width: usize) -> String {
+    let done = done.min(total);
+    let filled = (done * width).checked_div(total).unwrap_or(width);
+    let percent = (done * 100).checked_div(total).unwrap_or(100);
+    format!("[{}{}] {:>3}%", "#".repeat(filled), " ".repeat(width - filled), percent)
+}
+
+/// Most numbers one `seq` may print, so a huge range can't hang the pane.
+pub const SEQ_MAX_VALUES: u64 = 100_000;
+
+/// Why `seq` printed nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeqError {
+    /// An argument isn't a number, there are too few or too many, or the step is zero.
+    Usage,
+    /// The range holds this many numbers, more than `SEQ_MAX_VALUES`.
+    TooMany(u64),
+}
+
+/// The numbers `seq` prints for `[first [step]] last`, formatted with as many decimals as the
+/// most precise argument.
+pub fn seq_values(args: &[String]) -> Result<Vec<String>, SeqError> {
+    let nums: Vec<f64> = args.iter().map(|a| a.parse::<f64>().ok().filter(|n| n.is_finite())).collect::<Option<_>>().ok_or(SeqError::Usage)?;
+    let (first, step, last) = match nums[..] {
+        [last] => (1.0, 1.0, last),
+        [first, last] => (first, 1.0, last),
+        [first, step, last] => (first, step, last),
+        _ => return Err(SeqError::Usage),
+    };
+    if step == 0.0 {
+        return Err(SeqError::Usage);
+    }
+
+    // Counted in steps, so the tolerance for fractional steps that land just short of `last`
+    // scales with the step
+    let steps = ((last - first) / step + 1e-9).floor();
+    if steps < 0.0 {
+        return Ok(Vec::new());
+    }
+    let count = steps + 1.0;
+    if count > SEQ_MAX_VALUES as f64 {
+        return Err(SeqError::TooMany(count.min(u64::MAX as f64) as u64));
+    }
+
+    let decimals = args.iter().map(|a| a.split_once('.').map_or(0, |(_, frac)| frac.len())).max().unwrap_or(0);
+    // Step by index rather than accumulating, so fractional steps don't drift
+    Ok((0..count as u64).map(|i| format!("{:.*}", decimals, first + step * i as f64)).collect())
+}
+
+/// Byte offset of the `char_idx`-th character, clamped to the end of `s`.
+pub fn char_to_byte(s: &str, char_idx: usize) -> usize {
+    s.char_indices().nth(char_idx).map_or(s.len(), |(i, _)| i)
+}
+
+/// Cuts `s` down to its first `max` chars. Returns whether anything was cut.
+pub fn truncate_chars(s: &mut String, max: usize) -> bool {
+    // Bytes bound chars from above, so short strings skip the scan
+    if s.len() <= max {
+        return false;
+    }
+    let end = char_to_byte(s, max);
+    let cut = end < s.len();
+    s.truncate(end);
+    cut
+}
+
+/// Deletes the char before `cursor` (a char index), as Backspace does. Returns the new cursor position.
+pub fn delete_char_before(buf: &mut String, cursor: usize) -> usize {
+    let cursor = cursor.min(buf.chars().count());
+    if cursor == 0 {
+        return 0;
+    }
+    buf.remove(char_to_byte(buf, cursor - 1));
+    cursor - 1
+}
+
+/// Deletes the char at `cursor` (a char index), as forward Delete does; the cursor stays put.
+pub fn delete_char_at(buf: &mut String, cursor: usize) -> usize {
+    let cursor = cursor.min(buf.chars().count());
+    if cursor < buf.chars().count() {
+        buf.remove(char_to_byte(buf, cursor));
+    }
+    cursor
+}
+
+/// Whether `c` is part of a word for word deletion and double-click selection: any
+/// alphanumeric, plus the extra characters in `word_chars` (the `word_chars` setting).
+pub fn is_word_char(c: char, word_chars: &str) -> bool {
+    c.is_alphanumeric() || word_chars.contains(c)
+}
+
+/// Deletes the word before `cursor` (a char index) readline-style: the separators before the
+/// cursor first, then the word itself. Returns the new cursor position.
+pub fn delete_word_before(buf: &mut String, cursor: usize, word_chars: &str) -> usize {
+    let chars: Vec<char> = buf.chars().collect();
+    let end = cursor.min(chars.len());
+    let mut start = end;
+    while start > 0 && !is_word_char(chars[start - 1], word_chars) {
+        start -= 1;
+    }
+    while start > 0 && is_word_char(chars[start - 1], word_chars) {
+        start -= 1;
+    }
+    let (start_byte, end_byte) = (char_to_byte(buf, start), char_to_byte(buf, end));
+    buf.replace_range(start_byte..end_byte, "");
+    start
+}
+
+/// Expands `{mode}`, `{title}`, `{cwd}` and `{cmd}` in a window title template.
+pub fn format_window_title(format: &str, mode: &str, title: &str, cwd: &str, cmd: &str) -> String {
+    format
+        .replace("{mode}", mode)
+        .replace("{title}", title)
+        .replace("{cwd}", cwd)
+        .replace("{cmd}", cmd)
+}
+
+/// Scores `candidate` for the command palette query `query`, higher being a better match.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    // `None` unless the query's characters all appear in order, ignoring case. Runs of
+    // consecutive characters and matches at the start of a word score higher, skipped
+    // characters lower.
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last_match: Option<usize> = None;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        let offset = candidate[pos..].iter().position(|c| c.to_lowercase().eq(std::iter::once(q)))?;
+        let idx = pos + offset;
+        score += 1;
+        if last_match.is_some_and(|last| last + 1 == idx) {
+            score += 5;
+        }
+        if idx == 0 || !candidate[idx - 1].is_alphanumeric() {
+            score += 8;
+        }
+        score -= offset as i32;
+        last_match = Some(idx);
+        pos = idx + 1;
+    }
+    Some(score)
+}
+
+/// Matches `name` against a shell-style glob supporting `*` and `?`.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Formats a timestamp as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_system_time(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Format `date` prints without a format argument.
+pub const DEFAULT_DATE_FORMAT: &str = "%a %b %e %H:%M:%S %Z %Y";
+
+/// Formats `time` with a strftime-style `format`; `None` if it contains an invalid specifier.
+pub fn format_date<Tz: chrono::TimeZone>(time: &chrono::DateTime<Tz>, format: &str) -> Option<String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    use std::fmt::Write;
+    let mut out = String::new();
+    // An invalid specifier surfaces as a fmt::Error, which `to_string` would turn into a panic
+    write!(out, "{}", time.format(format)).ok()?;
+    Some(out)
+}
+
+// Converts days since 1970-01-01 into a (year, month, day) triple.
+// See Howard Hinnant's "chrono-Compatible Low-Level Date Algorithms".
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
 }
 
 pub fn parse_hex_color(hex: &str) -> Option<TerminalColor> {
@@ -96,3 +477,115 @@ pub fn parse_hex_color(hex: &str) -> Option<TerminalColor> {
     let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
     Some(TerminalColor::from_rgb(r, g, b))
 }
+
+/// The extensions each `ls_colors` category name covers.
+pub const LS_CATEGORIES: &[(&str, &[&str])] = &[
+    ("image", &["png", "jpg", "jpeg", "gif", "bmp", "svg", "webp", "ico", "tif", "tiff"]),
+    ("archive", &["zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "deb", "rpm"]),
+    ("audio", &["mp3", "wav", "flac", "ogg", "m4a", "opus"]),
+    ("video", &["mp4", "mkv", "webm", "avi", "mov"]),
+    ("document", &["pdf", "md", "txt", "doc", "docx", "odt", "rtf"]),
+    (
+        "code",
+        &["rs", "c", "h", "cpp", "hpp", "py", "js", "ts", "go", "java", "lua", "sh", "rb", "toml", "json", "yaml", "yml"],
+    ),
+];
+
+/// The color `ls_colors` gives a file named `name`. Longer extensions win (`tar.gz` before
+/// `gz`), and an extension listed by name wins over its category. Keys are lowercase.
+pub fn ls_color(name: &str, ls_colors: &BTreeMap<String, TerminalColor>) -> Option<TerminalColor> {
+    let name = name.to_lowercase();
+    // A leading dot marks a hidden file, not an extension
+    let stem_start = name.len() - name.trim_start_matches('.').len();
+    let extensions: Vec<&str> = name[stem_start..].match_indices('.').map(|(i, _)| &name[stem_start + i + 1..]).collect();
+    extensions.iter().find_map(|ext| ls_colors.get(*ext).copied()).or_else(|| {
+        extensions.iter().find_map(|ext| {
+            let (category, _) = LS_CATEGORIES.iter().find(|(_, exts)| exts.contains(ext))?;
+            ls_colors.get(*category).copied()
+        })
+    })
+}
+
+/// Reads the `*.ext=codes` entries of an `LS_COLORS` value into extension colors. Only the
+/// foreground color of each entry is used: 30-37, 90-97, `38;5;n` or `38;2;r;g;b`. Entries
+/// for file types (`di=`, `ln=`, ...) are skipped; those colors come from the config.
+pub fn parse_ls_colors(value: &str) -> BTreeMap<String, TerminalColor> {
+    let mut colors = BTreeMap::new();
+    for entry in value.split(':') {
+        let Some((pattern, codes)) = entry.split_once('=') else { continue };
+        let Some(ext) = pattern.strip_prefix("*.") else { continue };
+        if let Some(Some(color)) = sgr_foreground(codes) {
+            colors.insert(ext.to_lowercase(), color);
+        }
+    }
+    colors
+}
+
+/// The last foreground change made by a list of SGR codes such as `01;38;5;208`:
+/// `Some(None)` for a reset to the default color (`0`, `39` or no codes at all), `None`
+/// when the codes leave the foreground alone.
+pub fn sgr_foreground(codes: &str) -> Option<Option<TerminalColor>> {
+    let codes: Vec<u32> = codes.split(';').map(|c| c.parse().unwrap_or(0)).collect();
+    let mut color = None;
+    let mut i = 0;
+    while i < codes.len() {
+        match codes[i] {
+            0 | 39 => color = Some(None),
+            code @ 30..=37 => color = Some(Some(ansi_color((code - 30) as u8))),
+            code @ 90..=97 => color = Some(Some(ansi_color((code - 90 + 8) as u8))),
+            38 if codes.get(i + 1) == Some(&5) => {
+                if let Some(n) = codes.get(i + 2).and_then(|&n| u8::try_from(n).ok()) {
+                    color = Some(Some(ansi_color(n)));
+                }
+                i += 2;
+            }
+            38 if codes.get(i + 1) == Some(&2) => {
+                if let Some(rgb) = codes.get(i + 2..i + 5) {
+                    let channel = |v: u32| v.min(255) as u8;
+                    color = Some(Some(TerminalColor::from_rgb(channel(rgb[0]), channel(rgb[1]), channel(rgb[2]))));
+                }
+                i += 4;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    color
+}
+
+/// Entry `index` of the xterm 256-color palette.
+pub fn ansi_color(index: u8) -> TerminalColor {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    match index {
+        0..=15 => {
+            let (r, g, b) = BASE[index as usize];
+            TerminalColor::from_rgb(r, g, b)
+        }
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = index - 16;
+            TerminalColor::from_rgb(level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            TerminalColor::from_rgb(gray, gray, gray)
+        }
+    }
+}